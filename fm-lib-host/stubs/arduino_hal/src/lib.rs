@@ -0,0 +1,65 @@
+//! Stand-in for the parts of `arduino-hal` used by `fm-lib`. Like the one on
+//! the chip, there is only one EEPROM: every `Eeprom` on a thread shares the
+//! same memory, so tests can look at what was left behind after the code under
+//! test is done with it. Each test runs on its own thread, so each starts with
+//! an erased EEPROM.
+
+use std::cell::RefCell;
+
+use avr_device::atmega328p::EEPROM;
+
+const CAPACITY: u16 = 1024;
+
+thread_local! {
+    static MEMORY: RefCell<[u8; CAPACITY as usize]> =
+        const { RefCell::new([0xFF; CAPACITY as usize]) };
+}
+
+#[derive(Debug)]
+pub struct OutOfBoundsError;
+
+pub struct Eeprom(());
+
+impl Eeprom {
+    pub fn new(_eeprom: EEPROM) -> Self {
+        Self(())
+    }
+
+    pub fn capacity(&self) -> u16 {
+        CAPACITY
+    }
+
+    pub fn read_byte(&self, offset: u16) -> u8 {
+        MEMORY.with_borrow(|memory| memory[offset as usize])
+    }
+
+    pub fn write_byte(&mut self, offset: u16, data: u8) {
+        MEMORY.with_borrow_mut(|memory| memory[offset as usize] = data);
+    }
+
+    pub fn erase_byte(&mut self, offset: u16) {
+        self.write_byte(offset, 0xFF);
+    }
+
+    pub fn read(&self, offset: u16, buf: &mut [u8]) -> Result<(), OutOfBoundsError> {
+        let start = offset as usize;
+        MEMORY.with_borrow(|memory| {
+            let bytes = memory
+                .get(start..start + buf.len())
+                .ok_or(OutOfBoundsError)?;
+            buf.copy_from_slice(bytes);
+            Ok(())
+        })
+    }
+
+    pub fn write(&mut self, offset: u16, buf: &[u8]) -> Result<(), OutOfBoundsError> {
+        let start = offset as usize;
+        MEMORY.with_borrow_mut(|memory| {
+            let bytes = memory
+                .get_mut(start..start + buf.len())
+                .ok_or(OutOfBoundsError)?;
+            bytes.copy_from_slice(buf);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,7 @@
+//! Stand-in for the parts of `avr-device` used by `fm-lib`. The peripherals are
+//! just tokens, so anything can make one.
+#![no_std]
+
+pub mod atmega328p {
+    pub struct EEPROM;
+}
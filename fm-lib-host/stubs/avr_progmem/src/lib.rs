@@ -0,0 +1,33 @@
+//! Stand-in for the parts of `avr-progmem` used by the firmware. On the host
+//! there is no separate program memory, so the tables just live in regular
+//! statics.
+#![no_std]
+
+pub mod wrapper {
+    pub struct ProgMem<T>(pub T);
+
+    impl<T: Copy, const N: usize> ProgMem<[T; N]> {
+        pub fn load_at(&self, idx: usize) -> T {
+            self.0[idx]
+        }
+
+        pub fn len(&self) -> usize {
+            N
+        }
+
+        pub fn is_empty(&self) -> bool {
+            N == 0
+        }
+
+        pub fn load_sub_array<const M: usize>(&self, start_idx: usize) -> [T; M] {
+            core::array::from_fn(|i| self.0[start_idx + i])
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! progmem {
+    ($($vis:vis static progmem $name:ident : $ty:ty = $value:expr ;)*) => {
+        $($vis static $name: $crate::wrapper::ProgMem<$ty> = $crate::wrapper::ProgMem($value);)*
+    };
+}
@@ -0,0 +1,291 @@
+use arduino_hal::Eeprom;
+use avr_device::atmega328p::EEPROM;
+use fm_lib::eeprom::WearLevelledEepromWriter;
+
+/// Record sizes in use: the Drift's settings, and the Envelope's records before
+/// and after they grew
+const DRIFT_SIZE: usize = 8;
+const ENVELOPE_SIZE: usize = 24;
+const OLD_ENVELOPE_SIZE: usize = 1;
+
+const EMPTY: u16 = 0xFFFF;
+
+fn eeprom() -> Eeprom {
+    Eeprom::new(EEPROM)
+}
+
+/// Bytes per record, including the 2 byte version number
+fn record_size<const SIZE: usize>() -> u16 {
+    2 + SIZE as u16
+}
+
+/// How many records fit in the EEPROM
+fn records<const SIZE: usize>() -> u16 {
+    eeprom().capacity() / record_size::<SIZE>()
+}
+
+fn erase() {
+    let mut eeprom = eeprom();
+    for address in 0..eeprom.capacity() {
+        eeprom.erase_byte(address);
+    }
+}
+
+/// Writes a record the way `WearLevelledEepromWriter` lays them out
+fn write_record<const SIZE: usize>(index: u16, version: u16, data: [u8; SIZE]) {
+    let address = index * record_size::<SIZE>();
+    let mut eeprom = eeprom();
+    eeprom.write(address, &version.to_le_bytes()).unwrap();
+    eeprom.write(address + 2, &data).unwrap();
+}
+
+fn read_record<const SIZE: usize>(index: u16) -> [u8; SIZE] {
+    let mut data = [0; SIZE];
+    eeprom()
+        .read(index * record_size::<SIZE>() + 2, &mut data)
+        .unwrap();
+    data
+}
+
+/// The address and version of the newest record
+fn head<const SIZE: usize>() -> (u16, u16) {
+    WearLevelledEepromWriter::<SIZE>::binary_search_for_monotonic_ringbuffer_head(&eeprom())
+}
+
+fn empty_ring_has_no_head<const SIZE: usize>() {
+    erase();
+    assert_eq!(head::<SIZE>(), (0, EMPTY));
+}
+
+#[test]
+fn empty_ring_has_no_head_for_each_size() {
+    empty_ring_has_no_head::<DRIFT_SIZE>();
+    empty_ring_has_no_head::<ENVELOPE_SIZE>();
+    empty_ring_has_no_head::<OLD_ENVELOPE_SIZE>();
+}
+
+fn partly_filled_ring_ends_at_the_last_record<const SIZE: usize>() {
+    for last in 0..records::<SIZE>() - 1 {
+        erase();
+        for index in 0..=last {
+            write_record(index, index, [0; SIZE]);
+        }
+        assert_eq!(
+            head::<SIZE>(),
+            (last * record_size::<SIZE>(), last),
+            "records 0 to {last} of {SIZE} bytes"
+        );
+    }
+}
+
+#[test]
+fn partly_filled_ring_ends_at_the_last_record_for_each_size() {
+    partly_filled_ring_ends_at_the_last_record::<DRIFT_SIZE>();
+    partly_filled_ring_ends_at_the_last_record::<ENVELOPE_SIZE>();
+    partly_filled_ring_ends_at_the_last_record::<OLD_ENVELOPE_SIZE>();
+}
+
+fn full_ring_ends_at_the_last_record<const SIZE: usize>() {
+    erase();
+    let last = records::<SIZE>() - 1;
+    for index in 0..=last {
+        write_record(index, index, [0; SIZE]);
+    }
+    assert_eq!(head::<SIZE>(), (last * record_size::<SIZE>(), last));
+}
+
+#[test]
+fn full_ring_ends_at_the_last_record_for_each_size() {
+    full_ring_ends_at_the_last_record::<DRIFT_SIZE>();
+    full_ring_ends_at_the_last_record::<ENVELOPE_SIZE>();
+    full_ring_ends_at_the_last_record::<OLD_ENVELOPE_SIZE>();
+}
+
+/// After going round the ring `laps` times, the newest records are at the
+/// start and the oldest after them, wherever the ring wraps
+fn wrapped_ring_ends_at_the_newest_record<const SIZE: usize>(laps: u16) {
+    let len = records::<SIZE>();
+    for newest in 0..len - 1 {
+        erase();
+        for index in 0..len {
+            let lap = if index <= newest { laps } else { laps - 1 };
+            write_record(index, lap * len + index, [0; SIZE]);
+        }
+        assert_eq!(
+            head::<SIZE>(),
+            (newest * record_size::<SIZE>(), laps * len + newest),
+            "wrapped after record {newest} of {SIZE} bytes"
+        );
+    }
+}
+
+#[test]
+fn wrapped_ring_ends_at_the_newest_record_for_each_size() {
+    for laps in [1, 2, 7] {
+        wrapped_ring_ends_at_the_newest_record::<DRIFT_SIZE>(laps);
+        wrapped_ring_ends_at_the_newest_record::<ENVELOPE_SIZE>(laps);
+        wrapped_ring_ends_at_the_newest_record::<OLD_ENVELOPE_SIZE>(laps);
+    }
+}
+
+/// Restarts the device `restarts` times, changing the first byte each time,
+/// and checks every restart picks up where the last one left off
+fn restarts_keep_the_latest_data<const SIZE: usize>(restarts: u16) {
+    erase();
+    let mut expected = [0x42; SIZE];
+    for restart in 0..restarts {
+        let mut memory = [0x42; SIZE];
+        let mut writer =
+            WearLevelledEepromWriter::<SIZE>::init_and_advance(EEPROM, &mut memory, false);
+        assert_eq!(memory, expected, "restart {restart} of {SIZE} byte records");
+        assert_eq!(writer.version, restart);
+        assert_eq!(
+            writer.address,
+            restart % records::<SIZE>() * record_size::<SIZE>()
+        );
+
+        writer.update_byte(0, restart as u8);
+        expected[0] = restart as u8;
+    }
+}
+
+#[test]
+fn restarts_keep_the_latest_data_for_each_size() {
+    restarts_keep_the_latest_data::<DRIFT_SIZE>(5 * records::<DRIFT_SIZE>());
+    restarts_keep_the_latest_data::<ENVELOPE_SIZE>(5 * records::<ENVELOPE_SIZE>());
+    restarts_keep_the_latest_data::<OLD_ENVELOPE_SIZE>(5 * records::<OLD_ENVELOPE_SIZE>());
+}
+
+/// Until the ring filled up, the old search found the newest record too, so
+/// anything saved with it is still there
+fn rings_saved_by_the_old_search_keep_their_data<const SIZE: usize>() {
+    for last in 0..records::<SIZE>() - 1 {
+        erase();
+        for index in 0..=last {
+            write_record(index, index, [index as u8; SIZE]);
+        }
+        let mut memory = [0; SIZE];
+        let writer = WearLevelledEepromWriter::<SIZE>::init_and_advance(EEPROM, &mut memory, false);
+        assert_eq!(
+            memory, [last as u8; SIZE],
+            "records 0 to {last} of {SIZE} bytes"
+        );
+        assert_eq!(writer.version, last + 1);
+    }
+}
+
+#[test]
+fn rings_saved_by_the_old_search_keep_their_data_for_each_size() {
+    rings_saved_by_the_old_search_keep_their_data::<DRIFT_SIZE>();
+    rings_saved_by_the_old_search_keep_their_data::<OLD_ENVELOPE_SIZE>();
+}
+
+/// Once the ring had filled up, the old search took its first record for the
+/// newest, so every restart after that copied the first record over the second.
+/// Those devices now go on from the last record they filled the ring with, and
+/// wrap around from there.
+fn full_rings_saved_by_the_old_search_go_on_from_the_last_record<const SIZE: usize>() {
+    erase();
+    let len = records::<SIZE>();
+    for index in 0..len {
+        write_record(index, index, [index as u8; SIZE]);
+    }
+    write_record(1, 1, [0; SIZE]);
+
+    let mut memory = [0; SIZE];
+    let writer = WearLevelledEepromWriter::<SIZE>::init_and_advance(EEPROM, &mut memory, false);
+    assert_eq!(memory, [(len - 1) as u8; SIZE]);
+    assert_eq!((writer.address, writer.version), (0, len));
+}
+
+#[test]
+fn full_rings_saved_by_the_old_search_go_on_from_the_last_record_for_each_size() {
+    full_rings_saved_by_the_old_search_go_on_from_the_last_record::<DRIFT_SIZE>();
+    full_rings_saved_by_the_old_search_go_on_from_the_last_record::<OLD_ENVELOPE_SIZE>();
+}
+
+/// Starts up like the Envelope: records in the new layout start with a marker
+/// byte, and records in the old one only had the first byte
+fn init_envelope(
+    memory: &mut [u8; ENVELOPE_SIZE],
+    clear: bool,
+) -> WearLevelledEepromWriter<ENVELOPE_SIZE> {
+    memory[1] = 0xF5;
+    WearLevelledEepromWriter::<ENVELOPE_SIZE>::init_and_migrate::<OLD_ENVELOPE_SIZE>(
+        EEPROM,
+        memory,
+        clear,
+        |data| data[1] == 0xF5,
+        |old_data, data| data[0] = old_data[0],
+    )
+}
+
+#[test]
+fn record_size_change_keeps_the_newest_old_record() {
+    let len = records::<OLD_ENVELOPE_SIZE>();
+    for newest in [0, 1, 100, len - 2, len - 1] {
+        erase();
+        for index in 0..=newest {
+            write_record(index, index, [index as u8 % 6]);
+        }
+
+        let mut memory = [0; ENVELOPE_SIZE];
+        let writer = init_envelope(&mut memory, false);
+        assert_eq!(memory[0], newest as u8 % 6, "old records 0 to {newest}");
+        assert_eq!((writer.address, writer.version), (0, 0));
+        assert_eq!(read_record::<ENVELOPE_SIZE>(0), memory);
+        assert_eq!(head::<ENVELOPE_SIZE>(), (0, 0));
+    }
+}
+
+#[test]
+fn record_size_change_keeps_the_newest_old_record_after_wrapping() {
+    let len = records::<OLD_ENVELOPE_SIZE>();
+    for index in 0..len {
+        let version = if index <= 10 { len + index } else { index };
+        write_record(index, version, [version as u8 % 6]);
+    }
+
+    let mut memory = [0; ENVELOPE_SIZE];
+    init_envelope(&mut memory, false);
+    assert_eq!(memory[0], (len + 10) as u8 % 6);
+}
+
+#[test]
+fn record_size_change_starts_over_without_old_records() {
+    let mut memory = [0; ENVELOPE_SIZE];
+    memory[0] = 3;
+    let writer = init_envelope(&mut memory, false);
+    assert_eq!(memory[0], 3);
+    assert_eq!((writer.address, writer.version), (0, 0));
+    assert_eq!(read_record::<ENVELOPE_SIZE>(0), memory);
+}
+
+#[test]
+fn records_in_the_new_layout_are_not_migrated_again() {
+    for index in 0..5 {
+        write_record(index, index, [index as u8 % 6]);
+    }
+    let mut memory = [0; ENVELOPE_SIZE];
+    init_envelope(&mut memory, false);
+    assert_eq!(memory[0], 4);
+
+    for restart in 1..3 * records::<ENVELOPE_SIZE>() {
+        let mut memory = [0; ENVELOPE_SIZE];
+        let mut writer = init_envelope(&mut memory, false);
+        assert_eq!(memory[0], (restart + 3) as u8 % 6, "restart {restart}");
+        assert_eq!(writer.version, restart);
+        writer.update_byte(0, (restart + 4) as u8 % 6);
+    }
+}
+
+#[test]
+fn clearing_ignores_old_records() {
+    for index in 0..5 {
+        write_record(index, index, [4]);
+    }
+    let mut memory = [0; ENVELOPE_SIZE];
+    let writer = init_envelope(&mut memory, true);
+    assert_eq!(memory[0], 0);
+    assert_eq!((writer.address, writer.version), (0, 0));
+}
@@ -1,5 +1,3 @@
-use core::mem::MaybeUninit;
-
 use arduino_hal::Eeprom;
 use avr_device::atmega328p::EEPROM;
 
@@ -27,6 +25,52 @@ impl<const SIZE: usize> WearLevelledEepromWriter<SIZE> {
             Self::clear_all(&mut eep);
         }
 
+        Self::advance_or_start(eep, memory)
+    }
+
+    /**
+    Like `init_and_advance`, for data that used to be `OLD_SIZE` bytes long.
+    Records in the old layout are a different size, so they can't be found by
+    searching for the new one. If the newest record isn't `current`, the newest
+    record in the old layout (if there is one) is converted with `migrate`, and
+    the EEPROM is cleared and started over with the result.
+    */
+    #[inline(never)]
+    pub fn init_and_migrate<const OLD_SIZE: usize>(
+        eeprom: EEPROM,
+        memory: &mut [u8; SIZE],
+        clear: bool,
+        current: impl FnOnce(&[u8; SIZE]) -> bool,
+        migrate: impl FnOnce(&[u8; OLD_SIZE], &mut [u8; SIZE]),
+    ) -> Self {
+        let mut eep = arduino_hal::Eeprom::new(eeprom);
+
+        if !clear {
+            let (address, version) = Self::binary_search_for_monotonic_ringbuffer_head(&eep);
+            if version != 0xFFFF {
+                let mut data = [0u8; SIZE];
+                eep.read(address + 2, &mut data).unwrap();
+                if current(&data) {
+                    return Self::advance_or_start(eep, memory);
+                }
+            }
+
+            let (old_address, old_version) =
+                WearLevelledEepromWriter::<OLD_SIZE>::binary_search_for_monotonic_ringbuffer_head(
+                    &eep,
+                );
+            if old_version != 0xFFFF {
+                let mut old_data = [0u8; OLD_SIZE];
+                eep.read(old_address + 2, &mut old_data).unwrap();
+                migrate(&old_data, memory);
+            }
+        }
+
+        Self::clear_all(&mut eep);
+        Self::advance_or_start(eep, memory)
+    }
+
+    fn advance_or_start(eep: Eeprom, memory: &mut [u8; SIZE]) -> Self {
         let (address, version) = Self::binary_search_for_monotonic_ringbuffer_head(&eep);
 
         let mut writer = Self {
@@ -50,7 +94,7 @@ impl<const SIZE: usize> WearLevelledEepromWriter<SIZE> {
         let marker_bytes = self.version.to_le_bytes();
         self.eeprom.erase_byte(self.address + 1);
         self.eeprom.write(self.address + 2, data).unwrap();
-        self.eeprom.write_byte(self.address + 0, marker_bytes[0]);
+        self.eeprom.write_byte(self.address, marker_bytes[0]);
         self.eeprom.write_byte(self.address + 1, marker_bytes[1]);
     }
 
@@ -67,7 +111,7 @@ impl<const SIZE: usize> WearLevelledEepromWriter<SIZE> {
             self.address = 0;
         }
 
-        let mut data: [u8; SIZE] = unsafe { MaybeUninit::uninit().assume_init() };
+        let mut data = [0u8; SIZE];
         self.eeprom.read(self.address + 2, &mut data).unwrap();
 
         self.eeprom.erase_byte(new_address + 1);
@@ -150,10 +194,13 @@ impl<const SIZE: usize> WearLevelledEepromWriter<SIZE> {
                 high_value = mid_value;
             } else if gt(mid_value, high_value) {
                 if mid_idx + 1 == high_idx {
-                    return (mid_idx * Self::TOTAL_SIZE, mid_idx);
+                    return (mid_idx * Self::TOTAL_SIZE, mid_value);
                 }
                 low_idx = mid_idx;
                 low_value = mid_value;
+            } else if high_value != 0xFFFF {
+                // Every record is in order, so the last one is the newest
+                return (high_idx * Self::TOTAL_SIZE, high_value);
             } else {
                 return (low_idx * Self::TOTAL_SIZE, low_value);
             }
@@ -52,6 +52,25 @@ pub struct ChannelConfig {
     pub multiplier_mode: MultiplierMode,
 }
 
+/**
+The two bytes to send over SPI to write a 12-bit value to the given dac channel.
+Only needed to talk to the DAC without this driver, e.g. from an interrupt.
+*/
+pub fn command_bytes(channel: DacChannel, value: u16, config: &ChannelConfig) -> [u8; 2] {
+    let data_low: u8 = value as u8;
+    let data_high: u8 = (value >> 8) as u8;
+    debug_assert!(data_high <= 0xf);
+
+    let dac_bit = (channel as u8) << 7;
+    let buf_bit = (config.buffer_mode as u8) << 6;
+    let ga_bit = (config.multiplier_mode as u8) << 5;
+    let shdn_bit = (config.power as u8) << 4;
+
+    let first_byte = dac_bit | buf_bit | ga_bit | shdn_bit | data_high;
+    let second_byte = data_low;
+    [first_byte, second_byte]
+}
+
 impl<PIN> MCP4922<PIN>
 where
     PIN: OutputPin<Error = Infallible>,
@@ -106,21 +125,10 @@ where
         value: u16,
         config: &ChannelConfig,
     ) {
-        let data_low: u8 = value as u8;
-        let data_high: u8 = (value >> 8) as u8;
-        debug_assert!(data_high <= 0xf);
-
-        let dac_bit = (channel as u8) << 7;
-        let buf_bit = (config.buffer_mode as u8) << 6;
-        let ga_bit = (config.multiplier_mode as u8) << 5;
-        let shdn_bit = (config.power as u8) << 4;
-
-        let first_byte = dac_bit | buf_bit | ga_bit | shdn_bit | data_high;
-        let second_byte = data_low;
+        let mut bytes = command_bytes(channel, value, config);
 
         self.cs_pin.set_low().unwrap_infallible();
-        spi.transfer(&mut [first_byte, second_byte])
-            .unwrap_infallible();
+        spi.transfer(&mut bytes).unwrap_infallible();
     }
 
     /**
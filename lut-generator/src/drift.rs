@@ -0,0 +1,173 @@
+//! Tables for the Drift module
+
+use crate::{to_i16, to_u16, to_u32, to_u8, Lut};
+
+pub const TABLE_SIZE: usize = 256;
+
+/**
+How many octaves the exp2 table covers
+*/
+pub const EXP2_OCTAVES: f64 = 16.0;
+
+/**
+Computes 2^x on the interval [0, 16), sampled at 256 uniform points, as (16.16)
+fixed point numbers. `get_delta_t` interpolates it to turn a 1V/oct control
+voltage into a frequency.
+*/
+pub fn make_exp2_lut() -> [u32; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        let x = i as f64 * EXP2_OCTAVES / TABLE_SIZE as f64;
+        to_u32(x.exp2() * (1 << 16) as f64)
+    })
+}
+
+/**
+Half of a cosine wave, rising from 0 to 1 over [0, 1), sampled at 256 uniform
+points and scaled to the full range of a u16. The LFO uses this to turn its
+triangle wave into a sine wave.
+*/
+pub fn make_sine_lut() -> [u16; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        let x = i as f64 / TABLE_SIZE as f64;
+        to_u16(sine_reference(x) * u16::MAX as f64)
+    })
+}
+
+pub fn sine_reference(x: f64) -> f64 {
+    (1.0 - (x * std::f64::consts::PI).cos()) / 2.0
+}
+
+/**
+Samples an inverse CDF at p = i / 256 and scales it to (1.15) fixed point
+numbers. Feeding the table a uniformly distributed random number gives one
+with that distribution. Every distribution is on [-1, 1]. The uniform
+distribution doesn't need a table.
+*/
+pub fn make_icdf_lut(icdf: impl Fn(f64) -> f64) -> [i16; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        // The firmware looks up a 15 bit random number, so the table is
+        // indexed by its top 8 bits
+        let u = i << 7;
+        let p = u as f64 / i16::MAX as f64;
+        to_i16(icdf(p) * i16::MAX as f64)
+    })
+}
+
+pub fn triangle_icdf(p: f64) -> f64 {
+    if p < 0.5 {
+        -1.0 + (2.0 * p).sqrt()
+    } else {
+        1.0 - (2.0 * (1.0 - p)).sqrt()
+    }
+}
+
+pub fn triangle_pdf(x: f64) -> f64 {
+    1.0 - x.abs()
+}
+
+/**
+The standard deviation of the Gaussian distribution, before it is cut off at
++/-1. Almost all of it (99.7%) fits.
+*/
+pub const GAUSSIAN_SIGMA: f64 = 1.0 / 3.0;
+
+pub fn gaussian_pdf(x: f64) -> f64 {
+    (-0.5 * (x / GAUSSIAN_SIGMA).powi(2)).exp()
+}
+
+/**
+Half of the Cauchy distribution is within this distance of 0, before it is
+cut off at +/-1. The rest makes the occasional big jump.
+*/
+pub const CAUCHY_SCALE: f64 = 0.1;
+
+/**
+The Cauchy distribution, cut off at +/-1
+*/
+pub fn cauchy_icdf(p: f64) -> f64 {
+    let max_angle = (1.0 / CAUCHY_SCALE).atan();
+    CAUCHY_SCALE * ((2.0 * p - 1.0) * max_angle).tan()
+}
+
+pub fn cauchy_pdf(x: f64) -> f64 {
+    1.0 / (1.0 + (x / CAUCHY_SCALE).powi(2))
+}
+
+/**
+The bimodal distribution is two Gaussians centered at +/- this
+*/
+pub const BIMODAL_CENTER: f64 = 0.6;
+/// The standard deviation of each of the two Gaussians
+pub const BIMODAL_SIGMA: f64 = 0.15;
+
+pub fn bimodal_pdf(x: f64) -> f64 {
+    let peak = |center: f64| (-0.5 * ((x - center) / BIMODAL_SIGMA).powi(2)).exp();
+    peak(-BIMODAL_CENTER) + peak(BIMODAL_CENTER)
+}
+
+/**
+Numerically inverts the CDF of a distribution on [-1, 1], given its density,
+which doesn't need to be normalized. This is for the distributions that don't
+have a closed form inverse CDF.
+*/
+pub fn icdf_from_pdf(pdf: impl Fn(f64) -> f64) -> impl Fn(f64) -> f64 {
+    const STEPS: usize = 1 << 16;
+    let step = 2.0 / STEPS as f64;
+    // The CDF at each step, integrated with the trapezoid rule
+    let mut cdf = Vec::with_capacity(STEPS + 1);
+    cdf.push(0.0);
+    let mut total = 0.0;
+    let mut last = pdf(-1.0);
+    for k in 1..=STEPS {
+        let next = pdf(-1.0 + k as f64 * step);
+        total += (last + next) / 2.0 * step;
+        cdf.push(total);
+        last = next;
+    }
+
+    move |p| {
+        let target = p * total;
+        let k = cdf.partition_point(|&c| c <= target).clamp(1, STEPS);
+        let (low, high) = (cdf[k - 1], cdf[k]);
+        let fraction = if high > low {
+            (target - low) / (high - low)
+        } else {
+            0.0
+        };
+        -1.0 + (k - 1) as f64 * step + fraction * step
+    }
+}
+
+/**
+The LED brightness is perceived on a roughly logarithmic scale, so the LED's
+PWM duty cycle is its 8 bit brightness raised to this power
+*/
+pub const GAMMA: f64 = 2.2;
+
+/**
+Maps an 8 bit brightness to an 8 bit PWM duty cycle, with gamma correction
+*/
+pub fn make_gamma_lut() -> [u8; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        let x = i as f64 / 255.0;
+        to_u8(x.powf(GAMMA) * 255.0)
+    })
+}
+
+pub fn luts() -> Vec<Lut> {
+    vec![
+        Lut::from_u32("exp2lut.bin", &make_exp2_lut()),
+        Lut::from_u16("sine_lut.bin", &make_sine_lut()),
+        Lut::from_i16("icdf_triangle.bin", &make_icdf_lut(triangle_icdf)),
+        Lut::from_i16(
+            "icdf_gaussian.bin",
+            &make_icdf_lut(icdf_from_pdf(gaussian_pdf)),
+        ),
+        Lut::from_i16("icdf_cauchy.bin", &make_icdf_lut(cauchy_icdf)),
+        Lut::from_i16(
+            "icdf_bimodal.bin",
+            &make_icdf_lut(icdf_from_pdf(bimodal_pdf)),
+        ),
+        Lut::from_u8("gamma_lut.bin", &make_gamma_lut()),
+    ]
+}
@@ -0,0 +1,66 @@
+//! Tables for the Envelope module
+
+use crate::{to_u16, Lut};
+
+pub const TABLE_SIZE: usize = 256;
+
+/**
+Computes the curve (2^x - 1) / x on the interval [0, 1) (which has the range
+[ln(2), 1)), scaled to the range [0, 2^16), sampled at 256 uniform points,
+rounded to 16 bit integers.
+
+Multiplying an entry by x gives 2^x - 1 with the same relative precision no
+matter how small x is, which the curves need when they are nearly linear. The
+integer part of an exponent is applied with a bit shift, so one octave is all
+the table needs to cover.
+*/
+pub fn make_exp2_lut() -> [u16; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        let x = (i as f64) / TABLE_SIZE as f64;
+        to_u16(exp2_reference(x) * (u16::MAX as f64 + 1.0))
+    })
+}
+
+/**
+(2^x - 1) / x, and its limit at 0
+*/
+pub fn exp2_reference(x: f64) -> f64 {
+    if x == 0.0 {
+        std::f64::consts::LN_2
+    } else {
+        (x.exp2() - 1.0) / x
+    }
+}
+
+/**
+Computes the curve log_2(1 + x) / x - 1 on the interval [0, 1), (which has the
+range (0, 1/ln(2) - 1]) scaled to the range [0, 2^16), sampled at 256 uniform
+points, rounded to 16 bit integers.
+
+Like the exp2 table, this is divided by x so that multiplying an entry (plus
+one) by x gives log_2(1 + x) without losing precision near zero.
+*/
+pub fn make_log2_lut() -> [u16; TABLE_SIZE] {
+    core::array::from_fn(|i| {
+        let x = (i as f64) / TABLE_SIZE as f64;
+        to_u16(log2_reference(x) * (u16::MAX as f64 + 1.0))
+    })
+}
+
+/**
+log_2(1 + x) / x - 1, and its limit at 0
+*/
+pub fn log2_reference(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0 / std::f64::consts::LN_2 - 1.0
+    } else {
+        (1.0 + x).log2() / x - 1.0
+    }
+}
+
+pub fn luts() -> Vec<Lut> {
+    vec![
+        Lut::from_u16("exp2lut.bin", &make_exp2_lut()),
+        Lut::from_u16("log2lut.bin", &make_log2_lut()),
+    ]
+}
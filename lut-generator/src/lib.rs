@@ -0,0 +1,94 @@
+//! Generates the lookup tables that the firmware keeps in progmem. Computing
+//! these curves with floating point math is far too slow on an Atmega, so the
+//! firmware interpolates between precomputed entries instead.
+//!
+//! Each firmware crate's build script writes its tables to `OUT_DIR` with
+//! [`write_luts`], and the firmware loads them with
+//! `include_bytes!(concat!(env!("OUT_DIR"), "/<file name>"))`. The CLI in
+//! `main.rs` writes the same files to a directory, to look at them.
+
+use std::{fs, io, path::Path};
+
+pub mod drift;
+pub mod envelope;
+
+/**
+One table, as the bytes the firmware loads
+*/
+pub struct Lut {
+    pub file_name: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl Lut {
+    pub fn from_u8(file_name: &'static str, table: &[u8]) -> Self {
+        Self {
+            file_name,
+            bytes: table.to_vec(),
+        }
+    }
+
+    pub fn from_u16(file_name: &'static str, table: &[u16]) -> Self {
+        Self {
+            file_name,
+            bytes: table.iter().flat_map(|n| n.to_le_bytes()).collect(),
+        }
+    }
+
+    pub fn from_i16(file_name: &'static str, table: &[i16]) -> Self {
+        Self {
+            file_name,
+            bytes: table.iter().flat_map(|n| n.to_le_bytes()).collect(),
+        }
+    }
+
+    pub fn from_u32(file_name: &'static str, table: &[u32]) -> Self {
+        Self {
+            file_name,
+            bytes: table.iter().flat_map(|n| n.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/**
+Writes each table to a file in `dir`. Files that already have the right
+contents are left alone, so that cargo doesn't rebuild anything that includes
+them.
+*/
+pub fn write_luts(luts: &[Lut], dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for lut in luts {
+        let path = dir.join(lut.file_name);
+        if fs::read(&path).ok().as_deref() != Some(&lut.bytes[..]) {
+            fs::write(&path, &lut.bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+Rounds `value` to the nearest integer, making sure it is between `min` and
+`max`
+*/
+fn to_integer(value: f64, min: f64, max: f64) -> f64 {
+    let rounded = value.round();
+    assert!(rounded >= min, "{value} is below {min}");
+    assert!(rounded <= max, "{value} is above {max}");
+    rounded
+}
+
+fn to_u8(value: f64) -> u8 {
+    to_integer(value, 0.0, u8::MAX as f64) as u8
+}
+
+fn to_u16(value: f64) -> u16 {
+    to_integer(value, 0.0, u16::MAX as f64) as u16
+}
+
+fn to_i16(value: f64) -> i16 {
+    to_integer(value, i16::MIN as f64, i16::MAX as f64) as i16
+}
+
+fn to_u32(value: f64) -> u32 {
+    to_integer(value, 0.0, u32::MAX as f64) as u32
+}
@@ -0,0 +1,29 @@
+use std::{env, path::PathBuf, process::ExitCode};
+
+/**
+Writes one module's tables to a directory, which defaults to the current one.
+The firmware builds them on its own, so this is only needed to look at them.
+*/
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let module = args.next().unwrap_or_default();
+    let dir = PathBuf::from(args.next().unwrap_or(String::from(".")));
+
+    let luts = match module.as_str() {
+        "drift" => lut_generator::drift::luts(),
+        "envelope" => lut_generator::envelope::luts(),
+        _ => {
+            eprintln!("Usage: lut-generator <drift|envelope> [output directory]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(error) = lut_generator::write_luts(&luts, &dir) {
+        eprintln!("Couldn't write the tables to {}: {error}", dir.display());
+        return ExitCode::FAILURE;
+    }
+    for lut in &luts {
+        println!("{}", dir.join(lut.file_name).display());
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,140 @@
+use lut_generator::drift::{
+    bimodal_pdf, cauchy_icdf, cauchy_pdf, gaussian_pdf, icdf_from_pdf, make_exp2_lut,
+    make_gamma_lut, make_icdf_lut, make_sine_lut, sine_reference, triangle_icdf, triangle_pdf,
+    BIMODAL_CENTER, CAUCHY_SCALE, EXP2_OCTAVES, GAMMA, GAUSSIAN_SIGMA, TABLE_SIZE,
+};
+
+/// Checks that every entry is its reference value, correctly rounded
+fn assert_rounded(name: &str, table: &[f64], reference: impl Fn(usize) -> f64) {
+    for (i, &entry) in table.iter().enumerate() {
+        let expected = reference(i);
+        assert!(
+            (entry - expected).abs() <= 0.5,
+            "{name}[{i}] is {entry}, expected {expected}"
+        );
+    }
+}
+
+/// The firmware's interpolation: the top 8 bits of a 16 bit input pick the
+/// entry, and the rest of the bits how far to go toward the next one. The last
+/// entry is held for the inputs past it.
+fn interpolate(table: &[f64], input: u16) -> f64 {
+    let low = (input >> 8) as usize;
+    let high = usize::min(TABLE_SIZE - 1, low + 1);
+    let remainder = (input & 0xFF) as f64 / 256.0;
+    table[low] + (table[high] - table[low]) * remainder
+}
+
+#[test]
+fn exp2_lut_is_2_to_the_x() {
+    let table: Vec<f64> = make_exp2_lut().iter().map(|&n| n as f64).collect();
+    let scale = (1 << 16) as f64;
+    assert_rounded("exp2", &table, |i| {
+        (i as f64 * EXP2_OCTAVES / TABLE_SIZE as f64).exp2() * scale
+    });
+
+    // Each entry is 1/16th of an octave apart, which is close enough that
+    // interpolating is off by at most (ln(2) / 16)^2 / 8
+    for input in 0..=(255 << 8) {
+        let x = input as f64 * EXP2_OCTAVES / 65536.0;
+        let expected = x.exp2() * scale;
+        let error = interpolate(&table, input) / expected - 1.0;
+        assert!(error.abs() < 2.5e-4, "2^{x}: relative error {error}");
+    }
+}
+
+#[test]
+fn sine_lut_is_half_a_cosine() {
+    let table: Vec<f64> = make_sine_lut().iter().map(|&n| n as f64).collect();
+    let scale = u16::MAX as f64;
+    assert_rounded("sine", &table, |i| {
+        sine_reference(i as f64 / TABLE_SIZE as f64) * scale
+    });
+    assert_eq!(table[0], 0.0);
+    assert!(table.windows(2).all(|pair| pair[0] < pair[1]));
+
+    for input in 0..=(255 << 8) {
+        let x = input as f64 / 65536.0;
+        let error = interpolate(&table, input) / scale - sine_reference(x);
+        assert!(error.abs() < 2e-5, "sine({x}): error {error}");
+    }
+}
+
+fn assert_icdf_lut(name: &str, icdf: impl Fn(f64) -> f64) {
+    let table: Vec<f64> = make_icdf_lut(&icdf).iter().map(|&n| n as f64).collect();
+    let scale = i16::MAX as f64;
+    assert_rounded(name, &table, |i| icdf((i << 7) as f64 / scale) * scale);
+    assert_eq!(table[0], -scale, "{name}");
+    assert!(table.windows(2).all(|pair| pair[0] <= pair[1]), "{name}");
+}
+
+#[test]
+fn icdf_luts_are_the_inverse_cdfs() {
+    assert_icdf_lut("triangle", triangle_icdf);
+    assert_icdf_lut("gaussian", icdf_from_pdf(gaussian_pdf));
+    assert_icdf_lut("cauchy", cauchy_icdf);
+    assert_icdf_lut("bimodal", icdf_from_pdf(bimodal_pdf));
+}
+
+#[test]
+fn triangle_icdf_reference_is_a_triangle_distribution() {
+    // The CDF of a triangle distribution on [-1, 1] is (x + 1)^2 / 2 below 0
+    for i in 0..=100 {
+        let x = -1.0 + i as f64 / 100.0;
+        let p = (x + 1.0).powi(2) / 2.0;
+        assert!((triangle_icdf(p) - x).abs() < 1e-12);
+        assert!((triangle_icdf(1.0 - p) + x).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn numeric_icdf_matches_the_closed_forms() {
+    let triangle = icdf_from_pdf(triangle_pdf);
+    let cauchy = icdf_from_pdf(cauchy_pdf);
+    for i in 0..=1000 {
+        let p = i as f64 / 1000.0;
+        let error = triangle(p) - triangle_icdf(p);
+        assert!(error.abs() < 1e-4, "triangle({p}): {error}");
+        let error = cauchy(p) - cauchy_icdf(p);
+        assert!(error.abs() < 1e-4, "cauchy({p}): {error}");
+    }
+}
+
+#[test]
+fn cauchy_icdf_has_half_of_it_within_the_scale() {
+    // Cutting the distribution off at +/-1 moves the quartiles in a little
+    let quartile = cauchy_icdf(0.75);
+    assert!(quartile > 0.0 && quartile < CAUCHY_SCALE, "{quartile}");
+    assert!((cauchy_icdf(0.5)).abs() < 1e-12);
+    assert!((cauchy_icdf(1.0) - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn gaussian_icdf_has_68_percent_within_one_sigma() {
+    let icdf = icdf_from_pdf(gaussian_pdf);
+    // erf(1 / sqrt(2)) and erf(3 / sqrt(2)), from a table
+    let within_sigma = 0.682_689_492_137_086;
+    let within_cutoff = 0.997_300_203_936_740;
+    let p = 0.5 + within_sigma / within_cutoff / 2.0;
+    assert!((icdf(p) - GAUSSIAN_SIGMA).abs() < 1e-4, "{}", icdf(p));
+    assert!(icdf(0.5).abs() < 1e-4);
+}
+
+#[test]
+fn bimodal_icdf_has_half_of_it_around_each_peak() {
+    let icdf = icdf_from_pdf(bimodal_pdf);
+    assert!(icdf(0.5).abs() < 1e-4);
+    // The peaks are far enough apart that each is almost exactly the median
+    // of its half
+    assert!((icdf(0.25) + BIMODAL_CENTER).abs() < 0.01, "{}", icdf(0.25));
+    assert!((icdf(0.75) - BIMODAL_CENTER).abs() < 0.01, "{}", icdf(0.75));
+}
+
+#[test]
+fn gamma_lut_is_gamma_corrected() {
+    let table: Vec<f64> = make_gamma_lut().iter().map(|&n| n as f64).collect();
+    assert_rounded("gamma", &table, |i| (i as f64 / 255.0).powf(GAMMA) * 255.0);
+    assert_eq!(table[0], 0.0);
+    assert_eq!(table[255], 255.0);
+    assert!(table.windows(2).all(|pair| pair[0] <= pair[1]));
+}
@@ -0,0 +1,36 @@
+use lut_generator::envelope::{
+    exp2_reference, log2_reference, make_exp2_lut, make_log2_lut, TABLE_SIZE,
+};
+
+const SCALE: f64 = (1 << 16) as f64;
+
+#[test]
+fn exp2_lut_entries_are_correctly_rounded() {
+    for (i, &entry) in make_exp2_lut().iter().enumerate() {
+        let x = i as f64 / TABLE_SIZE as f64;
+        let expected = exp2_reference(x) * SCALE;
+        assert!((entry as f64 - expected).abs() <= 0.5, "exp2[{i}]");
+        // Multiplying back out by x gives 2^x - 1
+        let exp2 = entry as f64 / SCALE * x + 1.0;
+        assert!((exp2 / x.exp2() - 1.0).abs() < 1e-5, "2^{x}: {exp2}");
+    }
+}
+
+#[test]
+fn log2_lut_entries_are_correctly_rounded() {
+    for (i, &entry) in make_log2_lut().iter().enumerate() {
+        let x = i as f64 / TABLE_SIZE as f64;
+        let expected = log2_reference(x) * SCALE;
+        assert!((entry as f64 - expected).abs() <= 0.5, "log2[{i}]");
+        let log2 = (entry as f64 / SCALE + 1.0) * x;
+        let error = log2 - (1.0 + x).log2();
+        assert!(error.abs() <= x / SCALE, "log2(1 + {x}): {log2}");
+    }
+}
+
+#[test]
+fn references_are_continuous_at_zero() {
+    let tiny = 1e-6;
+    assert!((exp2_reference(0.0) - exp2_reference(tiny)).abs() < 1e-6);
+    assert!((log2_reference(0.0) - log2_reference(tiny)).abs() < 1e-6);
+}
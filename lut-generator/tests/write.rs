@@ -0,0 +1,39 @@
+use std::fs;
+
+use lut_generator::{drift, envelope, write_luts};
+
+#[test]
+fn tables_are_the_size_the_firmware_expects() {
+    let sizes: Vec<(&str, usize)> = drift::luts()
+        .iter()
+        .map(|lut| (lut.file_name, lut.bytes.len()))
+        .collect();
+    assert_eq!(
+        sizes,
+        [
+            ("exp2lut.bin", 256 * 4),
+            ("sine_lut.bin", 256 * 2),
+            ("icdf_triangle.bin", 256 * 2),
+            ("icdf_gaussian.bin", 256 * 2),
+            ("icdf_cauchy.bin", 256 * 2),
+            ("icdf_bimodal.bin", 256 * 2),
+            ("gamma_lut.bin", 256),
+        ]
+    );
+    for lut in envelope::luts() {
+        assert_eq!(lut.bytes.len(), 256 * 2, "{}", lut.file_name);
+    }
+}
+
+#[test]
+fn writes_every_table() {
+    let dir = std::env::temp_dir().join(format!("lut-generator-{}", std::process::id()));
+    let luts = drift::luts();
+    write_luts(&luts, &dir).unwrap();
+    // Writing again when nothing changed is fine too
+    write_luts(&luts, &dir).unwrap();
+    for lut in &luts {
+        assert_eq!(fs::read(dir.join(lut.file_name)).unwrap(), lut.bytes);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -5,7 +5,8 @@ use fixed::{
 use fm_lib::rng::ParallelLfsr;
 
 use crate::{
-    random::random_from_distribution,
+    clock::Clock,
+    random::{random_from_distribution, Distribution},
     shared::{get_delta_t, DriftModule},
 };
 
@@ -14,19 +15,39 @@ pub struct BezierModuleState {
     speed_adjust: i16,
     value_a: FixedU16<U12>,
     value_b: FixedU16<U12>,
+    /// Where the random values are drawn from, or None to draw them evenly
+    /// from the whole range
+    distribution: Option<Distribution>,
     rng: ParallelLfsr,
 }
 
 impl BezierModuleState {
-    pub fn new(random_seed: u16) -> Self {
-        let mut rng = ParallelLfsr::new(random_seed);
-        Self {
+    pub fn new(random_seed: u16, distribution: Option<Distribution>) -> Self {
+        let mut state = Self {
             time: 0,
             speed_adjust: 0,
             value_a: FixedU16::<U12>::from_bits(0),
-            value_b: FixedU16::<U12>::from_bits(rng.next() >> 4),
-            rng,
-        }
+            value_b: FixedU16::<U12>::from_bits(0),
+            distribution,
+            rng: ParallelLfsr::new(random_seed),
+        };
+        state.value_b = state.random_value();
+        state
+    }
+
+    /**
+    A new value for a curve to end at. With a distribution, the middle of the
+    range is its 0.
+    */
+    fn random_value(&mut self) -> FixedU16<U12> {
+        let bits = match self.distribution {
+            None => self.rng.next() >> 4,
+            Some(distribution) => {
+                let value = random_from_distribution(&mut self.rng, distribution);
+                ((value.to_bits() >> 4) + (1 << 11)) as u16
+            }
+        };
+        FixedU16::<U12>::from_bits(bits)
     }
 
     fn step_time(&mut self, knob: u16, cv: u16) -> (u32, bool) {
@@ -46,12 +67,7 @@ impl BezierModuleState {
         const DEAD_ZONE: u16 = 128;
         const RANGE: u16 = HALF - DEAD_ZONE;
 
-        let knob_magnitude = (if knob > HALF {
-            knob - HALF
-        } else {
-            HALF - knob
-        })
-        .saturating_sub(DEAD_ZONE);
+        let knob_magnitude = knob.abs_diff(HALF).saturating_sub(DEAD_ZONE);
         debug_assert!(knob_magnitude <= RANGE + 1);
 
         let sum = u16::min(knob_magnitude + cv / 2, RANGE);
@@ -62,7 +78,7 @@ impl BezierModuleState {
 
         let scaled =
             FixedI16::<U15>::from_bits((sum as u32 * i16::MAX as u32 / RANGE as u32) as i16);
-        (random_from_distribution(&mut self.rng) * scaled).to_bits()
+        (random_from_distribution(&mut self.rng, Distribution::Triangle) * scaled).to_bits()
     }
 }
 
@@ -88,7 +104,7 @@ fn _parametric_bezier_curve(x: FixedU16<U12>, c: FixedU16<U12>) -> FixedU16<U12>
 /**
 A special case of the bezier smoothing function
 
-```
+```text
 c * (1-x)^2 * x + (3-c) * (1-x) * x^2 + x^3
 ```
 
@@ -104,7 +120,7 @@ fn smooth_bezier_curve(x: FixedU16<U12>) -> FixedU16<U12> {
 /**
 A special case of the bezier smoothing function
 
-```
+```text
 c * (1-x)^2 * x + (3-c) * (1-x) * x^2 + x^3
 ```
 
@@ -128,20 +144,14 @@ fn reverse_bezier_interpolate(
     unsmooth_bezier_curve(x).lerp(a, b)
 }
 
-impl DriftModule for BezierModuleState {
-    fn step(&mut self, cv: &[u16; 4]) -> u16 {
-        let (t, rollover) = self.step_time(cv[2], cv[0]);
-
-        if rollover {
-            self.value_a = self.value_b;
-            self.value_b = FixedU16::<U12>::from_bits(self.rng.next() >> 4);
-            self.speed_adjust = self.get_speed_adjust(cv[3], cv[1]);
-            return self.value_a.to_bits();
-        }
-
+impl BezierModuleState {
+    /**
+    The value at time `t` along the curve from `value_a` to `value_b`
+    */
+    fn interpolate(&self, t: u32, texture_knob: u16) -> u16 {
         let t_fixed = FixedU16::<U12>::from_bits((t >> 20) as u16);
 
-        let result = if cv[3] < 1024 / 2 {
+        let result = if texture_knob < 1024 / 2 {
             reverse_bezier_interpolate(t_fixed, self.value_a, self.value_b)
         } else {
             bezier_interpolate(t_fixed, self.value_a, self.value_b)
@@ -155,3 +165,36 @@ impl DriftModule for BezierModuleState {
         }
     }
 }
+
+impl DriftModule for BezierModuleState {
+    fn step(&mut self, cv: &[u16; 4]) -> u16 {
+        let (t, rollover) = self.step_time(cv[2], cv[0]);
+
+        if rollover {
+            self.value_a = self.value_b;
+            self.value_b = self.random_value();
+            self.speed_adjust = self.get_speed_adjust(cv[3], cv[1]);
+            return self.value_a.to_bits();
+        }
+
+        self.interpolate(t, cv[3])
+    }
+
+    /**
+    Each clock starts a new curve toward a new random value. It starts from
+    wherever the output is, in case the clock came early, and waits at the end
+    if the clock is late.
+    */
+    fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16 {
+        if clock.edge {
+            let current = self.interpolate(self.time, cv[3]);
+            self.value_a = FixedU16::<U12>::from_bits(current);
+            self.value_b = self.random_value();
+            self.time = 0;
+            return current;
+        }
+
+        self.time = self.time.saturating_add(clock.delta_t);
+        self.interpolate(self.time, cv[3])
+    }
+}
@@ -1,45 +1,75 @@
 use fixed::{types::extra::U16, FixedU16};
 use fm_lib::rng::ParallelLfsr;
 
-use crate::shared::DriftModule;
+use crate::{
+    clock::Clock,
+    random::{random_from_distribution, Distribution},
+    shared::DriftModule,
+};
+
+/// The bottom and top 1/`CENTERING_MARGIN` of the range pull toward the center
+pub const CENTERING_MARGIN: u16 = 5;
+/// How much more likely a step toward the center is near the edges, as a
+/// fraction of all steps: 1/`CENTERING_STRENGTH`
+pub const CENTERING_STRENGTH: u16 = 64;
 
 pub struct BrownianModuleState {
     target_value: u16,
     current_value: u16,
+    /// How big each step is, or None for every step to be the same size
+    distribution: Option<Distribution>,
     rng: ParallelLfsr,
 }
 
 impl BrownianModuleState {
-    pub fn new(random_seed: u16) -> Self {
+    pub fn new(random_seed: u16, distribution: Option<Distribution>) -> Self {
         let rng = ParallelLfsr::new(random_seed);
         Self {
             target_value: 0,
             current_value: 0,
+            distribution,
             rng,
         }
     }
 
     fn step_target_value(&mut self, cv: u16) {
-        let step_size = 256 + cv >> 1;
+        let step_size = (256 + cv) >> 1;
         let cutoff = cv << 6;
 
         let random = self.rng.next();
         if random < cutoff {
-            const CENTERING_MARGIN: u16 = 5;
-            const CENTERING_STRENGTH: u16 = 64;
-            let cutoff2 = if self.target_value < u16::MAX / CENTERING_MARGIN {
-                (cutoff / 2) - (cutoff / CENTERING_STRENGTH)
-            } else if self.target_value > u16::MAX - (u16::MAX / CENTERING_MARGIN) {
-                (cutoff / 2) + (cutoff / CENTERING_STRENGTH)
-            } else {
-                cutoff / 2
-            };
+            self.take_step(random, cutoff, step_size);
+        }
+    }
 
-            if random >= cutoff2 {
-                self.target_value = self.target_value.saturating_add(step_size);
-            } else {
-                self.target_value = self.target_value.saturating_sub(step_size);
+    /**
+    Moves the target value up or down by `step_size`, with a slight bias toward
+    the center when it's near the edges. `random` should be evenly distributed
+    below `cutoff`. With a distribution, the size of the step is random too.
+    */
+    fn take_step(&mut self, random: u16, cutoff: u16, step_size: u16) {
+        let step_size = match self.distribution {
+            None => step_size,
+            // Twice the size of the distribution's magnitude (0 to 1), so a
+            // uniform distribution takes steps of `step_size` on average
+            Some(distribution) => {
+                let value = random_from_distribution(&mut self.rng, distribution);
+                let magnitude = value.to_bits().unsigned_abs() as u32;
+                u32::min((step_size as u32 * magnitude) >> 14, u16::MAX as u32) as u16
             }
+        };
+        let cutoff2 = if self.target_value < u16::MAX / CENTERING_MARGIN {
+            (cutoff / 2) - (cutoff / CENTERING_STRENGTH)
+        } else if self.target_value > u16::MAX - (u16::MAX / CENTERING_MARGIN) {
+            (cutoff / 2) + (cutoff / CENTERING_STRENGTH)
+        } else {
+            cutoff / 2
+        };
+
+        if random >= cutoff2 {
+            self.target_value = self.target_value.saturating_add(step_size);
+        } else {
+            self.target_value = self.target_value.saturating_sub(step_size);
         }
     }
 
@@ -72,4 +102,18 @@ impl DriftModule for BrownianModuleState {
         self.step_smoothed_value(u16::min(1023, cv[3] + cv[1]));
         self.current_value >> 4
     }
+
+    /**
+    Takes one step on each clock, always, with the speed knob setting the
+    size of the step instead of how often to take one
+    */
+    fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16 {
+        if clock.edge {
+            let step_size = (u16::min(1023, cv[2]) + 1) << 5;
+            let random = self.rng.next();
+            self.take_step(random, u16::MAX, step_size);
+        }
+        self.step_smoothed_value(u16::min(1023, cv[3] + cv[1]));
+        self.current_value >> 4
+    }
 }
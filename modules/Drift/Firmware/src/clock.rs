@@ -0,0 +1,93 @@
+/**
+The time base for following an external clock, passed to
+`DriftModule::step_clocked` each sample
+*/
+#[derive(Copy, Clone)]
+pub struct Clock {
+    /// How much to increment a 32-bit time counter each sample so that it
+    /// rolls over once per clock period, like `get_delta_t`
+    pub delta_t: u32,
+    /// True on the sample where a rising edge arrived
+    pub edge: bool,
+    /// True three quarters of the way through each period, where a clock a
+    /// quarter period ahead of this one would have its rising edge
+    pub quadrature_edge: bool,
+}
+
+/// About 2.5v
+const HIGH_THRESHOLD: u16 = 512;
+/// About 1.25v
+const LOW_THRESHOLD: u16 = 256;
+
+/**
+Finds rising edges in a gate or trigger read from a CV input
+*/
+#[derive(Default)]
+pub struct EdgeDetector {
+    high: bool,
+}
+
+impl EdgeDetector {
+    /**
+    Returns true if the input went high since the last sample. Uses separate
+    thresholds for rising and falling so that noise near the threshold doesn't
+    count as extra edges.
+    */
+    pub fn rising_edge(&mut self, cv: u16) -> bool {
+        if self.high {
+            if cv < LOW_THRESHOLD {
+                self.high = false;
+            }
+            false
+        } else {
+            self.high = cv >= HIGH_THRESHOLD;
+            self.high
+        }
+    }
+}
+
+/**
+Clocks faster than 100Hz, the top of the speed knob's range, are treated as
+100Hz so the modules' time counters can't overflow
+*/
+const MIN_PERIOD_SAMPLES: u16 = 25;
+
+/**
+Finds rising edges in a clock signal read from a CV input, and measures the
+time between them.
+*/
+#[derive(Default)]
+pub struct ClockInput {
+    input: EdgeDetector,
+    seen_edge: bool,
+    samples_since_edge: u16,
+    period: u16,
+    delta_t: u32,
+}
+
+impl ClockInput {
+    /**
+    Reads the clock input for one sample. Until the second rising edge, there's
+    no period to follow yet, so `delta_t` is 0.
+    */
+    pub fn step(&mut self, cv: u16) -> Clock {
+        self.samples_since_edge = self.samples_since_edge.saturating_add(1);
+
+        let edge = self.input.rising_edge(cv);
+        if edge {
+            if self.seen_edge {
+                self.period = u16::max(self.samples_since_edge, MIN_PERIOD_SAMPLES);
+                self.delta_t = u32::MAX / self.period as u32;
+            }
+            self.seen_edge = true;
+            self.samples_since_edge = 0;
+        }
+
+        Clock {
+            delta_t: self.delta_t,
+            edge,
+            quadrature_edge: self.period != 0
+                && self.samples_since_edge == self.period - (self.period >> 2),
+        }
+    }
+}
@@ -1,8 +1,154 @@
 use crate::{
-    bezier::BezierModuleState, brownian::BrownianModuleState, lfo::LfoModuleState,
-    perlin::PerlinModuleState, shared::DriftModule,
+    bezier::BezierModuleState,
+    brownian::BrownianModuleState,
+    clock::{Clock, ClockInput, EdgeDetector},
+    lfo::LfoModuleState,
+    perlin::PerlinModuleState,
+    settings::Settings,
+    shared::DriftModule,
 };
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AlgorithmKind {
+    Perlin,
+    Brownian,
+    Bezier,
+    Lfo,
+}
+
+pub const ALGORITHMS: [AlgorithmKind; 4] = [
+    AlgorithmKind::Perlin,
+    AlgorithmKind::Brownian,
+    AlgorithmKind::Bezier,
+    AlgorithmKind::Lfo,
+];
+
+impl AlgorithmKind {
+    /**
+    The algorithm picked by the DIP switches
+    */
+    pub fn from_switches(config: [bool; 2]) -> Self {
+        match (config[0], config[1]) {
+            (false, false) => AlgorithmKind::Perlin,
+            (false, true) => AlgorithmKind::Brownian,
+            (true, false) => AlgorithmKind::Bezier,
+            (true, true) => AlgorithmKind::Lfo,
+        }
+    }
+
+    pub fn index(self) -> u8 {
+        match self {
+            AlgorithmKind::Perlin => 0,
+            AlgorithmKind::Brownian => 1,
+            AlgorithmKind::Bezier => 2,
+            AlgorithmKind::Lfo => 3,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        ALGORITHMS.get(index as usize).copied()
+    }
+}
+
+/**
+What the texture CV input does
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TextureCv {
+    /// Adds to the texture knob, like normal
+    Texture,
+    /// Picks the algorithm, with each quarter of the 0-5v range selecting the
+    /// next one in the order of `ALGORITHMS`
+    SelectAlgorithm,
+    /// A trigger restarts the algorithm from its seed
+    Reset,
+}
+
+pub const TEXTURE_CV_MODES: [TextureCv; 3] = [
+    TextureCv::Texture,
+    TextureCv::SelectAlgorithm,
+    TextureCv::Reset,
+];
+
+impl TextureCv {
+    pub fn index(self) -> u8 {
+        match self {
+            TextureCv::Texture => 0,
+            TextureCv::SelectAlgorithm => 1,
+            TextureCv::Reset => 2,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        TEXTURE_CV_MODES.get(index as usize).copied()
+    }
+}
+
+/**
+What the speed CV input does
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SpeedCv {
+    /// Adds to the speed knob, like normal
+    Speed,
+    /// Takes a clock, and the algorithm follows it instead of the speed knob
+    Clock,
+}
+
+pub const SPEED_CV_MODES: [SpeedCv; 2] = [SpeedCv::Speed, SpeedCv::Clock];
+
+impl SpeedCv {
+    pub fn index(self) -> u8 {
+        match self {
+            SpeedCv::Speed => 0,
+            SpeedCv::Clock => 1,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        SPEED_CV_MODES.get(index as usize).copied()
+    }
+}
+
+/**
+What the second output does
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ChannelB {
+    /// Turned off, so it uses less power
+    Off,
+    /// The first output, upside down
+    Inverted,
+    /// For the LFO, the same wave a quarter of a cycle ahead. Otherwise, the
+    /// same as independent.
+    Quadrature,
+    /// The same algorithm, with its own random seed. For the LFO, only the
+    /// sample-and-hold is any different.
+    Independent,
+}
+
+pub const CHANNEL_B_MODES: [ChannelB; 4] = [
+    ChannelB::Off,
+    ChannelB::Inverted,
+    ChannelB::Quadrature,
+    ChannelB::Independent,
+];
+
+impl ChannelB {
+    pub fn index(self) -> u8 {
+        match self {
+            ChannelB::Off => 0,
+            ChannelB::Inverted => 1,
+            ChannelB::Quadrature => 2,
+            ChannelB::Independent => 3,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        CHANNEL_B_MODES.get(index as usize).copied()
+    }
+}
+
 pub enum DriftAlgorithm {
     Perlin(PerlinModuleState),
     Bezier(BezierModuleState),
@@ -11,12 +157,18 @@ pub enum DriftAlgorithm {
 }
 
 impl DriftAlgorithm {
-    pub fn new(config: [bool; 2], random_seed: u16) -> Self {
-        match (config[0], config[1]) {
-            (false, false) => Self::Perlin(PerlinModuleState::new(random_seed)),
-            (false, true) => Self::Brownian(BrownianModuleState::new(random_seed)),
-            (true, false) => Self::Bezier(BezierModuleState::new(random_seed)),
-            (true, true) => Self::Lfo(LfoModuleState::new()),
+    pub fn new(kind: AlgorithmKind, random_seed: u16, settings: &Settings) -> Self {
+        match kind {
+            AlgorithmKind::Perlin => {
+                Self::Perlin(PerlinModuleState::new(random_seed, settings.perlin_octaves))
+            }
+            AlgorithmKind::Brownian => {
+                Self::Brownian(BrownianModuleState::new(random_seed, settings.distribution))
+            }
+            AlgorithmKind::Bezier => {
+                Self::Bezier(BezierModuleState::new(random_seed, settings.distribution))
+            }
+            AlgorithmKind::Lfo => Self::Lfo(LfoModuleState::new(settings.lfo_shape, random_seed)),
         }
     }
 
@@ -28,4 +180,255 @@ impl DriftAlgorithm {
             DriftAlgorithm::Lfo(ref mut state) => state.step(cv),
         }
     }
+
+    pub fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16 {
+        match self {
+            DriftAlgorithm::Perlin(ref mut state) => state.step_clocked(cv, clock),
+            DriftAlgorithm::Bezier(ref mut state) => state.step_clocked(cv, clock),
+            DriftAlgorithm::Brownian(ref mut state) => state.step_clocked(cv, clock),
+            DriftAlgorithm::Lfo(ref mut state) => state.step_clocked(cv, clock),
+        }
+    }
+
+    /**
+    The algorithm for the second output, if it needs one
+    */
+    fn new_companion(kind: AlgorithmKind, random_seed: u16, settings: &Settings) -> Option<Self> {
+        match (settings.channel_b, kind) {
+            (ChannelB::Off | ChannelB::Inverted, _) => None,
+            (ChannelB::Quadrature, AlgorithmKind::Lfo) => Some(Self::Lfo(
+                LfoModuleState::new_quadrature(settings.lfo_shape, random_seed),
+            )),
+            // Any other seed would do, as long as it's different
+            _ => Some(Self::new(kind, random_seed ^ 0xA5A5, settings)),
+        }
+    }
+}
+
+/**
+About 100ms at 2.5kHz. A power of 2 so mixing doesn't need a division.
+*/
+const CROSSFADE_SAMPLES: u16 = 256;
+const CROSSFADE_SHIFT: u32 = CROSSFADE_SAMPLES.trailing_zeros();
+const _: () = assert!(CROSSFADE_SAMPLES.is_power_of_two());
+
+/**
+Smooths over a jump in the output (e.g. from switching algorithms) by fading
+from the last output value to the new signal.
+
+Fading from a fixed value, rather than keeping the old algorithm running until
+the fade is over, means switching again in the middle of a fade still doesn't
+jump, and nothing has to stay in memory.
+*/
+#[derive(Default)]
+pub struct Crossfade {
+    from: u16,
+    remaining: u16,
+    last_value: u16,
+}
+
+impl Crossfade {
+    /**
+    Starts fading from the given value to whatever is passed to `apply`
+    */
+    pub fn start_from(&mut self, value: u16) {
+        self.from = value;
+        self.remaining = CROSSFADE_SAMPLES;
+    }
+
+    /**
+    Starts fading from the last output value to whatever is passed to `apply`
+    */
+    pub fn start(&mut self) {
+        self.start_from(self.last_value);
+    }
+
+    pub fn last_value(&self) -> u16 {
+        self.last_value
+    }
+
+    /**
+    Mixes the new signal with the value being faded from, if a fade is in
+    progress. Should be called once per sample.
+    */
+    pub fn apply(&mut self, value: u16) -> u16 {
+        let mixed = if self.remaining > 0 {
+            self.remaining -= 1;
+            let progress = (CROSSFADE_SAMPLES - self.remaining) as i32;
+            let delta = value as i32 - self.from as i32;
+            (self.from as i32 + ((delta * progress) >> CROSSFADE_SHIFT)) as u16
+        } else {
+            value
+        };
+        self.last_value = mixed;
+        mixed
+    }
+}
+
+/**
+How far the algorithm select CV has to go past the edge of a zone before it
+switches to the next algorithm, so noise can't make it flip back and forth
+*/
+const SELECT_HYSTERESIS: u16 = 16;
+
+/**
+Splits the 10-bit CV range into one zone per algorithm, and only moves to a new
+zone once the CV is clearly inside it
+*/
+pub fn select_algorithm(cv: u16, current: AlgorithmKind) -> AlgorithmKind {
+    const ZONE_SIZE: u16 = 1024 / ALGORITHMS.len() as u16;
+    let zone = cv / ZONE_SIZE;
+    let current_zone = current.index() as u16;
+    if zone == current_zone {
+        return current;
+    }
+    let offset = cv % ZONE_SIZE;
+    let clearly_inside = if zone > current_zone {
+        offset >= SELECT_HYSTERESIS
+    } else {
+        offset < ZONE_SIZE - SELECT_HYSTERESIS
+    };
+    if clearly_inside {
+        AlgorithmKind::from_index(zone as u8).unwrap_or(current)
+    } else {
+        current
+    }
+}
+
+/**
+Everything that makes the module's outputs outside of setup mode: the current
+algorithm and its companion for the second output, the clock input and
+algorithm select CV if they are turned on, and the crossfade when the algorithm
+changes.
+*/
+pub struct Drift {
+    algorithm: DriftAlgorithm,
+    companion: Option<DriftAlgorithm>,
+    kind: AlgorithmKind,
+    settings: Settings,
+    switches: AlgorithmKind,
+    /// Only used if the settings don't pick a fixed seed
+    random_seed: u16,
+    clock: ClockInput,
+    reset: EdgeDetector,
+    crossfade: Crossfade,
+    crossfade_b: Crossfade,
+    output_b: Option<u16>,
+}
+
+impl Drift {
+    pub fn new(config: [bool; 2], random_seed: u16, settings: Settings) -> Self {
+        let switches = AlgorithmKind::from_switches(config);
+        let kind = settings.algorithm.unwrap_or(switches);
+        let seed = settings.seed(random_seed);
+        Self {
+            algorithm: DriftAlgorithm::new(kind, seed, &settings),
+            companion: DriftAlgorithm::new_companion(kind, seed, &settings),
+            kind,
+            settings,
+            switches,
+            random_seed,
+            clock: ClockInput::default(),
+            reset: EdgeDetector::default(),
+            crossfade: Crossfade::default(),
+            crossfade_b: Crossfade::default(),
+            output_b: None,
+        }
+    }
+
+    /**
+    Starts over with new settings, fading in from `last_value`
+    */
+    pub fn apply_settings(&mut self, settings: Settings, last_value: u16) {
+        self.settings = settings;
+        self.switch_to(settings.algorithm.unwrap_or(self.switches));
+        self.crossfade.start_from(last_value);
+    }
+
+    /**
+    Starts the algorithm over from its seed, so it makes exactly the same
+    output again, after a fade in from wherever the output was
+    */
+    fn switch_to(&mut self, kind: AlgorithmKind) {
+        let seed = self.settings.seed(self.random_seed);
+        self.kind = kind;
+        self.algorithm = DriftAlgorithm::new(kind, seed, &self.settings);
+        self.companion = DriftAlgorithm::new_companion(kind, seed, &self.settings);
+        self.crossfade.start();
+        self.crossfade_b.start();
+    }
+
+    /**
+    The value for the second output from the last call to `step`, or None if
+    it is turned off
+    */
+    pub fn output_b(&self) -> Option<u16> {
+        self.output_b
+    }
+
+    pub fn step(&mut self, cv: &[u16; 4]) -> u16 {
+        let mut cv = *cv;
+        match self.settings.texture_cv {
+            TextureCv::Texture => {}
+            TextureCv::SelectAlgorithm => {
+                let kind = select_algorithm(cv[1], self.kind);
+                if kind != self.kind {
+                    self.switch_to(kind);
+                }
+            }
+            TextureCv::Reset => {
+                if self.reset.rising_edge(cv[1]) {
+                    self.switch_to(self.kind);
+                }
+            }
+        }
+        if self.settings.texture_cv != TextureCv::Texture {
+            // The CV is busy, so only the knob sets the texture
+            cv[1] = 0;
+        }
+
+        let clock = match self.settings.speed_cv {
+            SpeedCv::Speed => None,
+            SpeedCv::Clock => {
+                let clock = self.clock.step(cv[0]);
+                cv[0] = 0;
+                Some(clock)
+            }
+        };
+
+        let value = self
+            .crossfade
+            .apply(step_algorithm(&mut self.algorithm, &cv, clock));
+
+        self.output_b = match self.companion {
+            Some(ref mut companion) => {
+                // The quadrature LFO is a quarter cycle ahead, so it has to
+                // start each cycle a quarter of the way before the clock
+                let clock = match self.settings.channel_b {
+                    ChannelB::Quadrature => clock.map(|clock| Clock {
+                        edge: clock.quadrature_edge,
+                        ..clock
+                    }),
+                    _ => clock,
+                };
+                let value_b = step_algorithm(companion, &cv, clock);
+                Some(self.crossfade_b.apply(value_b))
+            }
+            // Goes through the crossfade too, so that it knows where to fade
+            // from when channel B changes
+            None if self.settings.channel_b == ChannelB::Inverted => {
+                Some(self.crossfade_b.apply(0xFFF - value))
+            }
+            None => None,
+        };
+
+        value
+    }
+}
+
+fn step_algorithm(algorithm: &mut DriftAlgorithm, cv: &[u16; 4], clock: Option<Clock>) -> u16 {
+    match clock {
+        Some(clock) => algorithm.step_clocked(cv, clock),
+        None => algorithm.step(cv),
+    }
 }
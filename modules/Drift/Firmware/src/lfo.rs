@@ -1,59 +1,300 @@
-use fixed::{types::extra::U16, FixedU32};
+use avr_progmem::{progmem, wrapper::ProgMem};
+use fixed::{types::extra::U16, FixedU16};
+use fm_lib::rng::ParallelLfsr;
 
-use crate::shared::{get_delta_t, DriftModule};
+use crate::{
+    clock::Clock,
+    shared::{exp2_lut, get_delta_t, DriftModule},
+};
+
+const LUT_SIZE: usize = 256;
+const U16_BYTES: usize = u16::BITS as usize / 8;
+
+progmem! {
+    pub static progmem SINE_LUT: [u8; LUT_SIZE * U16_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/sine_lut.bin"));
+}
+
+const MAX_LEVEL: u32 = u32::MAX;
+
+/**
+The wave shapes the LFO can make. The texture knob and CV change the skew of
+the triangle, sine and exponential waves, the width of the square wave, and
+how far each step of the sample-and-hold can move.
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LfoShape {
+    Triangle,
+    Sine,
+    Square,
+    SampleAndHold,
+    Exponential,
+}
+
+pub const LFO_SHAPES: [LfoShape; 5] = [
+    LfoShape::Triangle,
+    LfoShape::Sine,
+    LfoShape::Square,
+    LfoShape::SampleAndHold,
+    LfoShape::Exponential,
+];
+
+impl LfoShape {
+    pub fn index(self) -> u8 {
+        match self {
+            LfoShape::Triangle => 0,
+            LfoShape::Sine => 1,
+            LfoShape::Square => 2,
+            LfoShape::SampleAndHold => 3,
+            LfoShape::Exponential => 4,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        LFO_SHAPES.get(index as usize).copied()
+    }
+}
 
 pub struct LfoModuleState {
+    shape: LfoShape,
     time: u32,
-    apex: u32,
+    level: u32,
+    rising: bool,
+    /// How far `time` is behind (positive) or ahead of (negative) the external
+    /// clock, still to be made up
+    phase_error: i32,
+    held_value: u16,
+    rng: ParallelLfsr,
 }
 
 impl LfoModuleState {
-    pub fn new() -> Self {
+    pub fn new(shape: LfoShape, random_seed: u16) -> Self {
+        let mut rng = ParallelLfsr::new(random_seed);
         Self {
+            shape,
             time: 0,
-            apex: u32::MAX / 2,
+            level: 0,
+            rising: true,
+            phase_error: 0,
+            held_value: rng.next(),
+            rng,
         }
     }
 
-    fn step_time(&mut self, knob: u16, cv: u16) -> (u32, bool) {
-        let dt = get_delta_t(knob, cv, 0);
-        self.time = self.time.saturating_add(dt);
-        let rollover = self.time == u32::MAX;
-        let before_rollover = self.time;
+    /**
+    Starts a quarter of a cycle ahead of `new`, so with the same settings the
+    two are 90 degrees apart. It starts where a symmetrical triangle would be,
+    and any other skew catches up by the end of the first rising segment.
+    */
+    pub fn new_quadrature(shape: LfoShape, random_seed: u16) -> Self {
+        Self {
+            time: 1 << 30,
+            level: MAX_LEVEL / 2,
+            ..Self::new(shape, random_seed)
+        }
+    }
+
+    /**
+    Advances the underlying triangle wave, which all of the shapes are built
+    from. Returns true at the start of each cycle.
+
+    Evaluating the wave as a closed-form function of time jumps whenever the
+    skew changes, so instead the phase counter only decides when each segment
+    ends, and the level moves toward the end of the current segment at whatever
+    rate gets it there on time. Changing the skew mid-cycle bends the current
+    segment without moving the output or the end of the cycle, and each segment
+    still lands exactly on its target, so integer errors can't accumulate.
+    */
+    fn step_triangle(&mut self, dt: u32, apex: u32) -> bool {
+        let mut from = self.time;
+        let (to, rollover) = self.time.overflowing_add(dt);
+        self.time = to;
+
         if rollover {
-            self.time = 0;
+            // The falling segment always ends at the bottom at the end of the
+            // cycle, and the leftover time belongs to the next rising segment
+            self.level = 0;
+            self.rising = true;
+            from = 0;
+        }
+
+        if self.rising {
+            // The rise ends at the apex. If the skew moves the apex toward the
+            // current time faster than the wave can follow without getting
+            // more than twice as steep as a steady wave with the same apex, it
+            // keeps rising past the apex instead, but leaves at least half of
+            // what's left of the cycle for the fall.
+            let min_remaining = ((apex as u64 * (MAX_LEVEL - self.level) as u64) >> 33) as u32;
+            let remaining = u32::max(
+                apex.saturating_sub(from),
+                u32::min(min_remaining, (u32::MAX - from) / 2),
+            );
+            if to - from < remaining {
+                self.level = approach(self.level, MAX_LEVEL, to - from, remaining);
+                return rollover;
+            }
+            // Reached the top during this step
+            self.level = MAX_LEVEL;
+            from += remaining;
+            self.rising = false;
+        }
+
+        self.level = approach(self.level, 0, to - from, u32::MAX - from);
+        rollover
+    }
+
+    /**
+    Picks the next value for the sample-and-hold. `texture` sets how far it
+    can move from the last one, from 1/16th of the way to a new random value
+    up to a completely new random value.
+    */
+    fn step_held_value(&mut self, texture: u16) {
+        let target = self.rng.next();
+        let amount = 64 + ((texture as i32 * 15) >> 4);
+        let delta = target as i32 - self.held_value as i32;
+        self.held_value = (self.held_value as i32 + ((delta * amount) >> 10)) as u16;
+    }
+}
+
+/**
+Computes value * numerator / denominator, where numerator < denominator,
+without a 64 bit division. The quotient keeps at least 15 significant bits.
+*/
+fn scale(value: u32, numerator: u32, denominator: u32) -> u32 {
+    debug_assert!(numerator < denominator);
+    if numerator == 0 {
+        return 0;
+    }
+    // Shift the numerator up as far as it will go and keep the 16 most
+    // significant bits of the denominator
+    let up = numerator.leading_zeros();
+    let down = (32 - denominator.leading_zeros()).saturating_sub(16);
+    let quotient = (numerator << up) / (denominator >> down);
+    // Dropping the low bits of the denominator rounds the quotient up, which
+    // can take the result just past `value`, and past u32::MAX with it
+    let result = (value as u64 * quotient as u64) >> (up + down);
+    u64::min(result, value as u64) as u32
+}
+
+/**
+Moves `level` toward `target` in a straight line that will reach the target
+after `remaining` more time, i.e. by `covered / remaining` of the distance
+between them. Returns the target if `covered` is at least `remaining`.
+*/
+fn approach(level: u32, target: u32, covered: u32, remaining: u32) -> u32 {
+    if covered >= remaining {
+        return target;
+    }
+    let step = scale(level.abs_diff(target), covered, remaining);
+    if target > level {
+        level + step
+    } else {
+        level - step
+    }
+}
+
+fn lut_load_u16(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U16_BYTES]>) -> u16 {
+    debug_assert!(i < lut.len() / U16_BYTES);
+    let bytes: [u8; U16_BYTES] = lut.load_sub_array::<U16_BYTES>(U16_BYTES * i);
+    u16::from_le_bytes(bytes)
+}
+
+/**
+Maps a level from the triangle wave onto half of a cosine wave, by finding the
+two nearest entries in the lookup table and interpolating between them. A
+symmetrical triangle comes out as a sine wave.
+*/
+fn sine_shape(level: u32) -> u16 {
+    let x = (level >> 16) as u16;
+    let idx_low = (x >> 8) as usize;
+    let remainder = (x & 0xFF) as u32;
+    let v_low = lut_load_u16(idx_low, &SINE_LUT) as u32;
+    let v_high = if idx_low + 1 < LUT_SIZE {
+        lut_load_u16(idx_low + 1, &SINE_LUT) as u32
+    } else {
+        u16::MAX as u32
+    };
+    ((v_low * (256 - remainder) + v_high * remainder) >> 8) as u16
+}
+
+/**
+Maps a level from the triangle wave onto an exponential curve,
+(2^(kx) - 1) / (2^k - 1), so it rises slowly and then quickly, and falls
+quickly and then slowly
+*/
+fn exponential_shape(level: u32) -> u16 {
+    const OCTAVES: u32 = 6;
+    // exp2_lut takes the exponent divided by 16
+    let exponent = FixedU16::<U16>::from_bits((((level >> 16) * OCTAVES) >> 4) as u16);
+    let power = exp2_lut(exponent).to_bits();
+    let result = (power - (1 << 16)) / ((1 << OCTAVES) - 1);
+    u32::min(result, u16::MAX as u32) as u16
+}
+
+impl LfoModuleState {
+    /**
+    Advances the wave by `dt` and shapes it
+    */
+    fn step_shape(&mut self, dt: u32, cv: &[u16; 4]) -> u16 {
+        let texture = u16::min(1023, cv[3] + cv[1]);
+        let apex = (texture as u32) << 22;
+
+        let rollover = self.step_triangle(dt, apex);
+
+        match self.shape {
+            LfoShape::Triangle => (self.level >> 20) as u16,
+            LfoShape::Sine => sine_shape(self.level) >> 4,
+            LfoShape::Exponential => exponential_shape(self.level) >> 4,
+            // High from the start of each cycle until the apex
+            LfoShape::Square => {
+                if self.rising {
+                    0xFFF
+                } else {
+                    0
+                }
+            }
+            LfoShape::SampleAndHold => {
+                if rollover {
+                    self.step_held_value(texture);
+                }
+                self.held_value >> 4
+            }
         }
-        (before_rollover, rollover)
     }
 }
 
 impl DriftModule for LfoModuleState {
     fn step(&mut self, cv: &[u16; 4]) -> u16 {
-        let (t, rollover) = self.step_time(cv[2], cv[0]);
+        let dt = get_delta_t(cv[2], cv[0], 0);
+        self.step_shape(dt, cv)
+    }
 
-        if rollover {
-            // TODO this isn't great
-            // This only updates the shape of the wave between cycles.
-            // Ideally, the shape would be continuously adjustable for FM and
-            // better user feedback. But you can't do that in closed form without
-            // getting discontinuities when you sweep skew, and when I tried to
-            // do it numerically it messed with the overall cycle time at high freq
-            // due to integer precision issues. This is definitely solvable but
-            // just doing this as a simple stopgap for now
-            self.apex = (u16::min(1023, cv[3] + cv[1]) as u32) << 22;
-        }
+    /**
+    Runs at the measured clock rate, and each clock lines the start of the
+    cycle back up with the clock.
 
-        let value = if t < self.apex {
-            let completed = FixedU32::<U16>::from_bits(t >> 16);
-            let out_of = FixedU32::<U16>::from_bits(self.apex >> 16);
-            (completed / out_of).to_bits() as u16
+    Moving the phase counter straight to the start of the cycle would cut the
+    current segment short and jump the output, so instead the wave runs faster
+    (up to twice the speed) after a clock that came before the end of the
+    cycle, and slower (down to half the speed) after one that came late, until
+    it has made up the difference. The segments just get shorter or longer,
+    and each one still ends at its target level.
+    */
+    fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16 {
+        if clock.edge {
+            // The cycle should start over right now, so whatever is left of
+            // it is how far behind the wave is, and however far into the
+            // next one it already got is how far ahead it is
+            self.phase_error = (self.time as i32).wrapping_neg();
+        }
+        let dt = if self.phase_error >= 0 {
+            let extra = u32::min(self.phase_error as u32, clock.delta_t);
+            self.phase_error -= extra as i32;
+            clock.delta_t + extra
         } else {
-            let completed = FixedU32::<U16>::from_bits((t - self.apex) >> 16);
-            let out_of = FixedU32::<U16>::from_bits((u32::MAX - self.apex) >> 16);
-            let y = u32::min((completed / out_of).to_bits(), u16::MAX as u32) as u16;
-            u16::MAX - y
+            let less = u32::min(self.phase_error.unsigned_abs(), clock.delta_t / 2);
+            self.phase_error += less as i32;
+            clock.delta_t - less
         };
-
-        value >> 4
+        self.step_shape(dt, cv)
     }
 }
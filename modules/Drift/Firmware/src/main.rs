@@ -8,16 +8,21 @@
 
 mod bezier;
 mod brownian;
+mod clock;
 mod drift;
 mod lfo;
 mod perlin;
 mod random;
+mod settings;
+mod setup;
 mod shared;
 
 use arduino_hal::adc::channel;
 use avr_progmem::progmem;
 use core::cell::Cell;
-use drift::DriftAlgorithm;
+use drift::{ChannelB, Drift};
+use settings::{Settings, SETTINGS_SIZE};
+use setup::{setup_requested, Setup};
 
 use avr_device::interrupt::{self, Mutex};
 use fm_lib::asynchronous::{assert_interrupts_disabled, AtomicRead, Borrowable};
@@ -25,7 +30,8 @@ use fm_lib::{
     async_adc::{
         handle_conversion_result, init_async_adc, new_async_adc_state, AsyncAdc, GetAdcValues,
     },
-    mcp4922::{DacChannel, MCP4922},
+    eeprom::WearLevelledEepromWriter,
+    mcp4922::{command_bytes, DacChannel, MCP4922},
 };
 
 static GLOBAL_ASYNC_ADC_STATE: AsyncAdc<4> = new_async_adc_state();
@@ -36,6 +42,8 @@ fn ADC() {
 }
 
 static DAC_WRITE_QUEUED: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+/// Sent by the timer interrupt right after it latches channel A
+static CHANNEL_B_QUEUED: Mutex<Cell<Option<[u8; 2]>>> = Mutex::new(Cell::new(None));
 
 #[cfg(feature = "debug")]
 static DEBUG_SKIPPED_WRITE_COUNT: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
@@ -54,12 +62,15 @@ fn main() -> ! {
     let a4 = pins.a4.into_analog_input(&mut adc);
     let a5 = pins.a5.into_analog_input(&mut adc);
 
+    let speed_knob = adc.read_blocking(&channel::ADC6);
+    let texture_knob = adc.read_blocking(&channel::ADC7);
+
     let random_seed = ((a0.analog_read(&mut adc) & 7) << 13)
         | ((a1.analog_read(&mut adc) & 7) << 10)
         | ((a2.analog_read(&mut adc) & 7) << 7)
         | ((a3.analog_read(&mut adc) & 7) << 4)
-        | ((adc.read_blocking(&channel::ADC6) & 1) << 3)
-        | ((adc.read_blocking(&channel::ADC7) & 1) << 2)
+        | ((speed_knob & 1) << 3)
+        | ((texture_knob & 1) << 2)
         | ((adc.read_blocking(&channel::Temperature) & 1) << 1)
         | ((adc.read_blocking(&channel::Vbg) & 1) << 0);
 
@@ -105,12 +116,30 @@ fn main() -> ! {
         ],
     );
 
+    // Only used if the EEPROM is empty
+    let mut eeprom_data = Settings::DEFAULT.to_bytes();
+    let mut eeprom = WearLevelledEepromWriter::<SETTINGS_SIZE>::init_and_advance(
+        dp.EEPROM,
+        &mut eeprom_data,
+        false,
+    );
+    let mut settings = Settings::from_bytes(&eeprom_data);
+
     let config = [config_pin_1.is_low(), config_pin_2.is_low()];
-    let mut algorithm = DriftAlgorithm::new(config, random_seed);
+
+    let mut setup = if setup_requested(speed_knob, texture_knob) {
+        Some(Setup::new(settings, config, speed_knob))
+    } else {
+        None
+    };
+
+    let mut drift = Drift::new(config, random_seed, settings);
 
     configure_timer_interrupt(&dp.TC0);
     let mut dac = MCP4922::new(d10);
-    dac.shutdown_channel(&mut spi, DacChannel::ChannelB);
+    if settings.channel_b == ChannelB::Off {
+        dac.shutdown_channel(&mut spi, DacChannel::ChannelB);
+    }
 
     let _ = pins.d3.into_output();
     configure_timer_for_pwm(&dp.TC2);
@@ -119,7 +148,39 @@ fn main() -> ! {
         let cv = interrupt::free(|cs| GLOBAL_ASYNC_ADC_STATE.get_inner(cs).get_all());
 
         if !DAC_WRITE_QUEUED.atomic_read() {
-            let value = algorithm.step(&cv);
+            // Channel B holds still in setup mode
+            let mut value_b = None;
+            let value = match setup {
+                Some(ref mut setup_state) => match setup_state.step(&cv) {
+                    Some(preview) => preview,
+                    None => {
+                        // Leaving setup: save anything that changed and
+                        // start over with the new settings, fading in from
+                        // the preview
+                        let new_settings = setup_state.settings();
+                        let old_bytes = settings.to_bytes();
+                        for (i, byte) in new_settings.to_bytes().into_iter().enumerate() {
+                            if byte != old_bytes[i] {
+                                eeprom.update_byte(i as u16, byte);
+                            }
+                        }
+                        settings = new_settings;
+                        if settings.channel_b == ChannelB::Off {
+                            dac.shutdown_channel(&mut spi, DacChannel::ChannelB);
+                        }
+                        drift.apply_settings(settings, setup_state.last_value());
+                        setup = None;
+                        let value = drift.step(&cv);
+                        value_b = drift.output_b();
+                        value
+                    }
+                },
+                None => {
+                    let value = drift.step(&cv);
+                    value_b = drift.output_b();
+                    value
+                }
+            };
 
             // Update LED PWM
             let pwm_duty = GAMMA_CORRECTION.load_at((value >> 4) as usize);
@@ -127,7 +188,12 @@ fn main() -> ! {
 
             // Queue new value for DAC write
             dac.write_keep_cs_pin_low(&mut spi, DacChannel::ChannelA, value, &Default::default());
-            DAC_WRITE_QUEUED.atomic_write(true);
+            let bytes_b = value_b
+                .map(|value| command_bytes(DacChannel::ChannelB, value, &Default::default()));
+            interrupt::free(|cs| {
+                CHANNEL_B_QUEUED.borrow(cs).set(bytes_b);
+                DAC_WRITE_QUEUED.borrow(cs).set(true);
+            });
         }
 
         #[cfg(feature = "debug")]
@@ -172,8 +238,9 @@ fn configure_timer_interrupt(tc0: &arduino_hal::pac::TC0) {
     tc0.tccr0a.write(|w| w.wgm0().ctc());
     // set timer frequency to cycle at 2.5kHz
     // (16MHz clock speed / 64 prescale factor / 100 count/reset )
+    // The counter resets after reaching OCR0A, so it counts OCR0A + 1 steps
     tc0.tccr0b.write(|w| w.cs0().prescale_64());
-    tc0.ocr0a.write(|w| w.bits(100));
+    tc0.ocr0a.write(|w| w.bits(99));
 
     // enable interrupt on match to compare register A
     tc0.timsk0.write(|w| w.ocie0a().set_bit());
@@ -189,6 +256,10 @@ fn TIMER0_COMPA() {
             dp.PORTB
                 .portb
                 .modify(|r, w| unsafe { w.bits(r.bits()) }.pb2().set_bit());
+            // Channel A is latched, so send channel B and latch it right after
+            if let Some(bytes) = CHANNEL_B_QUEUED.borrow(cs).take() {
+                write_dac_from_interrupt(&dp, bytes);
+            }
         } else {
             #[cfg(feature = "debug")]
             DEBUG_SKIPPED_WRITE_COUNT.borrow(cs).update(|x| x + 1);
@@ -196,6 +267,27 @@ fn TIMER0_COMPA() {
     });
 }
 
+/**
+Sends a command to the DAC and latches it, using the SPI registers directly
+since the SPI driver belongs to the main loop. This is only safe while a write
+is queued, because the main loop doesn't touch SPI until the queued write is
+done.
+*/
+fn write_dac_from_interrupt(dp: &arduino_hal::Peripherals, bytes: [u8; 2]) {
+    dp.PORTB
+        .portb
+        .modify(|r, w| unsafe { w.bits(r.bits()) }.pb2().clear_bit());
+    for byte in bytes {
+        dp.SPI.spdr.write(|w| unsafe { w.bits(byte) });
+        while dp.SPI.spsr.read().spif().bit_is_clear() {}
+        // Reading the data register clears the transfer complete flag
+        let _ = dp.SPI.spdr.read();
+    }
+    dp.PORTB
+        .portb
+        .modify(|r, w| unsafe { w.bits(r.bits()) }.pb2().set_bit());
+}
+
 progmem! {
-    static progmem GAMMA_CORRECTION: [u8; 256] = *include_bytes!("../luts/gamma_lut.bin");
+    static progmem GAMMA_CORRECTION: [u8; 256] = *include_bytes!(concat!(env!("OUT_DIR"), "/gamma_lut.bin"));
 }
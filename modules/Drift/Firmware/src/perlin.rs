@@ -1,7 +1,10 @@
 use fixed::types::{I1F15, U0F16};
 use fm_lib::rng::ParallelLfsr;
 
-use crate::shared::{get_delta_t, DriftModule};
+use crate::{
+    clock::Clock,
+    shared::{get_delta_t, DriftModule},
+};
 
 struct PerlinOctave {
     time: u32,
@@ -22,57 +25,165 @@ impl PerlinOctave {
 
         perlin_segment(time_fixed, self.last_grad, self.next_grad)
     }
+
+    /**
+    Starts a new segment now. Every segment starts and ends at 0, so this
+    doesn't jump if the current segment was almost over.
+    */
+    fn restart(&mut self, rng: &mut ParallelLfsr) {
+        self.time = 0;
+        self.last_grad = self.next_grad;
+        self.next_grad = random_grad(rng);
+    }
 }
 
+pub const MAX_OCTAVES: u8 = 6;
+
 pub struct PerlinModuleState {
-    base: PerlinOctave,
-    octave: PerlinOctave,
+    octaves: [PerlinOctave; MAX_OCTAVES as usize],
+    num_octaves: u8,
+    /// Mixes the base octave with one 4 times as fast, like the original
+    /// firmware, instead of doubling the speed for each octave
+    original_mix: bool,
+    /// The roughness the weights were last computed for
+    roughness: u16,
+    /// How much of each octave goes into the output, as (0.15) fixed point
+    /// fractions that add up to 1
+    weights: [u16; MAX_OCTAVES as usize],
     rng: ParallelLfsr,
 }
 
 impl PerlinModuleState {
-    pub fn new(random_seed: u16) -> Self {
+    /**
+    Each octave runs at twice the speed of the one before it. `num_octaves`
+    must be between 1 and `MAX_OCTAVES`, or None for the original mix: the base
+    octave and one 4 times as fast, which the roughness brings in up to a
+    quarter of the output.
+    */
+    pub fn new(random_seed: u16, num_octaves: Option<u8>) -> Self {
+        debug_assert!(matches!(num_octaves, None | Some(1..=MAX_OCTAVES)));
+        let original_mix = num_octaves.is_none();
+        let num_octaves = match num_octaves {
+            Some(n) => n.clamp(1, MAX_OCTAVES),
+            None => 2,
+        };
+
+        // Only the octaves in use take random numbers, so the original mix
+        // gets the same gradients as the original firmware
         let mut rng = ParallelLfsr::new(random_seed);
-        let grad1 = random_grad(&mut rng);
-        let grad2 = random_grad(&mut rng);
-        let grad3 = random_grad(&mut rng);
-        let grad4 = random_grad(&mut rng);
-
-        Self {
-            base: PerlinOctave {
-                time: 0,
-                last_grad: grad1,
-                next_grad: grad2,
-            },
-            octave: PerlinOctave {
-                time: 0,
-                last_grad: grad3,
-                next_grad: grad4,
-            },
+        let octaves = core::array::from_fn(|i| {
+            if i < num_octaves as usize {
+                PerlinOctave {
+                    time: 0,
+                    last_grad: random_grad(&mut rng),
+                    next_grad: random_grad(&mut rng),
+                }
+            } else {
+                PerlinOctave {
+                    time: 0,
+                    last_grad: I1F15::ZERO,
+                    next_grad: I1F15::ZERO,
+                }
+            }
+        });
+
+        let mut state = Self {
+            octaves,
+            num_octaves,
+            original_mix,
+            roughness: 0,
+            weights: [0; MAX_OCTAVES as usize],
             rng,
+        };
+        state.compute_weights(0);
+        state
+    }
+
+    /**
+    Sets how loud each octave is. `roughness` (0-1023) is how loud each octave
+    is compared to the one below it, so at 0 there's only the base octave, and
+    all the way up they're all the same. In the original mix, it is how much
+    of the fast octave goes in, up to a quarter.
+    */
+    fn compute_weights(&mut self, roughness: u16) {
+        self.roughness = roughness;
+        if self.original_mix {
+            self.weights[1] = roughness << 3;
+            self.weights[0] = (1 << 15) - self.weights[1];
+            return;
+        }
+
+        let n = self.num_octaves as usize;
+        let ratio = (roughness as u32) << 5;
+        let mut amplitude: u32 = 1 << 15;
+        let mut sum: u32 = 0;
+        for weight in &mut self.weights[..n] {
+            *weight = amplitude as u16;
+            sum += amplitude;
+            amplitude = (amplitude * ratio) >> 15;
+        }
+        // Scale the weights so they add up to 1, with one division instead
+        // of one per octave
+        let reciprocal = (1 << 30) / sum;
+        for weight in &mut self.weights[..n] {
+            *weight = ((*weight as u32 * reciprocal) >> 15) as u16;
         }
     }
+
+    /**
+    Advances every octave and mixes them together. If `hold_base` is set, the
+    base octave waits at the end of its segment instead of starting a new one.
+    */
+    fn step_octaves(&mut self, dt: u32, hold_base: bool, cv: &[u16; 4]) -> u16 {
+        let roughness = u16::min(1023, cv[3] + cv[1]);
+        if roughness != self.roughness {
+            self.compute_weights(roughness);
+        }
+
+        let mut total: i32 = 0;
+        for i in 0..self.num_octaves as usize {
+            let octave_dt = if i == 0 && hold_base {
+                u32::min(dt, u32::MAX - self.octaves[0].time)
+            } else {
+                // Wrapping is fine for the highest octaves at the highest
+                // speeds, which are just noise at that point
+                dt << if self.original_mix { 2 * i } else { i }
+            };
+            let value = self.octaves[i].step(&mut self.rng, octave_dt);
+            debug_assert!(value <= 0.25);
+            debug_assert!(value >= -0.25);
+            total += self.weights[i] as i32 * value.to_bits() as i32;
+        }
+
+        // Each octave is within +/-0.25 and the weights add up to 1, so
+        // multiplying by 4 fills the whole output range
+        let value = total >> 13;
+        let scaled = (value >> 4) + (1 << 11);
+        // Only the very top of the range can round up to 4096. The host tests
+        // rely on this to check the mix itself, which the clamp would hide.
+        debug_assert!((0..=4096).contains(&scaled));
+        u16::min(scaled as u16, 4095)
+    }
 }
 
 impl DriftModule for PerlinModuleState {
     fn step(&mut self, cv: &[u16; 4]) -> u16 {
         let dt = get_delta_t(cv[2], cv[0], 0);
+        self.step_octaves(dt, false, cv)
+    }
 
-        let base_value = self.base.step(&mut self.rng, dt);
-        let octave_value = self.octave.step(&mut self.rng, dt * 4);
-
-        debug_assert!(base_value <= 0.25);
-        debug_assert!(base_value >= -0.25);
-        debug_assert!(octave_value <= 0.25);
-        debug_assert!(octave_value >= -0.25);
-
-        let blend = I1F15::from_bits((u16::min(1023, cv[3] + cv[1]) << 5) as i16);
-        const ONE: I1F15 = I1F15::from_bits(0x7FFF);
-        let value = base_value * 3 + base_value * (ONE - blend) + octave_value * blend;
-
-        let scaled = (value.to_bits() / 16) + (1 << 11);
-        // TODO clamp might not be necessary
-        scaled.clamp(0, 4095) as u16
+    /**
+    Each clock starts a new segment of the base octave, with a new random
+    gradient, and restarts the other octaves to line up with it. If the next
+    clock is late, the base octave waits at the end of its segment for it.
+    */
+    fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16 {
+        if clock.edge {
+            for octave in &mut self.octaves[..self.num_octaves as usize] {
+                octave.restart(&mut self.rng);
+            }
+        }
+        self.step_octaves(clock.delta_t, true, cv)
     }
 }
 
@@ -99,9 +210,17 @@ fn fade(t: U0F16) -> U0F16 {
 }
 
 fn random_grad(rng: &mut ParallelLfsr) -> I1F15 {
-    let h = rng.next() & 15;
+    gradient(rng.next())
+}
+
+/**
+Picks one of 16 gradients, from -0.5 to +0.5 without 0, using the low 4 bits of
+`hash`
+*/
+pub fn gradient(hash: u16) -> I1F15 {
+    let h = hash & 15;
     let grad_int = 1 + (h & 7);
-    let grad = I1F15::from_bits(((grad_int as u16) << 11) as i16);
+    let grad = I1F15::from_bits((grad_int << 11) as i16);
     if (h & 8) != 0 {
         -grad
     } else {
@@ -119,7 +238,7 @@ Instead of interpolating between random values, perlin noise effectively interpo
 between different gradients/slopes for a much smoother and more organic looking curve.
 Returns a fixed point between -.25 and +.25
 */
-fn perlin_segment(x: U0F16, last_grad: I1F15, next_grad: I1F15) -> I1F15 {
+pub fn perlin_segment(x: U0F16, last_grad: I1F15, next_grad: I1F15) -> I1F15 {
     const ONE: I1F15 = I1F15::from_bits(0x7FFF);
 
     let u = fade(x);
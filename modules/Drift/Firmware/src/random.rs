@@ -4,34 +4,91 @@ use fm_lib::rng::ParallelLfsr;
 
 const LUT_SIZE: usize = 256;
 const I16_BYTES: usize = i16::BITS as usize / 8;
+const ICDF_LUT_BYTES: usize = LUT_SIZE * I16_BYTES;
 
 progmem! {
-    pub static progmem ICDF_LUT: [u8; LUT_SIZE * I16_BYTES] = *include_bytes!("../luts/icdf_lut.bin");
+    static progmem TRIANGLE_ICDF_LUT: [u8; ICDF_LUT_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/icdf_triangle.bin"));
+    static progmem GAUSSIAN_ICDF_LUT: [u8; ICDF_LUT_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/icdf_gaussian.bin"));
+    static progmem CAUCHY_ICDF_LUT: [u8; ICDF_LUT_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/icdf_cauchy.bin"));
+    static progmem BIMODAL_ICDF_LUT: [u8; ICDF_LUT_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/icdf_bimodal.bin"));
 }
 
-fn lut_load_i16(i: usize, lut: &ProgMem<[u8; LUT_SIZE * I16_BYTES]>) -> FixedI16<U15> {
+/**
+The shapes the random values can be drawn from, all between -1 and 1. The
+tables and their parameters are in `lut-generator`.
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Distribution {
+    Uniform,
+    /// Most likely near 0, falling off in a straight line toward +/-1
+    Triangle,
+    /// A bell curve, with 68% of values within +/-1/3
+    Gaussian,
+    /// Half of the values within +/-0.1, but with long tails that make the
+    /// occasional big jump
+    Cauchy,
+    /// Two bell curves, around -0.6 and +0.6
+    Bimodal,
+}
+
+pub const DISTRIBUTIONS: [Distribution; 5] = [
+    Distribution::Uniform,
+    Distribution::Triangle,
+    Distribution::Gaussian,
+    Distribution::Cauchy,
+    Distribution::Bimodal,
+];
+
+impl Distribution {
+    pub fn index(self) -> u8 {
+        match self {
+            Distribution::Uniform => 0,
+            Distribution::Triangle => 1,
+            Distribution::Gaussian => 2,
+            Distribution::Cauchy => 3,
+            Distribution::Bimodal => 4,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        DISTRIBUTIONS.get(index as usize).copied()
+    }
+}
+
+fn lut_load_i16(i: usize, lut: &ProgMem<[u8; ICDF_LUT_BYTES]>) -> FixedI16<U15> {
     debug_assert!(i < lut.len() / I16_BYTES);
     let bytes: [u8; I16_BYTES] = lut.load_sub_array::<I16_BYTES>(I16_BYTES * i);
     FixedI16::<U15>::from_bits(i16::from_le_bytes(bytes))
 }
 
-fn icdf(u: u16) -> FixedI16<U15> {
+fn icdf(u: u16, lut: &ProgMem<[u8; ICDF_LUT_BYTES]>) -> FixedI16<U15> {
     debug_assert!(u <= i16::MAX as u16);
     let idx_low = u >> 7;
     let idx_high = u16::min(LUT_SIZE as u16 - 1, idx_low + 1);
 
     let remainder = FixedI16::<U15>::from_bits(((u << 8) & 0x7FFF) as i16);
 
-    let v_low = lut_load_i16(idx_low as usize, &ICDF_LUT);
-    let v_high = lut_load_i16(idx_high as usize, &ICDF_LUT);
+    let v_low = lut_load_i16(idx_low as usize, lut);
+    let v_high = lut_load_i16(idx_high as usize, lut);
     remainder.lerp(v_low, v_high)
 }
 
 /**
-Returns a random value between -1 and 1 from a distribution given by the
-Inverse CDF lookup table. Right now, that is a triangular distribution but
-it could be Gaussian in the future
+Returns a random value between -1 and 1 from the given distribution, by
+looking up a uniformly distributed random number in that distribution's
+inverse CDF table
 */
-pub fn random_from_distribution(rng: &mut ParallelLfsr) -> FixedI16<U15> {
-    icdf(rng.next() & 0x7FFF)
+pub fn random_from_distribution(
+    rng: &mut ParallelLfsr,
+    distribution: Distribution,
+) -> FixedI16<U15> {
+    let lut = match distribution {
+        // Doesn't need a table
+        Distribution::Uniform => return FixedI16::<U15>::from_bits(rng.next() as i16),
+        Distribution::Triangle => &TRIANGLE_ICDF_LUT,
+        Distribution::Gaussian => &GAUSSIAN_ICDF_LUT,
+        Distribution::Cauchy => &CAUCHY_ICDF_LUT,
+        Distribution::Bimodal => &BIMODAL_ICDF_LUT,
+    };
+    icdf(rng.next() & 0x7FFF, lut)
 }
@@ -0,0 +1,128 @@
+use crate::{
+    drift::{AlgorithmKind, ChannelB, SpeedCv, TextureCv},
+    lfo::LfoShape,
+    perlin::MAX_OCTAVES,
+    random::Distribution,
+};
+
+pub const SETTINGS_SIZE: usize = 8;
+
+const LFO_SHAPE_OFFSET: usize = 0;
+const ALGORITHM_OFFSET: usize = 1;
+const TEXTURE_CV_OFFSET: usize = 2;
+const SPEED_CV_OFFSET: usize = 3;
+const CHANNEL_B_OFFSET: usize = 4;
+const FIXED_SEED_OFFSET: usize = 5;
+const PERLIN_OCTAVES_OFFSET: usize = 6;
+const DISTRIBUTION_OFFSET: usize = 7;
+
+/**
+The seeds that can be picked in setup mode instead of a random one. The values
+don't matter, as long as they never change.
+*/
+pub const FIXED_SEEDS: [u16; 8] = [
+    0xACE1, 0x1D87, 0x5EED, 0xB00F, 0x2F6B, 0x93C4, 0x6A09, 0xE667,
+];
+
+/**
+Everything that can be changed in setup mode. Stored in EEPROM.
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub lfo_shape: LfoShape,
+    /// Overrides the DIP switches, unless it is None
+    pub algorithm: Option<AlgorithmKind>,
+    pub texture_cv: TextureCv,
+    pub speed_cv: SpeedCv,
+    pub channel_b: ChannelB,
+    /// An index into `FIXED_SEEDS`, or None to use a random seed
+    pub fixed_seed: Option<u8>,
+    /// From 1 to `MAX_OCTAVES`, or None for the original mix of two octaves
+    pub perlin_octaves: Option<u8>,
+    /// Shapes the random steps of the Brownian and Bezier algorithms, unless it
+    /// is None
+    pub distribution: Option<Distribution>,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        lfo_shape: LfoShape::Triangle,
+        algorithm: None,
+        texture_cv: TextureCv::Texture,
+        speed_cv: SpeedCv::Speed,
+        channel_b: ChannelB::Off,
+        fixed_seed: None,
+        perlin_octaves: None,
+        distribution: None,
+    };
+
+    /**
+    The seed to start the algorithms from, which is only random if no fixed
+    seed is picked
+    */
+    pub fn seed(&self, random_seed: u16) -> u16 {
+        match self.fixed_seed {
+            Some(i) => FIXED_SEEDS[i as usize],
+            None => random_seed,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0u8; SETTINGS_SIZE];
+        bytes[LFO_SHAPE_OFFSET] = self.lfo_shape.index();
+        // 0 means "use the switches", so the algorithms start at 1
+        bytes[ALGORITHM_OFFSET] = match self.algorithm {
+            None => 0,
+            Some(kind) => kind.index() + 1,
+        };
+        bytes[TEXTURE_CV_OFFSET] = self.texture_cv.index();
+        bytes[SPEED_CV_OFFSET] = self.speed_cv.index();
+        bytes[CHANNEL_B_OFFSET] = self.channel_b.index();
+        // 0 means "random", so the fixed seeds start at 1
+        bytes[FIXED_SEED_OFFSET] = match self.fixed_seed {
+            None => 0,
+            Some(i) => i + 1,
+        };
+        // 0 means the original mix, so the octave counts are stored as is
+        bytes[PERLIN_OCTAVES_OFFSET] = self.perlin_octaves.unwrap_or(0);
+        // 0 means "none", so the distributions start at 1
+        bytes[DISTRIBUTION_OFFSET] = match self.distribution {
+            None => 0,
+            Some(distribution) => distribution.index() + 1,
+        };
+        bytes
+    }
+
+    /**
+    Falls back to the default for any setting with an unrecognized value
+    */
+    pub fn from_bytes(bytes: &[u8; SETTINGS_SIZE]) -> Self {
+        Self {
+            lfo_shape: LfoShape::from_index(bytes[LFO_SHAPE_OFFSET])
+                .unwrap_or(Self::DEFAULT.lfo_shape),
+            algorithm: match bytes[ALGORITHM_OFFSET] {
+                0 => None,
+                i => AlgorithmKind::from_index(i - 1).or(Self::DEFAULT.algorithm),
+            },
+            texture_cv: TextureCv::from_index(bytes[TEXTURE_CV_OFFSET])
+                .unwrap_or(Self::DEFAULT.texture_cv),
+            speed_cv: SpeedCv::from_index(bytes[SPEED_CV_OFFSET]).unwrap_or(Self::DEFAULT.speed_cv),
+            channel_b: ChannelB::from_index(bytes[CHANNEL_B_OFFSET])
+                .unwrap_or(Self::DEFAULT.channel_b),
+            fixed_seed: match bytes[FIXED_SEED_OFFSET] {
+                0 => None,
+                i if (i as usize) <= FIXED_SEEDS.len() => Some(i - 1),
+                _ => Self::DEFAULT.fixed_seed,
+            },
+            perlin_octaves: match bytes[PERLIN_OCTAVES_OFFSET] {
+                0 => None,
+                n @ 1..=MAX_OCTAVES => Some(n),
+                _ => Self::DEFAULT.perlin_octaves,
+            },
+            distribution: match bytes[DISTRIBUTION_OFFSET] {
+                0 => None,
+                i => Distribution::from_index(i - 1).or(Self::DEFAULT.distribution),
+            },
+        }
+    }
+}
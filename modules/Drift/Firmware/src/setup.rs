@@ -0,0 +1,296 @@
+use crate::{
+    drift::{
+        AlgorithmKind, Crossfade, DriftAlgorithm, CHANNEL_B_MODES, SPEED_CV_MODES, TEXTURE_CV_MODES,
+    },
+    lfo::LFO_SHAPES,
+    perlin::MAX_OCTAVES,
+    random::Distribution,
+    settings::{Settings, FIXED_SEEDS},
+};
+
+/**
+How close to the end of its range a knob has to be to count as all the way up
+or down
+*/
+const KNOB_END_ZONE: u16 = 32;
+
+/**
+How far the speed knob has to move before it starts changing the setting, so
+that entering setup or changing pages doesn't immediately overwrite it with
+wherever the knob happened to be
+*/
+const PICKUP_DISTANCE: u16 = 32;
+
+/// Runs the preview at about 1 Hz
+const PREVIEW_SPEED_KNOB: u16 = 454;
+const PREVIEW_TEXTURE_KNOB: u16 = 512;
+const PREVIEW_SEED: u16 = 0xACE1;
+
+/**
+The choices on the algorithm page. None means the DIP switches pick.
+*/
+const ALGORITHM_OPTIONS: [Option<AlgorithmKind>; 5] = [
+    None,
+    Some(AlgorithmKind::Perlin),
+    Some(AlgorithmKind::Brownian),
+    Some(AlgorithmKind::Bezier),
+    Some(AlgorithmKind::Lfo),
+];
+
+/**
+The choices on the seed page. None means a new random seed each time the
+module starts.
+*/
+const SEED_OPTIONS: [Option<u8>; FIXED_SEEDS.len() + 1] = [
+    None,
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(3),
+    Some(4),
+    Some(5),
+    Some(6),
+    Some(7),
+];
+
+/**
+The choices on the distribution page. None means every Brownian step is the
+same size, and Bezier curves pick values evenly from the whole range.
+*/
+const DISTRIBUTION_OPTIONS: [Option<Distribution>; 6] = [
+    None,
+    Some(Distribution::Uniform),
+    Some(Distribution::Triangle),
+    Some(Distribution::Gaussian),
+    Some(Distribution::Cauchy),
+    Some(Distribution::Bimodal),
+];
+
+/**
+The choices on the Perlin octaves page. None means the original mix of the base
+octave and one 4 times as fast.
+*/
+const PERLIN_OCTAVE_OPTIONS: [Option<u8>; MAX_OCTAVES as usize + 1] =
+    [None, Some(1), Some(2), Some(3), Some(4), Some(5), Some(6)];
+
+/**
+Each page changes one setting. The texture knob picks the page, from the bottom
+of its range to the top, so setup starts on the last one.
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SetupPage {
+    Seed,
+    ChannelB,
+    SpeedCv,
+    TextureCv,
+    Algorithm,
+    Distribution,
+    PerlinOctaves,
+    LfoShape,
+}
+
+const PAGES: [SetupPage; 8] = [
+    SetupPage::Seed,
+    SetupPage::ChannelB,
+    SetupPage::SpeedCv,
+    SetupPage::TextureCv,
+    SetupPage::Algorithm,
+    SetupPage::Distribution,
+    SetupPage::PerlinOctaves,
+    SetupPage::LfoShape,
+];
+
+/**
+Setup mode is entered by powering on the module with the speed knob all the way
+down and the texture knob all the way up
+*/
+pub fn setup_requested(speed_knob: u16, texture_knob: u16) -> bool {
+    speed_knob < KNOB_END_ZONE && texture_knob > 1023 - KNOB_END_ZONE
+}
+
+/**
+Changes the settings without needing the jumpers. The texture knob picks a page,
+the speed knob picks the setting on that page, and the output plays a preview
+of it. Turning the texture knob all the way down leaves setup.
+*/
+pub struct Setup {
+    settings: Settings,
+    page: SetupPage,
+    /// The algorithm to preview when the settings leave it up to the switches
+    switches: AlgorithmKind,
+    preview: DriftAlgorithm,
+    crossfade: Crossfade,
+    /// Where the speed knob was when the current page started, until it moves
+    pickup: Option<u16>,
+}
+
+impl Setup {
+    pub fn new(settings: Settings, config: [bool; 2], speed_knob: u16) -> Self {
+        let switches = AlgorithmKind::from_switches(config);
+        Self {
+            settings,
+            page: SetupPage::LfoShape,
+            switches,
+            preview: DriftAlgorithm::new(
+                AlgorithmKind::Lfo,
+                settings.seed(PREVIEW_SEED),
+                &settings,
+            ),
+            crossfade: Crossfade::default(),
+            pickup: Some(speed_knob),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub fn page(&self) -> SetupPage {
+        self.page
+    }
+
+    /**
+    The last value returned by `step`, so the module can fade in from it after
+    leaving setup
+    */
+    pub fn last_value(&self) -> u16 {
+        self.crossfade.last_value()
+    }
+
+    /**
+    Restarts the preview for the current page and setting
+    */
+    fn restart_preview(&mut self) {
+        let kind = match self.page {
+            SetupPage::LfoShape => AlgorithmKind::Lfo,
+            SetupPage::PerlinOctaves => AlgorithmKind::Perlin,
+            SetupPage::Distribution => match self.settings.algorithm.unwrap_or(self.switches) {
+                AlgorithmKind::Brownian => AlgorithmKind::Brownian,
+                // The only other algorithm with a distribution
+                _ => AlgorithmKind::Bezier,
+            },
+            _ => self.settings.algorithm.unwrap_or(self.switches),
+        };
+        let seed = self.settings.seed(PREVIEW_SEED);
+        self.preview = DriftAlgorithm::new(kind, seed, &self.settings);
+        self.crossfade.start();
+    }
+
+    /**
+    Changes the setting on the current page, if it is different. Returns true
+    if anything changed.
+    */
+    fn select(&mut self, speed_knob: u16) -> bool {
+        let settings = &mut self.settings;
+        match self.page {
+            SetupPage::LfoShape => {
+                let shape = knob_zone(speed_knob, &LFO_SHAPES);
+                let changed = shape != settings.lfo_shape;
+                settings.lfo_shape = shape;
+                changed
+            }
+            SetupPage::Algorithm => {
+                let algorithm = knob_zone(speed_knob, &ALGORITHM_OPTIONS);
+                let changed = algorithm != settings.algorithm;
+                settings.algorithm = algorithm;
+                changed
+            }
+            SetupPage::Distribution => {
+                let distribution = knob_zone(speed_knob, &DISTRIBUTION_OPTIONS);
+                let changed = distribution != settings.distribution;
+                settings.distribution = distribution;
+                changed
+            }
+            SetupPage::TextureCv => {
+                let mode = knob_zone(speed_knob, &TEXTURE_CV_MODES);
+                let changed = mode != settings.texture_cv;
+                settings.texture_cv = mode;
+                changed
+            }
+            SetupPage::SpeedCv => {
+                let mode = knob_zone(speed_knob, &SPEED_CV_MODES);
+                let changed = mode != settings.speed_cv;
+                settings.speed_cv = mode;
+                changed
+            }
+            SetupPage::ChannelB => {
+                let mode = knob_zone(speed_knob, &CHANNEL_B_MODES);
+                let changed = mode != settings.channel_b;
+                settings.channel_b = mode;
+                changed
+            }
+            SetupPage::PerlinOctaves => {
+                let octaves = knob_zone(speed_knob, &PERLIN_OCTAVE_OPTIONS);
+                let changed = octaves != settings.perlin_octaves;
+                settings.perlin_octaves = octaves;
+                changed
+            }
+            SetupPage::Seed => {
+                let seed = knob_zone(speed_knob, &SEED_OPTIONS);
+                let changed = seed != settings.fixed_seed;
+                settings.fixed_seed = seed;
+                changed
+            }
+        }
+    }
+
+    /**
+    Advances the preview one time step and returns its output, or None once
+    the user has left setup.
+    */
+    pub fn step(&mut self, cv: &[u16; 4]) -> Option<u16> {
+        let speed_knob = cv[2];
+        let texture_knob = cv[3];
+        if texture_knob < KNOB_END_ZONE {
+            return None;
+        }
+
+        let page = knob_zone(texture_knob, &PAGES);
+        if page != self.page {
+            self.page = page;
+            self.pickup = Some(speed_knob);
+            self.restart_preview();
+        }
+
+        if let Some(start) = self.pickup {
+            if speed_knob.abs_diff(start) > PICKUP_DISTANCE {
+                self.pickup = None;
+            }
+        }
+        if self.pickup.is_none() && self.select(speed_knob) {
+            self.restart_preview();
+        }
+
+        let value = match self.page {
+            SetupPage::TextureCv => {
+                option_level(self.settings.texture_cv.index(), TEXTURE_CV_MODES.len())
+            }
+            SetupPage::SpeedCv => {
+                option_level(self.settings.speed_cv.index(), SPEED_CV_MODES.len())
+            }
+            SetupPage::ChannelB => {
+                option_level(self.settings.channel_b.index(), CHANNEL_B_MODES.len())
+            }
+            _ => self
+                .preview
+                .step(&[0, 0, PREVIEW_SPEED_KNOB, PREVIEW_TEXTURE_KNOB]),
+        };
+        Some(self.crossfade.apply(value))
+    }
+}
+
+/**
+Splits the knob's range into equal zones, one for each option
+*/
+fn knob_zone<T: Copy>(knob: u16, options: &[T]) -> T {
+    let index = (knob as usize * options.len()) >> 10;
+    options[usize::min(index, options.len() - 1)]
+}
+
+/**
+For pages with nothing to preview, shows which option is selected with the
+output level, from 0v for the first to 5v for the last
+*/
+fn option_level(index: u8, num_options: usize) -> u16 {
+    index as u16 * (0xFFF / (num_options as u16 - 1))
+}
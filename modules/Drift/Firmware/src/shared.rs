@@ -1,10 +1,12 @@
 use avr_progmem::{progmem, wrapper::ProgMem};
 use fixed::{types::extra::U16, FixedU16, FixedU32};
 
+use crate::clock::Clock;
+
 const LUT_SIZE: usize = 256;
 const U32_BYTES: usize = u32::BITS as usize / 8;
 progmem! {
-    pub static progmem EXP_LUT: [u8; LUT_SIZE * U32_BYTES] = *include_bytes!("../luts/exp2lut.bin");
+    pub static progmem EXP_LUT: [u8; LUT_SIZE * U32_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/exp2lut.bin"));
 }
 
 fn lut_load_fixed32(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U32_BYTES]>) -> FixedU32<U16> {
@@ -17,7 +19,7 @@ fn lut_load_fixed32(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U32_BYTES]>) -> Fixe
 Returns 2^16x by finding the two nearest entries in the lookup table and
 interpolating between them.
 */
-fn exp2_lut(x: FixedU16<U16>) -> FixedU32<U16> {
+pub fn exp2_lut(x: FixedU16<U16>) -> FixedU32<U16> {
     let idx_low = x.to_bits() >> 8;
     let idx_high = u16::min(255, idx_low + 1);
     let remainder = FixedU32::<U16>::from_bits((x.to_bits() << 8) as u32);
@@ -39,13 +41,6 @@ fn decihertz_from_cv_vpo(raw_adc_value: u16) -> FixedU32<U16> {
     exp2_lut(FixedU16::<U16>::from_bits(raw_adc_value * 20))
 }
 
-fn divided_by(num: u32, denom: FixedU32<U16>) -> u32 {
-    // It seems like there should be a more efficient way to do this
-    // without the 64 bit division
-    let num64: u64 = (num as u64) << 16;
-    (num64 / denom.to_bits() as u64) as u32
-}
-
 /**
 Gets the time constant for the frequency given by the knob and cv inputs.
 - `knob` is the raw ADC reading of the knob position [0,1023] scaled as if it spanned 12v
@@ -54,10 +49,11 @@ Gets the time constant for the frequency given by the knob and cv inputs.
 
 All inputs are summed, clamped to the 0-12v range, and tract 1v/oct.
 The result is a unit-less number to increment the time counter each sample so that
-the 32-bit counter will roll over at the given frequency
+the 32-bit counter will roll over at the given frequency. The counter should wrap
+around rather than saturate, so the leftover time carries into the next cycle
+and the average period is exact.
 */
 pub fn get_delta_t(knob: u16, cv: u16, offset: i16) -> u32 {
-    const MICROS_PER_SECOND: u32 = 1_000_000;
     // knob scaled as if it spanned 12v
     let knob_12v = (knob * 12) / 5;
     const MAX_KNOB_VALUE: u16 = (1023 * 12) / 5;
@@ -65,17 +61,17 @@ pub fn get_delta_t(knob: u16, cv: u16, offset: i16) -> u32 {
     sum = sum.saturating_add_signed(offset / 64);
     sum = u16::min(sum, MAX_KNOB_VALUE);
 
-    let decihertz = decihertz_from_cv_vpo(sum);
-
-    // min freq = 1/40 hz. Max freq = 100Hz
-    const MIN_HERTZ_RECIP: u32 = 40;
-    let micros_per_cycle: u32 = divided_by(MICROS_PER_SECOND * MIN_HERTZ_RECIP, decihertz);
+    // Frequency as a multiple of the min freq, 1/40 hz. Max freq = 100Hz
+    let frequency = decihertz_from_cv_vpo(sum);
+    const MIN_HERTZ_RECIP: u64 = 40;
 
-    // 2.5kHz sample rate == .4 ms / sample
-    const MICROS_PER_SAMPLE: u32 = 400;
+    const SAMPLE_RATE_HERTZ: u64 = 2500;
 
-    let samples_per_cycle = micros_per_cycle / MICROS_PER_SAMPLE;
-    u32::MAX / samples_per_cycle
+    // delta_t = 2^32 * (frequency / 40) / 2500. Since frequency is (16.16) that
+    // is its bits times 2^16 / 100_000, which can be done as a multiplication
+    // by a (0.32) constant instead of a division
+    const SCALE: u64 = (1 << 48) / (MIN_HERTZ_RECIP * SAMPLE_RATE_HERTZ);
+    ((frequency.to_bits() as u64 * SCALE) >> 32) as u32
 }
 
 #[derive(Copy, Clone)]
@@ -90,4 +86,11 @@ pub trait DriftModule {
     Returns a value between 0 and 4095.
     */
     fn step(&mut self, cv: &[u16; 4]) -> u16;
+
+    /**
+    Advance the module one time step, following an external clock instead of
+    the speed knob and CV. `cv[0]` is the clock input, so it is always 0 here.
+    Returns a value between 0 and 4095.
+    */
+    fn step_clocked(&mut self, cv: &[u16; 4], clock: Clock) -> u16;
 }
@@ -0,0 +1,11 @@
+use std::{env, path::PathBuf};
+
+/**
+Generates the lookup tables the firmware sources include, the same way the
+firmware's own build script does
+*/
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    lut_generator::write_luts(&lut_generator::drift::luts(), &out_dir).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,54 @@
+//! Draws random values from each of the firmware's distributions and prints a
+//! histogram of them, to see what each one looks like without a scope.
+//!
+//! Usage: `cargo run --bin histogram -- [samples]`. The default is 100000
+//! samples per distribution.
+
+use fm_lib::rng::ParallelLfsr;
+use host_tests::random::{random_from_distribution, Distribution, DISTRIBUTIONS};
+
+const BINS: usize = 20;
+const BAR_WIDTH: usize = 60;
+const SEED: u16 = 0xACE1;
+
+fn name(distribution: Distribution) -> &'static str {
+    match distribution {
+        Distribution::Uniform => "Uniform",
+        Distribution::Triangle => "Triangle",
+        Distribution::Gaussian => "Gaussian",
+        Distribution::Cauchy => "Cauchy",
+        Distribution::Bimodal => "Bimodal",
+    }
+}
+
+/// How many of `samples` values land in each of `BINS` equal bins from -1 to 1
+fn histogram(distribution: Distribution, samples: usize) -> [usize; BINS] {
+    let mut rng = ParallelLfsr::new(SEED);
+    let mut counts = [0; BINS];
+    for _ in 0..samples {
+        let value = random_from_distribution(&mut rng, distribution).to_num::<f64>();
+        let bin = ((value + 1.0) / 2.0 * BINS as f64) as usize;
+        counts[usize::min(bin, BINS - 1)] += 1;
+    }
+    counts
+}
+
+fn main() {
+    let samples = match std::env::args().nth(1) {
+        Some(arg) => arg.parse().expect("samples should be a number"),
+        None => 100_000,
+    };
+
+    for distribution in DISTRIBUTIONS {
+        let counts = histogram(distribution, samples);
+        let most = *counts.iter().max().unwrap();
+        println!("{}", name(distribution));
+        for (i, &count) in counts.iter().enumerate() {
+            let low = -1.0 + 2.0 * i as f64 / BINS as f64;
+            let width = count * BAR_WIDTH / most;
+            let percent = 100.0 * count as f64 / samples as f64;
+            println!("{low:>5.1} {} {percent:.1}%", "#".repeat(width));
+        }
+        println!();
+    }
+}
@@ -0,0 +1,33 @@
+//! Builds the hardware-independent parts of the Drift firmware for the host
+//! machine so they can be tested with `cargo test`. The firmware crate itself
+//! can only be built for AVR.
+
+#[path = "../../../Firmware/src/clock.rs"]
+pub mod clock;
+
+#[path = "../../../Firmware/src/shared.rs"]
+pub mod shared;
+
+#[path = "../../../Firmware/src/random.rs"]
+pub mod random;
+
+#[path = "../../../Firmware/src/perlin.rs"]
+pub mod perlin;
+
+#[path = "../../../Firmware/src/brownian.rs"]
+pub mod brownian;
+
+#[path = "../../../Firmware/src/bezier.rs"]
+pub mod bezier;
+
+#[path = "../../../Firmware/src/lfo.rs"]
+pub mod lfo;
+
+#[path = "../../../Firmware/src/drift.rs"]
+pub mod drift;
+
+#[path = "../../../Firmware/src/settings.rs"]
+pub mod settings;
+
+#[path = "../../../Firmware/src/setup.rs"]
+pub mod setup;
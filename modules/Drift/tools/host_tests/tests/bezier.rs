@@ -0,0 +1,153 @@
+use fm_lib::rng::ParallelLfsr;
+use host_tests::{
+    bezier::BezierModuleState,
+    clock::Clock,
+    random::Distribution,
+    shared::{get_delta_t, DriftModule},
+};
+
+const SEED: u16 = 0x1234;
+
+/// Texture knob positions for each curve, away from the dead zone in the
+/// middle that turns off the random speed changes
+const REVERSE_CURVE: u16 = 100;
+const SMOOTH_CURVE: u16 = 900;
+
+fn run(speed: u16, texture: u16, samples: usize) -> Vec<u16> {
+    let mut bezier = BezierModuleState::new(SEED, None);
+    (0..samples)
+        .map(|_| bezier.step(&[0, 0, speed, texture]))
+        .collect()
+}
+
+/// The steepest a curve from 0 to 4095 can be, in output steps per sample, if
+/// the random speed change is as fast as it can go. The smooth curve is at most
+/// 1.5x as steep as a straight line, and the reverse curve 2x.
+fn steepest_step(speed: u16, texture: u16) -> f64 {
+    let slope = if texture < 512 { 2.0 } else { 1.5 };
+    // The curve is evaluated at 12 bit time steps
+    let dt = u32::max(get_delta_t(speed, 0, i16::MAX), 1 << 20);
+    slope * 4095.0 * dt as f64 / 2f64.powi(32)
+}
+
+#[test]
+fn each_curve_takes_a_whole_number_of_samples() {
+    // In the texture knob's dead zone the speed never changes, so every curve
+    // lasts until the time counter saturates. Slower curves sit at their end
+    // value for a few samples, which would hide being a sample off.
+    let texture = 512;
+    for speed in [600, 800, 1023] {
+        let dt = get_delta_t(speed, 0, 0);
+        let samples_per_curve = u32::MAX.div_ceil(dt) as usize;
+        let output = run(speed, texture, samples_per_curve * 20);
+
+        // The curves end at the same random values the module draws
+        let mut rng = ParallelLfsr::new(SEED);
+        for curve in 1..=20 {
+            let end = rng.next() >> 4;
+            assert_eq!(
+                output[curve * samples_per_curve - 1],
+                end,
+                "speed {speed}, curve {curve}"
+            );
+        }
+    }
+}
+
+#[test]
+fn output_stays_in_range() {
+    for speed in [0, 500, 1023] {
+        for texture in [0, REVERSE_CURVE, 512, SMOOTH_CURVE, 1023] {
+            let output = run(speed, texture, 50_000);
+            assert!(output.iter().all(|&value| value <= 4095));
+        }
+    }
+}
+
+#[test]
+fn curves_are_continuous() {
+    for speed in [0, 200, 400, 600, 700] {
+        for texture in [REVERSE_CURVE, SMOOTH_CURVE] {
+            let output = run(speed, texture, 100_000);
+            let limit = steepest_step(speed, texture) + 2.0;
+            for (i, pair) in output.windows(2).enumerate() {
+                let step = pair[0].abs_diff(pair[1]);
+                assert!(
+                    step as f64 <= limit,
+                    "speed {speed}, texture {texture}: {} -> {} at {i}, limit {limit}",
+                    pair[0],
+                    pair[1]
+                );
+            }
+        }
+    }
+}
+
+/// How much the output moves in total near the start and end of each segment.
+/// The texture knob should be in the dead zone, so every segment is the same
+/// length.
+fn movement_near_ends(speed: u16, texture: u16) -> u64 {
+    // The time saturates at the end of each segment instead of wrapping
+    let samples_per_segment = u32::MAX.div_ceil(get_delta_t(speed, 0, 0)) as usize;
+    run(speed, texture, 20_000)
+        .windows(2)
+        .enumerate()
+        .filter(|(i, _)| {
+            let phase = (i % samples_per_segment) as f64 / samples_per_segment as f64;
+            !(0.02..0.98).contains(&phase)
+        })
+        .map(|(_, pair)| pair[0].abs_diff(pair[1]) as u64)
+        .sum()
+}
+
+#[test]
+fn smooth_curve_flattens_out_at_each_value() {
+    let smooth = movement_near_ends(600, 512);
+    let reverse = movement_near_ends(600, 511);
+    assert!(smooth * 10 < reverse, "{smooth} vs {reverse}");
+}
+
+#[test]
+fn clock_edges_do_not_jump() {
+    let mut bezier = BezierModuleState::new(SEED, None);
+    let cv = [0, 0, 500, SMOOTH_CURVE];
+    let mut last = 0;
+    for i in 0..50_000 {
+        // Clocks that come early, on time, and late for a 400 sample period
+        let period = [250, 400, 700][(i / 5000) % 3];
+        let clock = Clock {
+            delta_t: u32::MAX / 400,
+            edge: i % period == 0,
+            quadrature_edge: false,
+        };
+        let value = bezier.step_clocked(&cv, clock);
+        if i > 0 {
+            assert!(value.abs_diff(last) <= 25, "{last} -> {value} at {i}");
+        }
+        last = value;
+    }
+}
+
+/// How much of the time the output spends in the middle quarter of its range
+fn time_near_middle(distribution: Option<Distribution>) -> f64 {
+    let mut bezier = BezierModuleState::new(SEED, distribution);
+    let output: Vec<u16> = (0..200_000)
+        .map(|_| bezier.step(&[0, 0, 700, SMOOTH_CURVE]))
+        .collect();
+    let near = output
+        .iter()
+        .filter(|&&value| (1536..2560).contains(&value))
+        .count();
+    near as f64 / output.len() as f64
+}
+
+#[test]
+fn distribution_shapes_where_curves_go() {
+    let even = time_near_middle(None);
+    let uniform = time_near_middle(Some(Distribution::Uniform));
+    let gaussian = time_near_middle(Some(Distribution::Gaussian));
+    let bimodal = time_near_middle(Some(Distribution::Bimodal));
+    assert!((uniform - even).abs() < 0.05, "{even} vs {uniform}");
+    assert!(gaussian > even * 1.5, "{even} vs {gaussian}");
+    assert!(bimodal < even * 0.75, "{even} vs {bimodal}");
+}
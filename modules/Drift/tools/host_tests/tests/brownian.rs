@@ -0,0 +1,184 @@
+use host_tests::{
+    brownian::{BrownianModuleState, CENTERING_MARGIN, CENTERING_STRENGTH},
+    clock::Clock,
+    random::Distribution,
+    shared::DriftModule,
+};
+
+const SEED: u16 = 0x1234;
+
+/// With the texture all the way up the output follows the random walk exactly,
+/// and with the speed all the way up it takes a step almost every sample
+const FAST_AND_UNSMOOTHED: [u16; 4] = [0, 0, 1023, 1023];
+
+/// When clocked, the speed knob sets the size of each step: (31 + 1) << 5,
+/// which is 64 once the output is shifted down to 12 bits
+const CLOCKED_STEPS: [u16; 4] = [0, 0, 31, 1023];
+const CLOCKED_STEP_SIZE: u16 = 64;
+
+fn run(cv: &[u16; 4], samples: usize) -> Vec<u16> {
+    let mut brownian = BrownianModuleState::new(SEED, None);
+    (0..samples).map(|_| brownian.step(cv)).collect()
+}
+
+#[test]
+fn output_stays_in_range() {
+    for speed in [0, 300, 1023] {
+        for texture in [0, 500, 1019, 1023] {
+            let output = run(&[0, 0, speed, texture], 100_000);
+            assert!(output.iter().all(|&value| value <= 4095));
+        }
+    }
+}
+
+#[test]
+fn steps_lean_toward_the_center_near_the_edges() {
+    let output = run(&FAST_AND_UNSMOOTHED, 3_000_000);
+    let margin = 4096 / CENTERING_MARGIN;
+    // (steps up, steps) for the bottom margin, the middle, and the top margin
+    let mut counts = [(0u32, 0u32); 3];
+    for pair in output.windows(2) {
+        let (before, after) = (pair[0], pair[1]);
+        // At the very ends a step past the end doesn't show up
+        if before == after || before == 0 || before >= 4095 - 40 {
+            continue;
+        }
+        let zone = if before < margin {
+            0
+        } else if before > 4095 - margin {
+            2
+        } else {
+            1
+        };
+        counts[zone].1 += 1;
+        if after > before {
+            counts[zone].0 += 1;
+        }
+    }
+
+    // Each random number is the last one shifted by a bit, so the few
+    // numbers that flip a step toward the center mostly come right after a run
+    // of steps away from it. That makes the lean about half as strong as
+    // `CENTERING_STRENGTH` alone would, but it should still be clear.
+    let bias = 1.0 / CENTERING_STRENGTH as f64;
+    let expected = [0.5 + bias / 2.0, 0.5, 0.5 - bias / 2.0];
+    for (zone, (&(up, steps), expected)) in counts.iter().zip(expected).enumerate() {
+        assert!(steps > 100_000, "zone {zone} only had {steps} steps");
+        let up_fraction = up as f64 / steps as f64;
+        assert!(
+            (up_fraction - expected).abs() < bias / 4.0,
+            "zone {zone}: {up_fraction} of steps went up, expected {expected}"
+        );
+    }
+}
+
+#[test]
+fn spends_less_time_near_the_edges() {
+    // A walk that doesn't lean toward the center would spend the same amount
+    // of time in every part of the range
+    let output = run(&FAST_AND_UNSMOOTHED, 3_000_000);
+    let margin = 4096 / CENTERING_MARGIN;
+    let near_edges = output
+        .iter()
+        .filter(|&&value| value < margin || value > 4095 - margin)
+        .count();
+    let fraction = near_edges as f64 / output.len() as f64;
+    let uniform = 2.0 / CENTERING_MARGIN as f64;
+    assert!(
+        fraction < uniform * 0.9,
+        "{fraction} of the time near the edges"
+    );
+}
+
+#[test]
+fn smoothing_follows_the_walk_without_overshooting() {
+    let target = run(&FAST_AND_UNSMOOTHED, 50_000);
+    let smoothed = run(&[0, 0, 1023, 200], 50_000);
+    // The smoothed output only ever moves toward the target, so it stays
+    // within the range the target covered
+    let min = *target.iter().min().unwrap();
+    let max = *target.iter().max().unwrap();
+    assert!(smoothed.iter().all(|&value| (min..=max).contains(&value)));
+    let biggest = smoothed
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .max()
+        .unwrap();
+    let biggest_target = target
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .max()
+        .unwrap();
+    assert!(biggest < biggest_target, "{biggest} vs {biggest_target}");
+}
+
+#[test]
+fn clocked_steps_are_the_size_the_speed_knob_sets() {
+    let mut brownian = BrownianModuleState::new(SEED, None);
+    let clock = |edge| Clock {
+        delta_t: u32::MAX / 10,
+        edge,
+        quadrature_edge: false,
+    };
+    let mut last = brownian.step_clocked(&CLOCKED_STEPS, clock(false));
+    let mut checked = 0;
+    for i in 1..20_000 {
+        let clock = clock(i % 10 == 0);
+        let value = brownian.step_clocked(&CLOCKED_STEPS, clock);
+        if !clock.edge {
+            assert_eq!(value, last);
+        } else if value != 0 && last != 0 && value < 4095 - 64 && last < 4095 - 64 {
+            let size = value.abs_diff(last);
+            assert!(size.abs_diff(CLOCKED_STEP_SIZE) <= 1, "{last} -> {value}");
+            checked += 1;
+        }
+        last = value;
+    }
+    assert!(checked > 1000, "only {checked} steps away from the ends");
+}
+
+/// The size of every clocked step that doesn't hit either end of the range
+fn clocked_step_sizes(distribution: Option<Distribution>) -> Vec<u16> {
+    let mut brownian = BrownianModuleState::new(SEED, distribution);
+    let clock = |edge| Clock {
+        delta_t: u32::MAX,
+        edge,
+        quadrature_edge: false,
+    };
+    let mut last = brownian.step_clocked(&CLOCKED_STEPS, clock(false));
+    let mut sizes = vec![];
+    for _ in 0..20_000 {
+        let value = brownian.step_clocked(&CLOCKED_STEPS, clock(true));
+        if value != 0 && last != 0 && value < 4095 - 128 && last < 4095 - 128 {
+            sizes.push(value.abs_diff(last));
+        }
+        last = value;
+    }
+    sizes
+}
+
+#[test]
+fn distribution_sets_the_size_of_each_step() {
+    // On average, uniform steps are the same size as the fixed ones
+    let uniform = clocked_step_sizes(Some(Distribution::Uniform));
+    let average = uniform.iter().map(|&size| size as f64).sum::<f64>() / uniform.len() as f64;
+    let expected = CLOCKED_STEP_SIZE as f64;
+    assert!((average - expected).abs() < 4.0, "{average}");
+    assert!(uniform.iter().all(|&size| size <= 2 * CLOCKED_STEP_SIZE));
+    assert!(uniform.iter().any(|&size| size < 16) && uniform.iter().any(|&size| size > 112));
+
+    // Most Cauchy steps are tiny, but some are as big as uniform ones
+    let mut cauchy = clocked_step_sizes(Some(Distribution::Cauchy));
+    cauchy.sort();
+    assert!(
+        cauchy[cauchy.len() / 2] < 16,
+        "median {}",
+        cauchy[cauchy.len() / 2]
+    );
+    assert!(cauchy.iter().any(|&size| size > 100));
+
+    // Bimodal steps are never tiny
+    let bimodal = clocked_step_sizes(Some(Distribution::Bimodal));
+    let tiny = bimodal.iter().filter(|&&size| size < 16).count();
+    assert!(tiny < bimodal.len() / 50, "{tiny} tiny steps");
+}
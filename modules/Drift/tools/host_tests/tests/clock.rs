@@ -0,0 +1,165 @@
+use host_tests::{
+    clock::ClockInput,
+    drift::{AlgorithmKind, Drift, SpeedCv},
+    lfo::LfoShape,
+    settings::Settings,
+};
+
+const SEED: u16 = 0x1234;
+const HIGH: u16 = 800;
+const LOW: u16 = 0;
+
+/// A clock signal with the given period in samples and a 50% duty cycle
+fn clock_signal(period: usize) -> impl Iterator<Item = u16> {
+    (0..).map(move |i| if i % period < period / 2 { HIGH } else { LOW })
+}
+
+fn clocked(algorithm: AlgorithmKind) -> Drift {
+    let settings = Settings {
+        algorithm: Some(algorithm),
+        speed_cv: SpeedCv::Clock,
+        ..Settings::DEFAULT
+    };
+    Drift::new([false, false], SEED, settings)
+}
+
+/// Runs the module for long enough to skip the startup crossfade and lock on
+/// to the clock, then returns the output at the clock edges and in between
+fn run(drift: &mut Drift, period: usize, texture: u16, cycles: usize) -> Vec<u16> {
+    clock_signal(period)
+        .take(period * cycles)
+        .map(|clock| drift.step(&[clock, 0, 512, texture]))
+        .skip(period * 4)
+        .collect()
+}
+
+#[test]
+fn clock_input_measures_the_period() {
+    let mut input = ClockInput::default();
+    let edges: Vec<(usize, u32)> = clock_signal(1000)
+        .take(5000)
+        .enumerate()
+        .map(|(i, cv)| (i, input.step(cv)))
+        .filter(|(_, clock)| clock.edge)
+        .map(|(i, clock)| (i, clock.delta_t))
+        .collect();
+    assert_eq!(
+        edges.iter().map(|e| e.0).collect::<Vec<_>>(),
+        [0, 1000, 2000, 3000, 4000]
+    );
+    // Nothing to follow until the second edge
+    assert_eq!(edges[0].1, 0);
+    for &(_, delta_t) in &edges[1..] {
+        assert_eq!(delta_t, u32::MAX / 1000);
+    }
+}
+
+#[test]
+fn clock_input_ignores_noise_near_the_threshold() {
+    let mut input = ClockInput::default();
+    let noisy = [0, 520, 500, 530, 490, 600, 300, 520, 100, 0, 520, 400];
+    let edges: Vec<usize> = noisy
+        .iter()
+        .enumerate()
+        .filter(|&(_, &cv)| input.step(cv).edge)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(edges, [1, 10]);
+}
+
+#[test]
+fn lfo_locks_to_the_clock() {
+    let settings = Settings {
+        algorithm: Some(AlgorithmKind::Lfo),
+        lfo_shape: LfoShape::Square,
+        speed_cv: SpeedCv::Clock,
+        ..Settings::DEFAULT
+    };
+    for period in [100, 1250, 4000] {
+        let mut drift = Drift::new([false, false], SEED, settings);
+        let output = run(&mut drift, period, 512, 12);
+        // The square wave rises exactly on each clock and falls halfway
+        // through, since the texture is in the middle
+        for (i, &value) in output.iter().enumerate() {
+            let phase = i % period;
+            if phase < period / 2 - 1 {
+                assert_eq!(value, 4095, "period {period}, sample {i}");
+            } else if phase > period / 2 + 1 {
+                assert_eq!(value, 0, "period {period}, sample {i}");
+            }
+        }
+    }
+}
+
+#[test]
+fn bezier_reaches_a_new_value_on_each_clock() {
+    let period = 1000;
+    let mut drift = clocked(AlgorithmKind::Bezier);
+    let output = run(&mut drift, period, 1023, 20);
+    let mut targets = Vec::new();
+    for cycle in output.chunks(period) {
+        let target = cycle[period - 1];
+        // Eases in to the target, so it settles at the end of each cycle
+        assert!(cycle[period - 10..].iter().all(|v| v.abs_diff(target) <= 1));
+        targets.push(target);
+    }
+    targets.dedup();
+    assert!(targets.len() > 10);
+}
+
+#[test]
+fn bezier_waits_for_a_late_clock() {
+    let mut drift = clocked(AlgorithmKind::Bezier);
+    run(&mut drift, 500, 1023, 8);
+    // Stop the clock
+    let held: Vec<u16> = (0..2000)
+        .map(|_| drift.step(&[LOW, 0, 512, 1023]))
+        .collect();
+    assert!(held[600..].iter().all(|&v| v == held[600]));
+}
+
+#[test]
+fn brownian_steps_once_per_clock() {
+    let period = 200;
+    let mut drift = clocked(AlgorithmKind::Brownian);
+    // With the texture all the way up there's no smoothing, so the output
+    // only changes when the target does
+    let output = run(&mut drift, period, 1023, 50);
+    for (i, pair) in output.windows(2).enumerate() {
+        if (i + 1) % period != 0 {
+            assert_eq!(pair[0], pair[1], "sample {i}");
+        }
+    }
+    let changes = output.windows(2).filter(|p| p[0] != p[1]).count();
+    // Some steps are lost against the bottom of the range, where it starts
+    assert!(changes > 30, "{changes}");
+}
+
+#[test]
+fn perlin_restarts_its_base_octave_on_each_clock() {
+    let period = 1000;
+    let mut drift = clocked(AlgorithmKind::Perlin);
+    // With the texture all the way down it's only the base octave, which is
+    // back at the center at the start of each segment
+    let output = run(&mut drift, period, 0, 20);
+    for cycle in output.chunks(period) {
+        assert!(cycle[0].abs_diff(2048) <= 4, "{}", cycle[0]);
+    }
+    let max = *output.iter().max().unwrap();
+    let min = *output.iter().min().unwrap();
+    assert!(max - min > 500);
+}
+
+#[test]
+fn clocked_output_does_not_jump_with_a_steady_clock() {
+    for algorithm in [AlgorithmKind::Perlin, AlgorithmKind::Bezier] {
+        let mut drift = clocked(algorithm);
+        let output = run(&mut drift, 1000, 800, 20);
+        let biggest_step = output
+            .windows(2)
+            .map(|p| p[0].abs_diff(p[1]))
+            .max()
+            .unwrap();
+        assert!(biggest_step < 40, "{biggest_step}");
+    }
+}
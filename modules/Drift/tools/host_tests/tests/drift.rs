@@ -0,0 +1,366 @@
+use host_tests::{
+    drift::{
+        select_algorithm, AlgorithmKind, ChannelB, Crossfade, Drift, SpeedCv, TextureCv, ALGORITHMS,
+    },
+    lfo::LfoShape,
+    settings::{Settings, FIXED_SEEDS},
+};
+
+const SEED: u16 = 0x1234;
+
+/// Fast enough that every algorithm moves a lot during a crossfade
+fn cv(texture_cv: u16) -> [u16; 4] {
+    [0, texture_cv, 800, 512]
+}
+
+/// The biggest step any algorithm takes on its own at the speed in `cv`
+fn biggest_natural_step(kind: AlgorithmKind) -> u16 {
+    let settings = Settings {
+        algorithm: Some(kind),
+        ..Settings::DEFAULT
+    };
+    let mut drift = Drift::new([false, false], SEED, settings);
+    let mut last = drift.step(&cv(0));
+    (0..20000)
+        .map(|_| {
+            let value = drift.step(&cv(0));
+            let step = value.abs_diff(last);
+            last = value;
+            step
+        })
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn every_algorithm_stays_in_range_with_any_inputs() {
+    let inputs = [0, 1, 511, 512, 1022, 1023];
+    for kind in ALGORITHMS {
+        let settings = Settings {
+            algorithm: Some(kind),
+            perlin_octaves: Some(6),
+            ..Settings::DEFAULT
+        };
+        let mut drift = Drift::new([false, false], SEED, settings);
+        for speed in inputs {
+            for texture in inputs {
+                for cv in [0, 1023] {
+                    for _ in 0..2000 {
+                        let value = drift.step(&[cv, cv, speed, texture]);
+                        assert!(value <= 4095, "{speed} {texture} {cv}: {value}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn crossfade_moves_from_the_old_value_to_the_new_signal() {
+    let mut crossfade = Crossfade::default();
+    assert_eq!(crossfade.apply(1000), 1000);
+    crossfade.start();
+    let faded: Vec<u16> = (0..300).map(|_| crossfade.apply(3000)).collect();
+    assert!(faded.windows(2).all(|w| w[0] <= w[1] && w[1] - w[0] <= 8));
+    assert_eq!(faded[255], 3000);
+    assert_eq!(faded[299], 3000);
+}
+
+#[test]
+fn crossfade_restarted_midway_does_not_jump() {
+    let mut crossfade = Crossfade::default();
+    crossfade.apply(0);
+    crossfade.start();
+    let mut last = 0;
+    for i in 0..1000 {
+        // Each fade is interrupted by the next one halfway through
+        if i % 128 == 0 {
+            crossfade.start();
+        }
+        let target = if (i / 128) % 2 == 0 { 4095 } else { 0 };
+        let value = crossfade.apply(target);
+        assert!(value.abs_diff(last) <= 17, "{i}: {last} -> {value}");
+        last = value;
+    }
+}
+
+#[test]
+fn persisted_algorithm_overrides_the_switches() {
+    for kind in ALGORITHMS {
+        let settings = Settings {
+            algorithm: Some(kind),
+            ..Settings::DEFAULT
+        };
+        for config in [[false, false], [true, true]] {
+            let mut drift = Drift::new(config, SEED, settings);
+            let mut reference = Drift::new(
+                [kind.index() >= 2, kind.index() % 2 == 1],
+                SEED,
+                Settings::DEFAULT,
+            );
+            for _ in 0..1000 {
+                assert_eq!(drift.step(&cv(0)), reference.step(&cv(0)));
+            }
+        }
+    }
+}
+
+#[test]
+fn select_cv_has_hysteresis_at_zone_edges() {
+    use AlgorithmKind::*;
+    assert!(select_algorithm(100, Perlin) == Perlin);
+    assert!(select_algorithm(300, Perlin) == Brownian);
+    // Just past the edge isn't enough
+    assert!(select_algorithm(260, Perlin) == Perlin);
+    assert!(select_algorithm(250, Brownian) == Brownian);
+    // Jumping across several zones works
+    assert!(select_algorithm(1023, Perlin) == Lfo);
+    assert!(select_algorithm(0, Lfo) == Perlin);
+}
+
+#[test]
+fn switching_algorithms_with_cv_does_not_jump() {
+    let settings = Settings {
+        texture_cv: TextureCv::SelectAlgorithm,
+        ..Settings::DEFAULT
+    };
+    let max_natural_step = ALGORITHMS
+        .map(biggest_natural_step)
+        .into_iter()
+        .max()
+        .unwrap();
+    let mut drift = Drift::new([false, false], SEED, settings);
+    let mut last = drift.step(&cv(0));
+    // Switch every 50 steps, which is faster than the crossfade, so fades
+    // often start in the middle of another one
+    for i in 0..20000usize {
+        let zone = (i / 50 * 7) % ALGORITHMS.len();
+        let value = drift.step(&cv(zone as u16 * 256 + 128));
+        assert!(
+            value.abs_diff(last) <= max_natural_step + 17,
+            "{i}: {last} -> {value}"
+        );
+        last = value;
+    }
+}
+
+fn with_channel_b(algorithm: AlgorithmKind, channel_b: ChannelB) -> Settings {
+    Settings {
+        algorithm: Some(algorithm),
+        channel_b,
+        ..Settings::DEFAULT
+    }
+}
+
+#[test]
+fn channel_b_is_off_by_default() {
+    let mut drift = Drift::new([false, false], SEED, Settings::DEFAULT);
+    drift.step(&cv(0));
+    assert!(drift.output_b().is_none());
+}
+
+#[test]
+fn inverted_channel_b_mirrors_channel_a() {
+    for kind in ALGORITHMS {
+        let settings = with_channel_b(kind, ChannelB::Inverted);
+        let mut drift = Drift::new([false, false], SEED, settings);
+        for _ in 0..5000 {
+            let a = drift.step(&cv(0));
+            assert_eq!(a + drift.output_b().unwrap(), 4095);
+        }
+    }
+}
+
+#[test]
+fn independent_channel_b_is_a_different_signal() {
+    for kind in ALGORITHMS {
+        // The seed only matters to the LFO for the sample-and-hold
+        let settings = Settings {
+            lfo_shape: LfoShape::SampleAndHold,
+            ..with_channel_b(kind, ChannelB::Independent)
+        };
+        let mut drift = Drift::new([false, false], SEED, settings);
+        let mut different = 0;
+        for _ in 0..20000 {
+            let a = drift.step(&cv(0));
+            let b = drift.output_b().unwrap();
+            assert!(b <= 4095);
+            if a.abs_diff(b) > 100 {
+                different += 1;
+            }
+        }
+        assert!(different > 5000, "{different}");
+    }
+}
+
+/// Finds the sample in each cycle where a symmetrical triangle wave peaks
+fn peaks(output: &[u16]) -> Vec<usize> {
+    output
+        .windows(3)
+        .enumerate()
+        .filter(|(_, w)| w[1] > 4000 && w[0] <= w[1] && w[1] > w[2])
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+#[test]
+fn quadrature_lfo_is_a_quarter_cycle_ahead() {
+    let settings = Settings {
+        lfo_shape: LfoShape::Triangle,
+        ..with_channel_b(AlgorithmKind::Lfo, ChannelB::Quadrature)
+    };
+    // Speed knob 454 is about 1Hz, so a cycle is about 2500 samples
+    let knobs = [0, 0, 454, 512];
+    for speed_cv in [SpeedCv::Speed, SpeedCv::Clock] {
+        let settings = Settings {
+            speed_cv,
+            ..settings
+        };
+        let mut drift = Drift::new([false, false], SEED, settings);
+        let period = 2000;
+        let (a, b): (Vec<u16>, Vec<u16>) = (0..period * 10)
+            .map(|i| {
+                let clock = match speed_cv {
+                    SpeedCv::Clock if i % period < 100 => 800,
+                    _ => 0,
+                };
+                let a = drift.step(&[clock, knobs[1], knobs[2], knobs[3]]);
+                (a, drift.output_b().unwrap())
+            })
+            .skip(period * 3)
+            .unzip();
+        let peaks_a = peaks(&a);
+        let peaks_b = peaks(&b);
+        let cycle = peaks_a[1] - peaks_a[0];
+        for (&peak_a, &peak_b) in peaks_a.iter().zip(&peaks_b) {
+            let lead = (peak_a + cycle - peak_b) % cycle;
+            assert!(lead.abs_diff(cycle / 4) <= 2, "lead {lead} of {cycle}");
+        }
+    }
+}
+
+#[test]
+fn switching_algorithms_fades_channel_b_too() {
+    let settings = Settings {
+        texture_cv: TextureCv::SelectAlgorithm,
+        ..with_channel_b(AlgorithmKind::Perlin, ChannelB::Independent)
+    };
+    let max_natural_step = ALGORITHMS
+        .map(biggest_natural_step)
+        .into_iter()
+        .max()
+        .unwrap();
+    let mut drift = Drift::new([false, false], SEED, settings);
+    drift.step(&cv(0));
+    let mut last = drift.output_b().unwrap();
+    for i in 0..20000usize {
+        let zone = (i / 50 * 7) % ALGORITHMS.len();
+        drift.step(&cv(zone as u16 * 256 + 128));
+        let value = drift.output_b().unwrap();
+        assert!(
+            value.abs_diff(last) <= max_natural_step + 17,
+            "{i}: {last} -> {value}"
+        );
+        last = value;
+    }
+}
+
+#[test]
+fn changing_channel_b_fades_from_what_it_was_outputting() {
+    for channel_b in [ChannelB::Quadrature, ChannelB::Independent] {
+        for kind in ALGORITHMS {
+            let mut drift = Drift::new(
+                [false, false],
+                SEED,
+                with_channel_b(kind, ChannelB::Inverted),
+            );
+            let mut value = 0;
+            for _ in 0..3000 {
+                value = drift.step(&cv(0));
+            }
+            let mut last = drift.output_b().unwrap();
+
+            drift.apply_settings(with_channel_b(kind, channel_b), value);
+            for i in 0..1000 {
+                drift.step(&cv(0));
+                let value_b = drift.output_b().unwrap();
+                assert!(
+                    value_b.abs_diff(last) <= biggest_natural_step(kind) + 17,
+                    "{i}: {last} -> {value_b}"
+                );
+                last = value_b;
+            }
+        }
+    }
+}
+
+fn run_with_triggers(drift: &mut Drift, samples: usize, trigger_every: usize) -> Vec<u16> {
+    (0..samples)
+        .map(|i| {
+            let trigger = if i % trigger_every < 10 { 800 } else { 0 };
+            drift.step(&[0, trigger, 800, 512])
+        })
+        .collect()
+}
+
+#[test]
+fn fixed_seed_repeats_across_restarts() {
+    for kind in ALGORITHMS {
+        let settings = Settings {
+            algorithm: Some(kind),
+            lfo_shape: LfoShape::SampleAndHold,
+            fixed_seed: Some(2),
+            ..Settings::DEFAULT
+        };
+        let run = |random_seed| {
+            let mut drift = Drift::new([false, false], random_seed, settings);
+            (0..5000).map(|_| drift.step(&cv(0))).collect::<Vec<u16>>()
+        };
+        assert_eq!(run(0x1234), run(0xBEEF));
+    }
+    assert!(Settings::DEFAULT.seed(0x1234) == 0x1234);
+    let fixed = Settings {
+        fixed_seed: Some(2),
+        ..Settings::DEFAULT
+    };
+    assert!(fixed.seed(0x1234) == FIXED_SEEDS[2]);
+}
+
+#[test]
+fn reset_trigger_repeats_the_same_motion() {
+    for kind in ALGORITHMS {
+        for fixed_seed in [None, Some(5)] {
+            let settings = Settings {
+                algorithm: Some(kind),
+                lfo_shape: LfoShape::SampleAndHold,
+                texture_cv: TextureCv::Reset,
+                fixed_seed,
+                ..Settings::DEFAULT
+            };
+            let mut drift = Drift::new([false, false], SEED, settings);
+            let bar = 3000;
+            let output = run_with_triggers(&mut drift, bar * 4, bar);
+            // After the fade in from wherever the last bar ended, every bar
+            // is exactly the same
+            let bars: Vec<&[u16]> = output.chunks(bar).map(|bar| &bar[256..]).collect();
+            for i in 2..bars.len() {
+                assert!(bars[i] == bars[1], "{} bar {i}", kind.index());
+            }
+        }
+    }
+}
+
+#[test]
+fn reset_does_not_jump() {
+    let settings = Settings {
+        algorithm: Some(AlgorithmKind::Bezier),
+        texture_cv: TextureCv::Reset,
+        ..Settings::DEFAULT
+    };
+    let max_natural_step = biggest_natural_step(AlgorithmKind::Bezier);
+    let mut drift = Drift::new([false, false], SEED, settings);
+    let output = run_with_triggers(&mut drift, 20000, 777);
+    for pair in output.windows(2) {
+        assert!(pair[0].abs_diff(pair[1]) <= max_natural_step + 17);
+    }
+}
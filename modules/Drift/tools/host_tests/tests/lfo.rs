@@ -0,0 +1,572 @@
+use host_tests::{
+    clock::Clock,
+    lfo::{LfoModuleState, LfoShape},
+    shared::{get_delta_t, DriftModule},
+};
+
+const SAMPLE_RATE_HZ: f64 = 2500.0;
+const MAX_OUTPUT: f64 = 4095.0;
+
+/// The inputs the LFO reads, in the order of the firmware's `cv` array
+fn lfo_cv(frequency_knob: u16, skew_knob: u16) -> [u16; 4] {
+    [0, 0, frequency_knob, skew_knob]
+}
+
+/// The frequency `get_delta_t` is supposed to produce: 1/40 Hz at 0V, 1V/oct,
+/// with the knob spanning 12V
+fn expected_hz(knob: u16, cv: u16) -> f64 {
+    let sum = u16::min((knob * 12) / 5 + cv, (1023 * 12) / 5);
+    let volts = sum as f64 * 5.0 / 1024.0;
+    volts.exp2() / 40.0
+}
+
+fn samples_per_cycle(dt: u32) -> f64 {
+    2f64.powi(32) / dt as f64
+}
+
+#[test]
+fn delta_t_matches_frequency_over_full_range() {
+    let mut worst = (0.0, 0, 0);
+    for knob in 0..=1023 {
+        for cv in [0, 1, 100, 500, 1023] {
+            let expected = SAMPLE_RATE_HZ / expected_hz(knob, cv);
+            let actual = samples_per_cycle(get_delta_t(knob, cv, 0));
+            let error = (actual / expected - 1.0).abs();
+            if error > worst.0 {
+                worst = (error, knob, cv);
+            }
+        }
+    }
+    assert!(
+        worst.0 < 0.001,
+        "worst error (relative, knob, cv): {:?}",
+        worst
+    );
+}
+
+#[test]
+fn delta_t_covers_documented_range() {
+    let slowest = SAMPLE_RATE_HZ / samples_per_cycle(get_delta_t(0, 0, 0));
+    let fastest = SAMPLE_RATE_HZ / samples_per_cycle(get_delta_t(1023, 1023, 0));
+    assert!((slowest - 1.0 / 40.0).abs() < 1e-5, "slowest: {slowest} Hz");
+    assert!((99.0..103.0).contains(&fastest), "fastest: {fastest} Hz");
+}
+
+/// Runs the LFO for `samples` samples, with the skew knob given by
+/// `skew(sample)`
+fn run(frequency_knob: u16, samples: usize, skew: impl Fn(usize) -> u16) -> Vec<u16> {
+    run_shape(LfoShape::Triangle, frequency_knob, samples, skew)
+}
+
+fn run_shape(
+    shape: LfoShape,
+    frequency_knob: u16,
+    samples: usize,
+    skew: impl Fn(usize) -> u16,
+) -> Vec<u16> {
+    let mut lfo = LfoModuleState::new(shape, 1);
+    (0..samples)
+        .map(|i| lfo.step(&lfo_cv(frequency_knob, skew(i))))
+        .collect()
+}
+
+/// The sample indices where the output turns from falling to rising, which
+/// happens exactly once per cycle for any skew
+fn cycle_starts(output: &[u16]) -> Vec<usize> {
+    let mut starts = vec![];
+    let mut falling = false;
+    for (i, pair) in output.windows(2).enumerate() {
+        if pair[1] < pair[0] {
+            falling = true;
+        } else if pair[1] > pair[0] {
+            if falling {
+                starts.push(i);
+            }
+            falling = false;
+        }
+    }
+    starts
+}
+
+/// Sweeps the skew knob up and down between `low` and `high`, once every
+/// `period` samples
+fn sweep(low: u16, high: u16, period: usize) -> impl Fn(usize) -> u16 {
+    move |i| {
+        let phase = (i % period) as f64 / period as f64;
+        let triangle = 1.0 - (2.0 * phase - 1.0).abs();
+        low + ((high - low) as f64 * triangle).round() as u16
+    }
+}
+
+type SkewFn = Box<dyn Fn(usize) -> u16>;
+
+#[test]
+fn period_is_exact_for_any_skew() {
+    for frequency_knob in [0, 100, 300, 512, 700, 900, 1023] {
+        let expected = samples_per_cycle(get_delta_t(frequency_knob, 0, 0));
+        let cycles = f64::max(5.0, 200_000.0 / expected).round();
+        let samples = (expected * (cycles + 1.5)) as usize;
+
+        let skews: [(&str, SkewFn); 5] = [
+            ("falling saw", Box::new(|_| 0)),
+            ("triangle", Box::new(|_| 512)),
+            ("rising saw", Box::new(|_| 1023)),
+            // Near the ends of the range, a fast enough sweep can hide a whole
+            // rising segment between two samples at high frequencies, which
+            // would throw off `cycle_starts`
+            (
+                "slow sweep",
+                Box::new(sweep(100, 923, (expected * 3.7) as usize)),
+            ),
+            (
+                "fast sweep",
+                Box::new(sweep(100, 923, (expected * 0.3) as usize + 2)),
+            ),
+        ];
+        for (name, skew) in skews {
+            let starts = cycle_starts(&run(frequency_knob, samples, skew));
+            let measured_cycles = starts.len() - 1;
+            assert!(
+                measured_cycles as f64 >= cycles,
+                "{name} at knob {frequency_knob}: expected {cycles} cycles, got {measured_cycles}"
+            );
+            let measured = (starts[measured_cycles] - starts[0]) as f64 / measured_cycles as f64;
+            // Cycles start between two samples, and depending on how steep the
+            // end of the fall and the start of the rise are, the turn shows up
+            // on either one, so each end can be off by up to a sample. At low
+            // frequencies the output can also sit at 0 for a few samples after
+            // the start, depending on the skew at the time.
+            let tolerance = (2.0 + expected / 4096.0) / measured_cycles as f64;
+            assert!(
+                (measured - expected).abs() <= tolerance,
+                "{name} at knob {frequency_knob}: period {measured}, expected {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn constant_skew_is_a_triangle() {
+    for frequency_knob in [0, 400, 800, 1023] {
+        for skew_knob in [0, 1, 256, 512, 1000, 1023] {
+            let dt = get_delta_t(frequency_knob, 0, 0);
+            let apex = (skew_knob as u32) << 22;
+            let samples = (samples_per_cycle(dt) * 2.5) as usize;
+            let output = run(frequency_knob, samples, |_| skew_knob);
+
+            let mut time = 0u32;
+            for (i, &value) in output.iter().enumerate() {
+                time = time.wrapping_add(dt);
+                let t = time as f64;
+                let expected = if time < apex {
+                    t / apex as f64
+                } else {
+                    1.0 - (t - apex as f64) / (u32::MAX - apex) as f64
+                };
+                let error = (value as f64 - expected * MAX_OUTPUT).abs();
+                assert!(
+                    error <= 2.0,
+                    "knob {frequency_knob}, skew {skew_knob}, sample {i}: {value} should be {}",
+                    expected * MAX_OUTPUT
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn whole_samples_per_cycle_stay_a_triangle() {
+    // A clock measured in whole samples sets a delta_t that ends each cycle
+    // just short of the rollover, so the last step of a short falling segment
+    // covers almost all of it
+    for period in 25..=60 {
+        let dt = u32::MAX / period;
+        for skew_knob in [0, 512, 1000, 1023] {
+            let mut lfo = LfoModuleState::new(LfoShape::Triangle, 1);
+            let cv = lfo_cv(0, skew_knob);
+            let clock = Clock {
+                delta_t: dt,
+                edge: false,
+                quadrature_edge: false,
+            };
+            for i in 0..period as usize * 3 {
+                let value = lfo.step_clocked(&cv, clock);
+                // The last sample of each cycle is right at the bottom
+                if (i + 1) % period as usize == 0 {
+                    assert!(
+                        value < 4095 / 2,
+                        "period {period}, skew {skew_knob}, sample {i}: {value}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The largest change between two samples of a static wave with the given
+/// skew knob
+fn steepest_static_step(dt: u32, skew_knob: u16) -> f64 {
+    let rise = ((skew_knob as u32) << 22) as f64;
+    let fall = 2f64.powi(32) - rise;
+    MAX_OUTPUT * dt as f64 / f64::min(rise, fall)
+}
+
+#[test]
+fn sweeping_skew_does_not_jump() {
+    for frequency_knob in [0, 200, 500, 800, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let cycle = samples_per_cycle(dt);
+        for (low, high) in [(256, 768), (100, 900), (50, 300), (700, 1000)] {
+            for sweep_cycles in [0.37, 1.0, 2.9] {
+                let period = usize::max(2, (cycle * sweep_cycles) as usize);
+                let samples = (cycle * 4.0) as usize;
+                let output = run(frequency_knob, samples, sweep(low, high, period));
+
+                // The output can get steeper than a static wave while the apex
+                // moves toward the current time, but never by much
+                let bound =
+                    2.0 * f64::max(
+                        steepest_static_step(dt, low),
+                        steepest_static_step(dt, high),
+                    ) + 2.0;
+                let biggest_step = output
+                    .windows(2)
+                    .map(|pair| pair[0].abs_diff(pair[1]))
+                    .max()
+                    .unwrap();
+                assert!(
+                    (biggest_step as f64) <= bound,
+                    "knob {frequency_knob}, skew {low}..{high} every {sweep_cycles} cycles: \
+                     step of {biggest_step}, expected at most {bound}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn full_range_at_any_frequency() {
+    for frequency_knob in [0, 512, 1023] {
+        let cycle = samples_per_cycle(get_delta_t(frequency_knob, 0, 0));
+        let output = run(frequency_knob, (cycle * 3.0) as usize, |_| 512);
+        let skip = cycle as usize;
+        let max = *output[skip..].iter().max().unwrap();
+        let min = *output[skip..].iter().min().unwrap();
+        // At 100 Hz there are only 25 samples per cycle, so the peak can fall
+        // between two of them
+        let tolerance = (2.0 * MAX_OUTPUT / cycle) as u16 + 1;
+        assert!(max >= 4095 - tolerance, "knob {frequency_knob}: max {max}");
+        assert!(min <= tolerance, "knob {frequency_knob}: min {min}");
+    }
+}
+
+#[test]
+fn turning_the_skew_knob_quickly_does_not_jump() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let cycle = samples_per_cycle(dt);
+        for (before, after) in [(200, 800), (800, 200), (512, 100), (100, 900)] {
+            let bound =
+                2.0 * f64::max(
+                    steepest_static_step(dt, before),
+                    steepest_static_step(dt, after),
+                ) + 2.0;
+            // Change the skew at a range of points in the second cycle
+            for eighths in 0..8 {
+                let change_at = (cycle * (1.0 + eighths as f64 / 8.0)) as usize;
+                let output = run(frequency_knob, (cycle * 3.0) as usize, |i| {
+                    if i < change_at {
+                        before
+                    } else {
+                        after
+                    }
+                });
+                let biggest_step = output
+                    .windows(2)
+                    .map(|pair| pair[0].abs_diff(pair[1]))
+                    .max()
+                    .unwrap();
+                assert!(
+                    (biggest_step as f64) <= bound,
+                    "knob {frequency_knob}, skew {before} to {after} at sample {change_at}: \
+                     step of {biggest_step}, expected at most {bound}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn moving_the_apex_toward_the_wave_still_reaches_the_top() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let cycle = samples_per_cycle(dt);
+        let samples = (cycle * 6.0) as usize;
+        // Pull the apex back toward the start of the cycle while the wave is
+        // still rising toward it, at a range of speeds
+        for sweep_cycles in [0.1, 0.25, 0.5, 1.0] {
+            let period = usize::max(2, (cycle * sweep_cycles) as usize);
+            let skew = |i: usize| 1000 - ((i % period) * 900 / period) as u16;
+            let output = run(frequency_knob, samples, skew);
+            // The turn can fall between two samples
+            let tolerance = (2.0 * steepest_static_step(dt, 100)) as u16 + 2;
+            for value in turning_points(&output[cycle as usize..]) {
+                assert!(
+                    value <= tolerance || value >= 4095 - tolerance,
+                    "knob {frequency_knob}, sweep every {sweep_cycles} cycles: \
+                     turned around at {value}"
+                );
+            }
+        }
+    }
+}
+
+fn biggest_step(output: &[u16]) -> u16 {
+    output
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn sine_is_a_sine_wave() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let samples = (samples_per_cycle(dt) * 2.5) as usize;
+        let output = run_shape(LfoShape::Sine, frequency_knob, samples, |_| 512);
+
+        let mut time = 0u32;
+        for (i, &value) in output.iter().enumerate() {
+            time = time.wrapping_add(dt);
+            let phase = time as f64 / 2f64.powi(32);
+            let expected = (1.0 - (phase * std::f64::consts::TAU).cos()) / 2.0 * MAX_OUTPUT;
+            assert!(
+                (value as f64 - expected).abs() <= 2.0,
+                "knob {frequency_knob}, sample {i}: {value} should be {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn exponential_follows_curve() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let samples = (samples_per_cycle(dt) * 2.5) as usize;
+        let output = run_shape(LfoShape::Exponential, frequency_knob, samples, |_| 512);
+
+        let mut time = 0u32;
+        for (i, &value) in output.iter().enumerate() {
+            time = time.wrapping_add(dt);
+            let phase = time as f64 / 2f64.powi(32);
+            let triangle = 1.0 - (2.0 * phase - 1.0).abs();
+            let expected = ((6.0 * triangle).exp2() - 1.0) / 63.0 * MAX_OUTPUT;
+            assert!(
+                (value as f64 - expected).abs() <= 2.0,
+                "knob {frequency_knob}, sample {i}: {value} should be {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn smooth_shapes_do_not_jump_while_sweeping_skew() {
+    // The steepest slope of each shape relative to the triangle it's made from
+    let shapes = [
+        (LfoShape::Sine, std::f64::consts::FRAC_PI_2),
+        (
+            LfoShape::Exponential,
+            6.0 * std::f64::consts::LN_2 * 64.0 / 63.0,
+        ),
+    ];
+    for (shape, steepness) in shapes {
+        for frequency_knob in [0, 500, 1023] {
+            let dt = get_delta_t(frequency_knob, 0, 0);
+            let cycle = samples_per_cycle(dt);
+            let (low, high) = (100, 900);
+            let period = usize::max(2, (cycle * 0.37) as usize);
+            let output = run_shape(
+                shape,
+                frequency_knob,
+                (cycle * 4.0) as usize,
+                sweep(low, high, period),
+            );
+            let bound = 2.0
+                * steepness
+                * f64::max(
+                    steepest_static_step(dt, low),
+                    steepest_static_step(dt, high),
+                )
+                + 3.0;
+            let biggest_step = biggest_step(&output);
+            assert!(
+                (biggest_step as f64) <= bound,
+                "knob {frequency_knob}: step of {biggest_step}, expected at most {bound}"
+            );
+        }
+    }
+}
+
+#[test]
+fn square_width_follows_texture() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let cycle = samples_per_cycle(dt);
+        let cycles = f64::max(3.0, 50_000.0 / cycle).round();
+        // Start and end at the start of a cycle
+        let samples = (cycle * cycles).ceil() as usize;
+        for width in [64, 256, 512, 900] {
+            let output = run_shape(LfoShape::Square, frequency_knob, samples, |_| width);
+            assert!(output.iter().all(|&value| value == 0 || value == 4095));
+
+            let high = output.iter().filter(|&&value| value != 0).count() as f64;
+            let expected = width as f64 / 1024.0;
+            let tolerance = 2.0 / cycle + 0.001;
+            assert!(
+                (high / samples as f64 - expected).abs() <= tolerance,
+                "knob {frequency_knob}, width {width}: high {} of the time",
+                high / samples as f64
+            );
+
+            let rising_edges = output.windows(2).filter(|pair| pair[1] > pair[0]).count();
+            assert!(
+                (rising_edges as f64 - (cycles - 1.0)).abs() <= 1.0,
+                "knob {frequency_knob}, width {width}: {rising_edges} cycles in {cycles}"
+            );
+        }
+    }
+}
+
+#[test]
+fn sample_and_hold_changes_once_per_cycle() {
+    for frequency_knob in [0, 500, 1023] {
+        let dt = get_delta_t(frequency_knob, 0, 0);
+        let cycle = samples_per_cycle(dt);
+        let samples = (cycle * f64::max(5.0, 20_000.0 / cycle)) as usize;
+        for texture in [0, 512, 1023] {
+            let output = run_shape(LfoShape::SampleAndHold, frequency_knob, samples, |_| {
+                texture
+            });
+
+            // The time after the first sample
+            let mut time = dt;
+            let mut changes = 0;
+            for i in 1..output.len() {
+                let (next, rollover) = time.overflowing_add(dt);
+                time = next;
+                if output[i] != output[i - 1] {
+                    assert!(rollover, "knob {frequency_knob}: changed mid-cycle at {i}");
+                    changes += 1;
+                }
+            }
+            let cycles = (samples as f64 / cycle) as usize;
+            // A step can land on the same value by chance
+            assert!(
+                changes + 3 >= cycles && changes <= cycles,
+                "knob {frequency_knob}, texture {texture}: {changes} changes in {cycles} cycles"
+            );
+
+            if texture == 0 {
+                // Each step only goes 1/16th of the way to a new random value
+                assert!(biggest_step(&output) <= 4096 / 16 + 1);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_shape_runs_at_the_knobs_frequency() {
+    for frequency_knob in [300, 600, 900, 1023] {
+        let expected = samples_per_cycle(get_delta_t(frequency_knob, 0, 0));
+        let cycles = f64::max(5.0, 100_000.0 / expected).round();
+        let samples = (expected * (cycles + 0.5)) as usize;
+        for (name, shape) in [
+            ("triangle", LfoShape::Triangle),
+            ("sine", LfoShape::Sine),
+            ("square", LfoShape::Square),
+            ("exponential", LfoShape::Exponential),
+        ] {
+            let output = run_shape(shape, frequency_knob, samples, |_| 512);
+            // Every one of these shapes rises through the middle once per
+            // cycle
+            let crossings: Vec<usize> = output
+                .windows(2)
+                .enumerate()
+                .filter(|(_, pair)| pair[0] < 2048 && pair[1] >= 2048)
+                .map(|(i, _)| i)
+                .collect();
+            let first = crossings[0];
+            let last = *crossings.last().unwrap();
+            let measured = (last - first) as f64 / (crossings.len() - 1) as f64;
+            assert!(
+                (measured / expected - 1.0).abs() < 0.002,
+                "{name} at knob {frequency_knob}: {measured} samples per cycle, expected {expected}"
+            );
+        }
+    }
+}
+
+/// The values where the output changes direction
+fn turning_points(output: &[u16]) -> Vec<u16> {
+    let mut points = vec![];
+    let mut rising = None;
+    for pair in output.windows(2) {
+        let now_rising = match pair[1].cmp(&pair[0]) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => continue,
+        };
+        if rising.is_some_and(|rising| rising != now_rising) {
+            points.push(pair[0]);
+        }
+        rising = Some(now_rising);
+    }
+    points
+}
+
+#[test]
+fn early_and_late_clocks_bend_the_wave_instead_of_jumping() {
+    let period = 1000;
+    let dt = u32::MAX / period as u32;
+    // Edges at a steady period, then one early, then one late, then steady
+    // again for long enough to settle
+    let mut edges = vec![0, 1000, 2000, 3000, 3600, 4600, 5900, 6900];
+    edges.extend((8..14).map(|i| i * 1000 - 100));
+    let end = *edges.last().unwrap() + 1;
+
+    for skew_knob in [100, 512, 900] {
+        let mut lfo = LfoModuleState::new(LfoShape::Triangle, 1);
+        let cv = lfo_cv(0, skew_knob);
+        let output: Vec<u16> = (0..end)
+            .map(|i| {
+                let clock = Clock {
+                    delta_t: dt,
+                    edge: edges.contains(&i),
+                    quadrature_edge: false,
+                };
+                lfo.step_clocked(&cv, clock)
+            })
+            .collect();
+
+        // Catching up runs the wave at up to twice the speed
+        let bound = 2.0 * steepest_static_step(dt, skew_knob) + 2.0;
+        let biggest_step = biggest_step(&output);
+        assert!(
+            (biggest_step as f64) <= bound,
+            "skew {skew_knob}: step of {biggest_step}, expected at most {bound}"
+        );
+
+        // Still turns around only at the top and bottom, and lines back up
+        // with the clock. The turn can fall between two samples.
+        let tolerance = bound as u16;
+        for value in turning_points(&output) {
+            assert!(
+                value <= tolerance || value >= 4095 - tolerance,
+                "skew {skew_knob}: turned around at {value}"
+            );
+        }
+        let last_edge = edges[edges.len() - 2];
+        assert!(output[last_edge] <= tolerance, "skew {skew_knob}");
+    }
+}
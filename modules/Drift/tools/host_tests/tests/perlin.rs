@@ -0,0 +1,310 @@
+use fixed::types::{I1F15, U0F16};
+use fm_lib::rng::ParallelLfsr;
+use host_tests::{
+    clock::Clock,
+    perlin::{gradient, perlin_segment, PerlinModuleState, MAX_OCTAVES},
+    shared::{get_delta_t, DriftModule},
+};
+
+const SEED: u16 = 0x1234;
+
+/// Every setting on the octaves page, starting with the original mix
+fn all_octave_settings() -> impl Iterator<Item = Option<u8>> {
+    [None].into_iter().chain((1..=MAX_OCTAVES).map(Some))
+}
+
+fn run_setting(octaves: Option<u8>, speed: u16, roughness: u16, samples: usize) -> Vec<u16> {
+    let mut perlin = PerlinModuleState::new(SEED, octaves);
+    (0..samples)
+        .map(|_| perlin.step(&[0, 0, speed, roughness]))
+        .collect()
+}
+
+fn run(octaves: u8, speed: u16, roughness: u16, samples: usize) -> Vec<u16> {
+    run_setting(Some(octaves), speed, roughness, samples)
+}
+
+fn biggest_step(output: &[u16]) -> u16 {
+    output
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn mix_stays_in_range_for_all_settings() {
+    // The firmware's debug assertions check the mix before it's clamped to the
+    // output range, so that weights that add up to more than 1 show up, and
+    // that each octave stays in range
+    for octaves in all_octave_settings() {
+        for roughness in (0..=1023).step_by(64).chain([1023]) {
+            for speed in [0, 500, 1023] {
+                let mut perlin = PerlinModuleState::new(SEED, octaves);
+                let cv = [0, 0, speed, roughness];
+                for _ in 0..10000 {
+                    let output = perlin.step(&cv);
+                    assert!(
+                        output <= 4095,
+                        "{octaves:?} octaves, {roughness}, {speed}: {output}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn output_uses_most_of_the_range() {
+    for octaves in 1..=MAX_OCTAVES {
+        for roughness in [0, 512, 1023] {
+            let output = run(octaves, 600, roughness, 100_000);
+            let min = *output.iter().min().unwrap();
+            let max = *output.iter().max().unwrap();
+            assert!(
+                max - min > 1500,
+                "{octaves} octaves, {roughness}: {min}-{max}"
+            );
+        }
+    }
+}
+
+#[test]
+fn roughness_only_matters_with_more_than_one_octave() {
+    assert_eq!(run(1, 500, 0, 10000), run(1, 500, 1023, 10000));
+    assert!(run(3, 500, 0, 10000) != run(3, 500, 1023, 10000));
+}
+
+#[test]
+fn no_roughness_is_as_smooth_as_one_octave() {
+    // The silent octaves still use up random numbers, so the base octave
+    // isn't exactly the same, but it's just as smooth
+    let smooth = biggest_step(&run(1, 500, 0, 50000));
+    for octaves in 2..=MAX_OCTAVES {
+        let step = biggest_step(&run(octaves, 500, 0, 50000));
+        assert!(step <= smooth * 2, "{octaves} octaves: {step} vs {smooth}");
+    }
+}
+
+#[test]
+fn more_octaves_and_roughness_make_bigger_steps() {
+    let smooth = biggest_step(&run(1, 500, 0, 50000));
+    let rough = biggest_step(&run(MAX_OCTAVES, 500, 1023, 50000));
+    assert!(rough > smooth * 4, "{smooth} {rough}");
+    let less_rough = biggest_step(&run(MAX_OCTAVES, 500, 700, 50000));
+    assert!(
+        rough > less_rough && less_rough > smooth,
+        "{smooth} {less_rough} {rough}"
+    );
+}
+
+#[test]
+fn clocked_mix_stays_in_range() {
+    let cv = [0, 0, 0, 1023];
+    for octaves in all_octave_settings() {
+        let mut perlin = PerlinModuleState::new(SEED, octaves);
+        for i in 0..20000 {
+            // Each clock restarts the octaves. In between, the base octave
+            // waits at the end of its segment, since the clock is late. As
+            // above, the debug assertions check the mix before it's clamped.
+            let clock = Clock {
+                delta_t: u32::MAX / 500,
+                edge: i % 700 == 0,
+                quadrature_edge: false,
+            };
+            let output = perlin.step_clocked(&cv, clock);
+            assert!(output <= 4095, "{octaves:?} octaves: {output}");
+        }
+    }
+}
+
+/// One octave of the original firmware's Perlin noise
+struct OriginalOctave {
+    time: u32,
+    last_grad: I1F15,
+    next_grad: I1F15,
+}
+
+impl OriginalOctave {
+    fn new(rng: &mut ParallelLfsr) -> Self {
+        Self {
+            time: 0,
+            last_grad: gradient(rng.next()),
+            next_grad: gradient(rng.next()),
+        }
+    }
+
+    fn step(&mut self, rng: &mut ParallelLfsr, delta_time: u32) -> I1F15 {
+        let (new_time, rollover) = self.time.overflowing_add(delta_time);
+        self.time = new_time;
+        if rollover {
+            self.last_grad = self.next_grad;
+            self.next_grad = gradient(rng.next());
+        }
+        perlin_segment(
+            U0F16::from_bits((self.time >> 16) as u16),
+            self.last_grad,
+            self.next_grad,
+        )
+    }
+}
+
+/// The original firmware's Perlin noise: the base octave, with the texture
+/// blending in up to a quarter of an octave 4 times as fast
+fn run_original(speed: u16, texture: u16, samples: usize) -> Vec<u16> {
+    let mut rng = ParallelLfsr::new(SEED);
+    let mut base = OriginalOctave::new(&mut rng);
+    let mut octave = OriginalOctave::new(&mut rng);
+    let dt = get_delta_t(speed, 0, 0);
+    let blend = I1F15::from_bits((texture << 5) as i16);
+    const ONE: I1F15 = I1F15::from_bits(0x7FFF);
+    (0..samples)
+        .map(|_| {
+            let base_value = base.step(&mut rng, dt);
+            let octave_value = octave.step(&mut rng, dt * 4);
+            let value = base_value * 3 + base_value * (ONE - blend) + octave_value * blend;
+            ((value.to_bits() / 16) + (1 << 11)).clamp(0, 4095) as u16
+        })
+        .collect()
+}
+
+#[test]
+fn default_is_the_original_mix() {
+    for speed in [0, 500, 1023] {
+        for texture in [0, 300, 700, 1023] {
+            let original = run_original(speed, texture, 50000);
+            let output = run_setting(None, speed, texture, 50000);
+            // Only the rounding is different
+            for (i, (a, b)) in original.iter().zip(&output).enumerate() {
+                assert!(
+                    a.abs_diff(*b) <= 2,
+                    "{speed} {texture}, sample {i}: {a} vs {b}"
+                );
+            }
+        }
+    }
+}
+
+/// An in-place radix 2 FFT of `re` + i * `im`, whose length must be a power of 2
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+const FFT_SIZE: usize = 4096;
+
+/// The power spectrum of `output`, averaged over windows of `FFT_SIZE` samples
+fn power_spectrum(output: &[u16]) -> Vec<f64> {
+    let mut power = vec![0.0; FFT_SIZE / 2];
+    let windows = output.chunks_exact(FFT_SIZE);
+    let count = windows.len() as f64;
+    for window in windows {
+        let mean = window.iter().map(|&v| v as f64).sum::<f64>() / FFT_SIZE as f64;
+        // A Hann window, so that the ends of each chunk don't leak into
+        // every frequency
+        let mut re: Vec<f64> = window
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let hann =
+                    0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / FFT_SIZE as f64).cos();
+                (v as f64 - mean) * hann
+            })
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+        for (bin, p) in power.iter_mut().enumerate() {
+            *p += (re[bin] * re[bin] + im[bin] * im[bin]) / count;
+        }
+    }
+    power
+}
+
+/// The power in each octave wide band, starting from the base octave's
+/// segment rate
+fn octave_bands(speed: u16, output: &[u16]) -> Vec<f64> {
+    let spectrum = power_spectrum(output);
+    let base_bin = get_delta_t(speed, 0, 0) as f64 / 2f64.powi(32) * FFT_SIZE as f64;
+    let mut bands = vec![];
+    let mut low = base_bin;
+    while low * 2.0 < spectrum.len() as f64 {
+        let band = &spectrum[low.round() as usize..(low * 2.0).round() as usize];
+        bands.push(band.iter().sum());
+        low *= 2.0;
+    }
+    bands
+}
+
+fn decibels(power: f64, reference: f64) -> f64 {
+    10.0 * (power / reference).log10()
+}
+
+const SPECTRUM_SPEED: u16 = 600;
+
+#[test]
+fn spectrum_rolls_off_above_the_top_octave() {
+    for octaves in [1, 3, MAX_OCTAVES] {
+        for roughness in [0, 512, 1023] {
+            let output = run(octaves, SPECTRUM_SPEED, roughness, FFT_SIZE * 32);
+            let bands = octave_bands(SPECTRUM_SPEED, &output);
+            // With no roughness, only the base octave is there
+            let top = if roughness == 0 {
+                0
+            } else {
+                octaves as usize - 1
+            };
+            let drop = decibels(bands[top + 1], bands[top]);
+            assert!(drop < -12.0, "{octaves} octaves, {roughness}: {drop} dB");
+        }
+    }
+}
+
+#[test]
+fn each_octave_is_quieter_by_the_roughness() {
+    for roughness in [300, 512, 700] {
+        let output = run(MAX_OCTAVES, SPECTRUM_SPEED, roughness, FFT_SIZE * 32);
+        let bands = octave_bands(SPECTRUM_SPEED, &output);
+        // Each octave's amplitude is `roughness / 1024` of the one below it
+        let expected = 20.0 * (roughness as f64 / 1024.0).log10();
+        for band in 1..MAX_OCTAVES as usize - 1 {
+            let slope = decibels(bands[band + 1], bands[band]);
+            assert!(slope < 0.0, "{roughness}: band {band} {slope} dB");
+        }
+        // The lowest band also picks up the base octave's own rolloff, and
+        // the highest picks up what is above it, so only look in between
+        let slope = decibels(bands[4], bands[1]) / 3.0;
+        assert!(
+            (slope - expected).abs() < 1.5,
+            "{roughness}: {slope} dB per octave, expected {expected}"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use fm_lib::rng::ParallelLfsr;
+use host_tests::random::{random_from_distribution, Distribution, DISTRIBUTIONS};
+use lut_generator::drift::{bimodal_pdf, cauchy_pdf, gaussian_pdf, triangle_pdf};
+
+const SEED: u16 = 0x1234;
+const SAMPLES: usize = 200_000;
+
+fn draw(distribution: Distribution) -> Vec<f64> {
+    let mut rng = ParallelLfsr::new(SEED);
+    (0..SAMPLES)
+        .map(|_| random_from_distribution(&mut rng, distribution).to_num::<f64>())
+        .collect()
+}
+
+fn mean(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let count = values.clone().count();
+    values.sum::<f64>() / count as f64
+}
+
+/// E[x^2] of a distribution on [-1, 1] given its density
+fn second_moment(pdf: impl Fn(f64) -> f64) -> f64 {
+    let steps = 100_000;
+    let xs = (0..steps).map(|i| -1.0 + (i as f64 + 0.5) * 2.0 / steps as f64);
+    let total: f64 = xs.clone().map(&pdf).sum();
+    xs.map(|x| x * x * pdf(x)).sum::<f64>() / total
+}
+
+fn expected_second_moment(distribution: Distribution) -> f64 {
+    match distribution {
+        Distribution::Uniform => 1.0 / 3.0,
+        Distribution::Triangle => second_moment(triangle_pdf),
+        Distribution::Gaussian => second_moment(gaussian_pdf),
+        Distribution::Cauchy => second_moment(cauchy_pdf),
+        Distribution::Bimodal => second_moment(bimodal_pdf),
+    }
+}
+
+#[test]
+fn every_distribution_is_centered_with_the_right_spread() {
+    for distribution in DISTRIBUTIONS {
+        let values = draw(distribution);
+        assert!(values.iter().all(|value| (-1.0..1.0).contains(value)));
+
+        let center = mean(values.iter().copied());
+        assert!(center.abs() < 0.01, "mean {center}");
+        let spread = mean(values.iter().map(|value| value * value));
+        let expected = expected_second_moment(distribution);
+        assert!(
+            (spread / expected - 1.0).abs() < 0.03,
+            "E[x^2] {spread}, expected {expected}"
+        );
+    }
+}
+
+#[test]
+fn each_distribution_has_its_shape() {
+    let near_zero = |distribution| {
+        let values = draw(distribution);
+        values.iter().filter(|value| value.abs() < 0.1).count() as f64 / SAMPLES as f64
+    };
+    // 1/10th of the range
+    let uniform = near_zero(Distribution::Uniform);
+    assert!((uniform - 0.1).abs() < 0.005, "{uniform}");
+    // The triangle is twice as dense as the uniform distribution at 0
+    let triangle = near_zero(Distribution::Triangle);
+    assert!((triangle - 0.19).abs() < 0.01, "{triangle}");
+    let gaussian = near_zero(Distribution::Gaussian);
+    assert!(gaussian > triangle, "{gaussian}");
+    // About half of the Cauchy distribution is within +/-0.1
+    let cauchy = near_zero(Distribution::Cauchy);
+    assert!(cauchy > 0.45 && cauchy < 0.6, "{cauchy}");
+    // The bimodal distribution is almost never near 0
+    let bimodal = near_zero(Distribution::Bimodal);
+    assert!(bimodal < 0.01, "{bimodal}");
+}
+
+#[test]
+fn cauchy_has_the_longest_tails_for_its_spread() {
+    // How far out the furthest 1% of values are, compared to the median
+    let tail_ratio = |distribution| {
+        let mut magnitudes: Vec<f64> = draw(distribution).iter().map(|v| v.abs()).collect();
+        magnitudes.sort_by(f64::total_cmp);
+        magnitudes[SAMPLES * 99 / 100] / magnitudes[SAMPLES / 2]
+    };
+    let cauchy = tail_ratio(Distribution::Cauchy);
+    for distribution in [Distribution::Uniform, Distribution::Gaussian] {
+        assert!(cauchy > tail_ratio(distribution) * 2.0, "{cauchy}");
+    }
+}
@@ -0,0 +1,328 @@
+use host_tests::{
+    drift::{
+        AlgorithmKind, ChannelB, SpeedCv, TextureCv, ALGORITHMS, CHANNEL_B_MODES, SPEED_CV_MODES,
+        TEXTURE_CV_MODES,
+    },
+    lfo::{LfoShape, LFO_SHAPES},
+    perlin::MAX_OCTAVES,
+    random::{Distribution, DISTRIBUTIONS},
+    settings::{Settings, FIXED_SEEDS, SETTINGS_SIZE},
+    setup::{setup_requested, Setup, SetupPage},
+};
+
+const SWITCHES: [bool; 2] = [false, false];
+
+/// Texture knob positions in the middle of each page
+const SEED_PAGE: u16 = 64;
+const CHANNEL_B_PAGE: u16 = 192;
+const SPEED_CV_PAGE: u16 = 320;
+const TEXTURE_CV_PAGE: u16 = 448;
+const ALGORITHM_PAGE: u16 = 576;
+const DISTRIBUTION_PAGE: u16 = 704;
+const PERLIN_OCTAVES_PAGE: u16 = 832;
+const LFO_SHAPE_PAGE: u16 = 960;
+
+fn knobs(speed: u16, texture: u16) -> [u16; 4] {
+    [0, 0, speed, texture]
+}
+
+fn zone_center(i: usize, len: usize) -> u16 {
+    ((2 * i + 1) * 1024 / (2 * len)) as u16
+}
+
+#[test]
+fn settings_round_trip_through_bytes() {
+    let default = Settings::DEFAULT;
+    let mut all = vec![default];
+    all.extend(LFO_SHAPES.map(|lfo_shape| Settings {
+        lfo_shape,
+        ..default
+    }));
+    all.extend(ALGORITHMS.map(|kind| Settings {
+        algorithm: Some(kind),
+        ..default
+    }));
+    all.extend(TEXTURE_CV_MODES.map(|texture_cv| Settings {
+        texture_cv,
+        ..default
+    }));
+    all.extend(SPEED_CV_MODES.map(|speed_cv| Settings {
+        speed_cv,
+        ..default
+    }));
+    all.extend(CHANNEL_B_MODES.map(|channel_b| Settings {
+        channel_b,
+        ..default
+    }));
+    all.extend((0..FIXED_SEEDS.len() as u8).map(|i| Settings {
+        fixed_seed: Some(i),
+        ..default
+    }));
+    all.extend((1..=MAX_OCTAVES).map(|octaves| Settings {
+        perlin_octaves: Some(octaves),
+        ..default
+    }));
+    all.extend(DISTRIBUTIONS.map(|distribution| Settings {
+        distribution: Some(distribution),
+        ..default
+    }));
+    all.push(Settings {
+        lfo_shape: LfoShape::Exponential,
+        algorithm: Some(AlgorithmKind::Bezier),
+        texture_cv: TextureCv::SelectAlgorithm,
+        speed_cv: SpeedCv::Clock,
+        channel_b: ChannelB::Independent,
+        fixed_seed: Some(3),
+        perlin_octaves: Some(6),
+        distribution: Some(Distribution::Cauchy),
+    });
+    for settings in all {
+        assert!(Settings::from_bytes(&settings.to_bytes()) == settings);
+    }
+}
+
+#[test]
+fn unrecognized_bytes_fall_back_to_default() {
+    // Erased EEPROM reads as 0xFF
+    let settings = Settings::from_bytes(&[0xFF; SETTINGS_SIZE]);
+    assert!(settings == Settings::DEFAULT);
+}
+
+#[test]
+fn setup_needs_both_knobs_at_the_ends() {
+    assert!(setup_requested(0, 1023));
+    assert!(setup_requested(20, 1000));
+    assert!(!setup_requested(512, 1023));
+    assert!(!setup_requested(0, 512));
+    assert!(!setup_requested(1023, 0));
+}
+
+#[test]
+fn entering_setup_keeps_the_saved_shape_until_the_knob_moves() {
+    let saved = Settings {
+        lfo_shape: LfoShape::Square,
+        ..Settings::DEFAULT
+    };
+    let mut setup = Setup::new(saved, SWITCHES, 0);
+    for _ in 0..100 {
+        assert!(setup.step(&knobs(10, 1023)).is_some());
+    }
+    assert!(setup.settings() == saved);
+
+    // All the way up is the last shape
+    setup.step(&knobs(1023, 1023));
+    assert!(setup.settings().lfo_shape == LfoShape::Exponential);
+    // Once it has moved, going back down picks the first shape
+    setup.step(&knobs(0, 1023));
+    assert!(setup.settings().lfo_shape == LfoShape::Triangle);
+}
+
+#[test]
+fn speed_knob_picks_each_shape() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    for (i, &shape) in LFO_SHAPES.iter().enumerate() {
+        setup.step(&knobs(zone_center(i, LFO_SHAPES.len()), LFO_SHAPE_PAGE));
+        assert!(setup.settings().lfo_shape == shape, "zone {i}");
+    }
+}
+
+#[test]
+fn preview_plays_the_selected_shape() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    // Square is the only shape that is always at one end or the other
+    let square_zone = 2 * 1024 / LFO_SHAPES.len() as u16 + 10;
+    let preview: Vec<u16> = (0..5000)
+        .map(|_| setup.step(&knobs(square_zone, LFO_SHAPE_PAGE)).unwrap())
+        // Skip the fade in
+        .skip(256)
+        .collect();
+    assert!(preview.iter().all(|&value| value == 0 || value == 4095));
+    assert!(preview.contains(&0) && preview.contains(&4095));
+}
+
+#[test]
+fn turning_texture_down_leaves_setup() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    setup.step(&knobs(300, 1023));
+    assert!(setup.step(&knobs(300, ALGORITHM_PAGE)).is_some());
+    assert!(setup.step(&knobs(300, 0)).is_none());
+    assert!(setup.settings().lfo_shape == LfoShape::Sine);
+}
+
+#[test]
+fn texture_knob_picks_the_page() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    assert!(setup.page() == SetupPage::LfoShape);
+    setup.step(&knobs(0, ALGORITHM_PAGE));
+    assert!(setup.page() == SetupPage::Algorithm);
+    setup.step(&knobs(0, TEXTURE_CV_PAGE));
+    assert!(setup.page() == SetupPage::TextureCv);
+    setup.step(&knobs(0, SPEED_CV_PAGE));
+    assert!(setup.page() == SetupPage::SpeedCv);
+    setup.step(&knobs(0, CHANNEL_B_PAGE));
+    assert!(setup.page() == SetupPage::ChannelB);
+    setup.step(&knobs(0, SEED_PAGE));
+    assert!(setup.page() == SetupPage::Seed);
+    setup.step(&knobs(0, PERLIN_OCTAVES_PAGE));
+    assert!(setup.page() == SetupPage::PerlinOctaves);
+    setup.step(&knobs(0, DISTRIBUTION_PAGE));
+    assert!(setup.page() == SetupPage::Distribution);
+}
+
+#[test]
+fn changing_pages_does_not_change_the_new_page_until_the_knob_moves() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    // Pick the last LFO shape, then go to the algorithm page without moving
+    // the speed knob
+    setup.step(&knobs(1023, LFO_SHAPE_PAGE));
+    for _ in 0..100 {
+        setup.step(&knobs(1023, ALGORITHM_PAGE));
+    }
+    assert!(setup.settings().algorithm.is_none());
+
+    setup.step(&knobs(900, ALGORITHM_PAGE));
+    assert!(setup.settings().algorithm == Some(AlgorithmKind::Lfo));
+    assert!(setup.settings().lfo_shape == LfoShape::Exponential);
+}
+
+#[test]
+fn speed_knob_picks_each_algorithm_and_cv_mode() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    setup.step(&knobs(0, ALGORITHM_PAGE));
+    // The first zone leaves it up to the switches
+    setup.step(&knobs(100, ALGORITHM_PAGE));
+    assert!(setup.settings().algorithm.is_none());
+    for (i, &kind) in ALGORITHMS.iter().enumerate() {
+        setup.step(&knobs(
+            zone_center(i + 1, ALGORITHMS.len() + 1),
+            ALGORITHM_PAGE,
+        ));
+        assert!(setup.settings().algorithm == Some(kind), "zone {i}");
+    }
+
+    setup.step(&knobs(0, TEXTURE_CV_PAGE));
+    for (i, &mode) in TEXTURE_CV_MODES.iter().enumerate() {
+        setup.step(&knobs(
+            zone_center(i, TEXTURE_CV_MODES.len()),
+            TEXTURE_CV_PAGE,
+        ));
+        assert!(setup.settings().texture_cv == mode, "zone {i}");
+    }
+
+    setup.step(&knobs(0, SPEED_CV_PAGE));
+    setup.step(&knobs(1023, SPEED_CV_PAGE));
+    assert!(setup.settings().speed_cv == SpeedCv::Clock);
+    let level = setup.step(&knobs(1023, SPEED_CV_PAGE)).unwrap();
+    assert!(setup.step(&knobs(0, SPEED_CV_PAGE)).unwrap() < level);
+    assert!(setup.settings().speed_cv == SpeedCv::Speed);
+
+    setup.step(&knobs(0, CHANNEL_B_PAGE));
+    for (i, &mode) in CHANNEL_B_MODES.iter().enumerate() {
+        setup.step(&knobs(
+            zone_center(i, CHANNEL_B_MODES.len()),
+            CHANNEL_B_PAGE,
+        ));
+        assert!(setup.settings().channel_b == mode, "zone {i}");
+    }
+
+    setup.step(&knobs(0, SEED_PAGE));
+    setup.step(&knobs(50, SEED_PAGE));
+    assert!(setup.settings().fixed_seed.is_none());
+    let num_options = FIXED_SEEDS.len() + 1;
+    for i in 0..FIXED_SEEDS.len() {
+        setup.step(&knobs(zone_center(i + 1, num_options), SEED_PAGE));
+        assert!(setup.settings().fixed_seed == Some(i as u8), "zone {i}");
+    }
+
+    setup.step(&knobs(0, PERLIN_OCTAVES_PAGE));
+    setup.step(&knobs(50, PERLIN_OCTAVES_PAGE));
+    assert!(setup.settings().perlin_octaves.is_none());
+    let num_options = MAX_OCTAVES as usize + 1;
+    for octaves in 1..=MAX_OCTAVES {
+        let i = octaves as usize;
+        setup.step(&knobs(zone_center(i, num_options), PERLIN_OCTAVES_PAGE));
+        assert!(setup.settings().perlin_octaves == Some(octaves));
+    }
+
+    setup.step(&knobs(0, DISTRIBUTION_PAGE));
+    setup.step(&knobs(50, DISTRIBUTION_PAGE));
+    assert!(setup.settings().distribution.is_none());
+    let num_options = DISTRIBUTIONS.len() + 1;
+    for (i, &distribution) in DISTRIBUTIONS.iter().enumerate() {
+        setup.step(&knobs(zone_center(i + 1, num_options), DISTRIBUTION_PAGE));
+        assert!(
+            setup.settings().distribution == Some(distribution),
+            "zone {i}"
+        );
+    }
+}
+
+#[test]
+fn seed_page_previews_each_seed() {
+    let settings = Settings {
+        algorithm: Some(AlgorithmKind::Perlin),
+        ..Settings::DEFAULT
+    };
+    let mut previews = Vec::new();
+    for i in 0..FIXED_SEEDS.len() {
+        let mut setup = Setup::new(settings, SWITCHES, 0);
+        setup.step(&knobs(0, SEED_PAGE));
+        let knob = zone_center(i + 1, FIXED_SEEDS.len() + 1);
+        let preview: Vec<u16> = (0..3000)
+            .map(|_| setup.step(&knobs(knob, SEED_PAGE)).unwrap())
+            .collect();
+        previews.push(preview);
+    }
+    for i in 1..previews.len() {
+        assert!(previews[i] != previews[i - 1]);
+    }
+}
+
+#[test]
+fn preview_does_not_jump_between_pages_or_settings() {
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    let mut last = setup.step(&knobs(0, LFO_SHAPE_PAGE)).unwrap();
+    let moves = [
+        (1023, LFO_SHAPE_PAGE),
+        (1023, ALGORITHM_PAGE),
+        (500, ALGORITHM_PAGE),
+        (100, ALGORITHM_PAGE),
+        (1023, TEXTURE_CV_PAGE),
+        (0, TEXTURE_CV_PAGE),
+        (1023, SPEED_CV_PAGE),
+        (1023, CHANNEL_B_PAGE),
+        (500, SEED_PAGE),
+        (1023, PERLIN_OCTAVES_PAGE),
+        (1023, DISTRIBUTION_PAGE),
+        (300, DISTRIBUTION_PAGE),
+        (0, LFO_SHAPE_PAGE),
+    ];
+    for (speed, texture) in moves {
+        for _ in 0..300 {
+            let value = setup.step(&knobs(speed, texture)).unwrap();
+            // The square wave preview jumps on its own, but a fade never
+            // moves more than 1/256th of the range at a time
+            if setup.settings().lfo_shape != LfoShape::Square {
+                assert!(value.abs_diff(last) <= 17, "{last} -> {value}");
+            }
+            last = value;
+        }
+    }
+}
+
+#[test]
+fn distribution_page_previews_an_algorithm_that_uses_it() {
+    // The switches pick Perlin noise, which has no distribution, so the
+    // preview switches to Bezier curves
+    let mut setup = Setup::new(Settings::DEFAULT, SWITCHES, 0);
+    setup.step(&knobs(0, DISTRIBUTION_PAGE));
+    let mut previews = Vec::new();
+    for knob in [50, 1000] {
+        let preview: Vec<u16> = (0..5000)
+            .map(|_| setup.step(&knobs(knob, DISTRIBUTION_PAGE)).unwrap())
+            .skip(256)
+            .collect();
+        previews.push(preview);
+    }
+    assert!(previews[0] != previews[1]);
+}
@@ -0,0 +1,10 @@
+use std::{env, path::PathBuf};
+
+/**
+Generates the lookup tables, which the firmware includes from `OUT_DIR`
+*/
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    lut_generator::write_luts(&lut_generator::envelope::luts(), &out_dir).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,106 +1,120 @@
-use crate::envelope::{AcrcLoopState, AcrcState, AdsrState, AhrdState, EnvelopeMode};
+use crate::envelope::{EnvelopeMode, MICROS_PER_STEP};
 
-#[derive(Clone, Copy)]
-pub enum AuxMode {
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AuxSignal {
     EndOfRise,
     EndOfFall,
     NonZero,
     FollowGate,
+    /// A short trigger each time the envelope finishes, or each time a loop
+    /// starts over
+    EndOfCycle,
+    /// A short trigger every time the envelope moves on to a new stage
+    StageChange,
 }
 
-pub fn update_aux(env_mode: &EnvelopeMode, config: &AuxMode) -> bool {
-    match config {
-        AuxMode::EndOfRise => match env_mode {
-            EnvelopeMode::Adsr(phase) => match phase {
-                AdsrState::Wait => false,
-                AdsrState::Attack => false,
-                AdsrState::Decay => true,
-                AdsrState::Sustain => true,
-                AdsrState::Release => true,
-            },
-            EnvelopeMode::Acrc(phase) => match phase {
-                AcrcState::Wait => false,
-                AcrcState::Attack => false,
-                AcrcState::Hold => true,
-                AcrcState::Release => true,
-            },
-            EnvelopeMode::AcrcLoop(phase) => match phase {
-                AcrcLoopState::Attack => false,
-                AcrcLoopState::Release => true,
-            },
-            EnvelopeMode::AhrdLoop(phase) => match phase {
-                AhrdState::Attack => false,
-                AhrdState::Hold => true,
-                AhrdState::Release => true,
-                AhrdState::Delay => false,
-            },
-        },
-        AuxMode::EndOfFall => match env_mode {
-            EnvelopeMode::Adsr(phase) => match phase {
-                AdsrState::Wait => true,
-                AdsrState::Attack => false,
-                AdsrState::Decay => false,
-                AdsrState::Sustain => false,
-                AdsrState::Release => false,
-            },
-            EnvelopeMode::Acrc(phase) => match phase {
-                AcrcState::Wait => true,
-                AcrcState::Attack => false,
-                AcrcState::Hold => false,
-                AcrcState::Release => false,
-            },
-            EnvelopeMode::AcrcLoop(phase) => match phase {
-                AcrcLoopState::Attack => true,
-                AcrcLoopState::Release => false,
-            },
-            EnvelopeMode::AhrdLoop(phase) => match phase {
-                AhrdState::Attack => false,
-                AhrdState::Hold => false,
-                AhrdState::Release => false,
-                AhrdState::Delay => true,
-            },
-        },
-        AuxMode::NonZero => match env_mode {
-            EnvelopeMode::Adsr(phase) => match phase {
-                AdsrState::Wait => false,
-                AdsrState::Attack => true,
-                AdsrState::Decay => true,
-                AdsrState::Sustain => true,
-                AdsrState::Release => true,
-            },
-            EnvelopeMode::Acrc(phase) => match phase {
-                AcrcState::Wait => false,
-                AcrcState::Attack => true,
-                AcrcState::Hold => true,
-                AcrcState::Release => true,
-            },
-            EnvelopeMode::AcrcLoop(phase) => match phase {
-                AcrcLoopState::Attack => true,
-                AcrcLoopState::Release => true,
-            },
-            EnvelopeMode::AhrdLoop(phase) => match phase {
-                AhrdState::Attack => true,
-                AhrdState::Hold => true,
-                AhrdState::Release => true,
-                AhrdState::Delay => false,
-            },
-        },
-        AuxMode::FollowGate => match env_mode {
-            EnvelopeMode::Adsr(phase) => match phase {
-                AdsrState::Wait => false,
-                AdsrState::Attack => true,
-                AdsrState::Decay => true,
-                AdsrState::Sustain => true,
-                AdsrState::Release => false,
-            },
-            EnvelopeMode::Acrc(phase) => match phase {
-                AcrcState::Wait => false,
-                AcrcState::Attack => true,
-                AcrcState::Hold => true,
-                AcrcState::Release => false,
-            },
-            EnvelopeMode::AcrcLoop(_) => false,
-            EnvelopeMode::AhrdLoop(_) => false,
-        },
+pub const AUX_SIGNALS: [AuxSignal; 6] = [
+    AuxSignal::EndOfRise,
+    AuxSignal::EndOfFall,
+    AuxSignal::NonZero,
+    AuxSignal::FollowGate,
+    AuxSignal::EndOfCycle,
+    AuxSignal::StageChange,
+];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct AuxMode {
+    pub signal: AuxSignal,
+    pub inverted: bool,
+}
+
+impl AuxMode {
+    pub const DEFAULT: Self = Self {
+        signal: AuxSignal::EndOfRise,
+        inverted: false,
+    };
+
+    /**
+    The mode picked by configuration jumpers 1 and 2, which chose it before it
+    was a setting
+    */
+    pub const fn from_jumpers(pair_1_bridged: bool, pair_2_bridged: bool) -> Self {
+        let signal = match (pair_1_bridged, pair_2_bridged) {
+            (false, false) => return Self::DEFAULT,
+            (true, false) => AuxSignal::EndOfFall,
+            (false, true) => AuxSignal::NonZero,
+            (true, true) => AuxSignal::FollowGate,
+        };
+        Self {
+            signal,
+            inverted: false,
+        }
+    }
+}
+
+// ~10ms
+const TRIGGER_LENGTH_SAMPLES: u8 = 10_000u32.div_ceil(MICROS_PER_STEP) as u8;
+
+pub struct AuxOutput {
+    pub mode: AuxMode,
+    trigger_samples_left: u8,
+}
+
+impl AuxOutput {
+    pub const fn new(mode: AuxMode) -> Self {
+        Self {
+            mode,
+            trigger_samples_left: 0,
+        }
     }
+
+    /**
+    Should be called once per sample, after the envelope has been updated.
+    Returns the new state of the aux output.
+    */
+    pub fn update(&mut self, env_mode: &EnvelopeMode, did_change_phase: bool) -> bool {
+        let stage = env_mode.stage_info();
+        let value = match self.mode.signal {
+            AuxSignal::EndOfRise => stage.past_rise,
+            AuxSignal::EndOfFall => stage.past_fall,
+            AuxSignal::NonZero => stage.nonzero,
+            AuxSignal::FollowGate => stage.gated,
+            AuxSignal::EndOfCycle => self.update_trigger(did_change_phase && stage.ends_cycle),
+            AuxSignal::StageChange => self.update_trigger(did_change_phase),
+        };
+        value != self.mode.inverted
+    }
+
+    fn update_trigger(&mut self, fire: bool) -> bool {
+        if fire {
+            self.trigger_samples_left = TRIGGER_LENGTH_SAMPLES;
+        }
+        if self.trigger_samples_left == 0 {
+            return false;
+        }
+        self.trigger_samples_left -= 1;
+        true
+    }
+}
+
+impl AuxSignal {
+    pub const fn index(self) -> u8 {
+        match self {
+            AuxSignal::EndOfRise => 0,
+            AuxSignal::EndOfFall => 1,
+            AuxSignal::NonZero => 2,
+            AuxSignal::FollowGate => 3,
+            AuxSignal::EndOfCycle => 4,
+            AuxSignal::StageChange => 5,
+        }
+    }
+}
+
+/**
+Signals are counted in binary on the first three LEDs, and the last LED shows
+whether the output is inverted
+*/
+pub const fn ui_show_aux_mode(mode: AuxMode) -> u8 {
+    let number = (mode.signal.index() + 1) | (mode.inverted as u8) << 3;
+    number << 4
 }
@@ -1,15 +1,24 @@
 mod acrc;
 mod adsr;
 mod ahrd;
+mod mseg;
 mod shared;
+mod sync;
 
 use acrc::{acrc, acrc_loop};
 use adsr::adsr;
 use ahrd::ahrd;
+use mseg::mseg;
+use shared::read_cv_level;
 
 pub use self::acrc::{AcrcLoopState, AcrcState};
-pub use self::adsr::AdsrState;
+pub use self::adsr::{AdsrCurve, AdsrState, RC_CURVATURES};
 pub use self::ahrd::AhrdState;
+pub use self::mseg::{MsegPhase, MsegPreset, MsegState};
+pub use self::shared::{MAX_PHASE_TIME_MICROS, MICROS_PER_STEP};
+pub use self::sync::{
+    synced_cycle_length, synced_stage_length, ClockSync, LoopTiming, SyncRatio, SYNC_RATIOS,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum GateState {
@@ -19,6 +28,57 @@ pub enum GateState {
     Low,
 }
 
+/**
+Describes what an envelope is doing in its current stage, so that other
+outputs can follow along without knowing the details of every mode
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct StageInfo {
+    /// Finished rising, and now holding or falling
+    pub past_rise: bool,
+    /// Finished falling, and waiting for the next rise
+    pub past_fall: bool,
+    /// Output is (or might be) above zero
+    pub nonzero: bool,
+    /// In one of the stages that play while the gate is held
+    pub gated: bool,
+    /// Entering this stage means a full cycle of the envelope has finished
+    pub ends_cycle: bool,
+}
+
+impl StageInfo {
+    pub const RESTING: Self = Self {
+        past_rise: false,
+        past_fall: true,
+        nonzero: false,
+        gated: false,
+        ends_cycle: true,
+    };
+
+    pub const RISING: Self = Self {
+        past_rise: false,
+        past_fall: false,
+        nonzero: true,
+        gated: false,
+        ends_cycle: false,
+    };
+
+    pub const FALLING: Self = Self {
+        past_rise: true,
+        past_fall: false,
+        nonzero: true,
+        gated: false,
+        ends_cycle: false,
+    };
+
+    pub const fn while_gated(self) -> Self {
+        Self {
+            gated: true,
+            ..self
+        }
+    }
+}
+
 pub struct Input {
     pub gate: GateState,
     pub trigger: bool,
@@ -29,6 +89,30 @@ pub struct EnvelopeState {
     pub time: u32,
     pub last_value: u16,
     pub artificial_gate: bool,
+    pub retrigger: RetriggerPolicy,
+    pub adsr_curve: AdsrCurve,
+    /// If set, looping modes use the gate as a clock to set their overall speed
+    pub sync: Option<SyncRatio>,
+    pub clock: ClockSync,
+    /// If set, the cv at this index sets the peak level each time the
+    /// envelope starts
+    pub velocity: Option<u8>,
+    /// Level the whole envelope is scaled down to
+    pub peak: u16,
+}
+
+/**
+What happens when the envelope is started again (by the trigger input, or by
+the gate in modes that follow it) while it is still running
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum RetriggerPolicy {
+    /// Restart the attack from the current level, without any jump
+    Continue,
+    /// Jump back to zero and restart from there
+    Reset,
+    /// Same as `Continue`, but triggers are ignored while the gate is held
+    Legato,
 }
 
 #[derive(Copy, Clone)]
@@ -37,6 +121,19 @@ pub enum EnvelopeMode {
     Acrc(AcrcState),
     AcrcLoop(AcrcLoopState),
     AhrdLoop(AhrdState),
+    Mseg(MsegState),
+}
+
+impl EnvelopeMode {
+    pub fn stage_info(&self) -> StageInfo {
+        match self {
+            EnvelopeMode::Adsr(phase) => phase.stage_info(),
+            EnvelopeMode::Acrc(phase) => phase.stage_info(),
+            EnvelopeMode::AcrcLoop(phase) => phase.stage_info(),
+            EnvelopeMode::AhrdLoop(phase) => phase.stage_info(),
+            EnvelopeMode::Mseg(state) => state.stage_info(),
+        }
+    }
 }
 
 pub const fn ui_show_mode(state: &EnvelopeMode) -> u8 {
@@ -45,6 +142,46 @@ pub const fn ui_show_mode(state: &EnvelopeMode) -> u8 {
         EnvelopeMode::Acrc(_) => 0b0100,
         EnvelopeMode::AcrcLoop(_) => 0b0010,
         EnvelopeMode::AhrdLoop(_) => 0b0001,
+        EnvelopeMode::Mseg(state) => match state.preset {
+            MsegPreset::Dahdsr => 0b1010,
+            MsegPreset::Tremolo => 0b0101,
+        },
+    }
+    .reverse_bits()
+}
+
+/**
+Shown solid (as opposed to the blinking mode display) after changing the
+retrigger policy
+*/
+pub const fn ui_show_retrigger_policy(policy: RetriggerPolicy) -> u8 {
+    match policy {
+        RetriggerPolicy::Continue => 0b0110 as u8,
+        RetriggerPolicy::Reset => 0b1001,
+        RetriggerPolicy::Legato => 0b1111,
+    }
+    .reverse_bits()
+}
+
+/**
+The first LED for linear, and the other three for each curvature of the RC
+curves, from gentlest to strongest
+*/
+pub const fn ui_show_adsr_curve(curve: AdsrCurve) -> u8 {
+    match curve {
+        AdsrCurve::Linear => 0b1000 as u8,
+        AdsrCurve::Rc(curvature) => 0b1000 >> curvature,
+    }
+    .reverse_bits()
+}
+
+/**
+Lights up the LED under the knob that controls velocity, if any
+*/
+pub const fn ui_show_velocity(velocity: Option<u8>) -> u8 {
+    match velocity {
+        None => 0b0000 as u8,
+        Some(i) => 0b1000 >> i,
     }
     .reverse_bits()
 }
@@ -74,15 +211,62 @@ pub const fn ui_show_stage(state: &EnvelopeMode) -> u8 {
             AhrdState::Release => 0b0010,
             AhrdState::Delay => 0b0001,
         },
+        // Segments are counted in binary since there can be more of them than LEDs
+        EnvelopeMode::Mseg(state) => match state.phase {
+            MsegPhase::Idle => 0b0000,
+            MsegPhase::Segment(i) | MsegPhase::Sustain(i) => (i + 1).reverse_bits() >> 4,
+        },
     }
     .reverse_bits()
 }
 
 pub fn update(state: &mut EnvelopeState, input: &Input, cv: &[u16; 4]) -> (u16, bool) {
-    let (value, rollover) = match state.mode {
-        EnvelopeMode::Adsr(ref mut phase) => {
-            adsr(phase, &mut state.time, state.last_value, input, cv)
+    let mut input = Input {
+        gate: input.gate,
+        trigger: input.trigger
+            && !(state.retrigger == RetriggerPolicy::Legato && input.gate == GateState::High),
+    };
+
+    let timing = match (state.sync, &state.mode) {
+        (Some(ratio), EnvelopeMode::AcrcLoop(_) | EnvelopeMode::AhrdLoop(_)) => {
+            // The gate is used as a clock instead of to pause the loop
+            if state.clock.tick(input.gate == GateState::Rising, ratio) {
+                input.trigger = true;
+            }
+            input.gate = GateState::Low;
+            match state.clock.cycle_length(ratio) {
+                Some(cycle_length) => LoopTiming::Synced { cycle_length },
+                None => LoopTiming::Free,
+            }
         }
+        _ => LoopTiming::Free,
+    };
+    let input = &input;
+
+    // The peak is sampled once per note, like velocity on a keyboard
+    if input.trigger || input.gate == GateState::Rising {
+        if let Some(i) = state.velocity {
+            set_peak(state, read_cv_level(cv[i as usize]));
+        }
+    }
+
+    // Every mode restarts from `last_value` to stay continuous, so pretending
+    // we were already at zero is enough to get a hard reset
+    if state.retrigger == RetriggerPolicy::Reset
+        && (input.trigger || input.gate == GateState::Rising)
+    {
+        state.last_value = 0;
+    }
+
+    let (value, rollover) = match state.mode {
+        EnvelopeMode::Adsr(ref mut phase) => adsr(
+            phase,
+            &mut state.time,
+            state.last_value,
+            input,
+            cv,
+            state.adsr_curve,
+        ),
         EnvelopeMode::Acrc(ref mut phase) => acrc(
             phase,
             &mut state.time,
@@ -91,14 +275,43 @@ pub fn update(state: &mut EnvelopeState, input: &Input, cv: &[u16; 4]) -> (u16,
             cv,
             &mut state.artificial_gate,
         ),
-        EnvelopeMode::AcrcLoop(ref mut phase) => acrc_loop(phase, &mut state.time, input, cv),
-        EnvelopeMode::AhrdLoop(ref mut phase) => ahrd(phase, &mut state.time, input, cv),
+        EnvelopeMode::AcrcLoop(ref mut phase) => {
+            acrc_loop(phase, &mut state.time, state.last_value, input, cv, timing)
+        }
+        EnvelopeMode::AhrdLoop(ref mut phase) => {
+            ahrd(phase, &mut state.time, state.last_value, input, cv, timing)
+        }
+        EnvelopeMode::Mseg(ref mut mseg_state) => {
+            mseg(mseg_state, &mut state.time, state.last_value, input, cv)
+        }
     };
 
     debug_assert!(value <= MAX_DAC_VALUE);
     state.last_value = value;
 
-    (value, rollover)
+    (scale_to_peak(value, state.peak), rollover)
+}
+
+/**
+The modes themselves always run over the full range. Scaling their output
+(rather than their targets) keeps sustain levels and curve shapes relative to
+the peak.
+*/
+fn scale_to_peak(value: u16, peak: u16) -> u16 {
+    ((value as u32 * (peak as u32 + 1)) >> 12) as u16
+}
+
+/**
+Changes the peak without making the output jump. Every mode continues from
+`last_value`, so it gets rescaled to whatever gives the same output under the
+new peak. If the output is already above the new peak, it drops down to it.
+*/
+pub fn set_peak(state: &mut EnvelopeState, peak: u16) {
+    debug_assert!(peak <= MAX_DAC_VALUE);
+    let output = scale_to_peak(state.last_value, state.peak) as u32;
+    let rescaled = (output << 12).div_ceil(peak as u32 + 1);
+    state.last_value = u32::min(rescaled, MAX_DAC_VALUE as u32) as u16;
+    state.peak = peak;
 }
 
-const MAX_DAC_VALUE: u16 = 4095;
+pub const MAX_DAC_VALUE: u16 = 4095;
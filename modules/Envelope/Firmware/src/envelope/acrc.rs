@@ -1,13 +1,12 @@
 use fixed::{types::extra::U16, FixedU16};
 
 use super::{
-    shared::{read_cv_signed_fixed, step_time},
-    GateState, Input, MAX_DAC_VALUE,
+    shared::{get_delta_t, read_cv_signed_fixed, step_time_by},
+    sync::LoopTiming,
+    GateState, Input, StageInfo, MAX_DAC_VALUE,
 };
 use crate::exponential_curves::{exp_curve, exp_curve_inverse};
 
-const CFG_ACRC_HARD_SYNC_ON_TRIGGER: bool = false;
-
 #[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub enum AcrcState {
     #[default]
@@ -17,6 +16,17 @@ pub enum AcrcState {
     Release,
 }
 
+impl AcrcState {
+    pub const fn stage_info(self) -> StageInfo {
+        match self {
+            AcrcState::Wait => StageInfo::RESTING,
+            AcrcState::Attack => StageInfo::RISING.while_gated(),
+            AcrcState::Hold => StageInfo::FALLING.while_gated(),
+            AcrcState::Release => StageInfo::FALLING,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub enum AcrcLoopState {
     #[default]
@@ -24,6 +34,21 @@ pub enum AcrcLoopState {
     Release,
 }
 
+impl AcrcLoopState {
+    pub const fn stage_info(self) -> StageInfo {
+        match self {
+            // There is no rest between loops, so the fall ends right as the
+            // next attack begins
+            AcrcLoopState::Attack => StageInfo {
+                past_fall: true,
+                ends_cycle: true,
+                ..StageInfo::RISING
+            },
+            AcrcLoopState::Release => StageInfo::FALLING,
+        }
+    }
+}
+
 pub fn acrc(
     phase: &mut AcrcState,
     time: &mut u32,
@@ -38,27 +63,22 @@ pub fn acrc(
             let (c_fixed, c_negative) = read_cv_signed_fixed(cv[1]);
             *time = get_acrc_inverse_attack(last_value, c_fixed, c_negative);
             *phase = AcrcState::Attack;
-            // We don't have time to compute the inverse AND compute a new sample
-            // in one cycle, so repeat the last value for one sample
-            (last_value, true)
+            let (value, _) = compute_acrc_value(phase, time, cv);
+            (value, true)
         }
         GateState::Falling => {
             let (c_fixed, c_negative) = read_cv_signed_fixed(cv[3]);
             *phase = AcrcState::Release;
             *time = get_acrc_inverse_release(last_value, c_fixed, c_negative);
-            (last_value, true)
+            let (value, _) = compute_acrc_value(phase, time, cv);
+            (value, true)
         }
         GateState::Low => {
             if input.trigger {
-                *time = if CFG_ACRC_HARD_SYNC_ON_TRIGGER {
-                    0
-                } else {
-                    let (c_fixed, c_negative) = read_cv_signed_fixed(cv[1]);
-                    get_acrc_inverse_attack(last_value, c_fixed, c_negative)
-                };
+                let (c_fixed, c_negative) = read_cv_signed_fixed(cv[1]);
+                *time = get_acrc_inverse_attack(last_value, c_fixed, c_negative);
                 *phase = AcrcState::Attack;
                 *artificial_gate = true;
-                return (last_value, true);
             }
 
             let (value, rollover) = compute_acrc_value(phase, time, cv);
@@ -69,7 +89,7 @@ pub fn acrc(
                 }
             }
 
-            (value, rollover)
+            (value, rollover || input.trigger)
         }
     }
 }
@@ -92,7 +112,7 @@ fn compute_acrc_value(phase: &mut AcrcState, time: &mut u32, cv: &[u16; 4]) -> (
     match phase {
         AcrcState::Wait => (0, false),
         AcrcState::Attack => {
-            let (t, rollover) = acrc_segment(time, cv[0], cv[1], false);
+            let (t, rollover) = acrc_segment(time, get_delta_t(cv[0]), cv[1], false);
             if rollover {
                 *phase = AcrcState::Hold;
             }
@@ -100,7 +120,7 @@ fn compute_acrc_value(phase: &mut AcrcState, time: &mut u32, cv: &[u16; 4]) -> (
         }
         AcrcState::Hold => (MAX_DAC_VALUE, false),
         AcrcState::Release => {
-            let (t, rollover) = acrc_segment(time, cv[2], cv[3], true);
+            let (t, rollover) = acrc_segment(time, get_delta_t(cv[2]), cv[3], true);
             if rollover {
                 *phase = AcrcState::Wait;
             }
@@ -112,26 +132,33 @@ fn compute_acrc_value(phase: &mut AcrcState, time: &mut u32, cv: &[u16; 4]) -> (
 pub fn acrc_loop(
     phase: &mut AcrcLoopState,
     time: &mut u32,
+    last_value: u16,
     input: &Input,
     cv: &[u16; 4],
+    timing: LoopTiming,
 ) -> (u16, bool) {
+    let time_cvs = [cv[0], cv[2]];
+
+    let mut did_change = false;
     if input.trigger {
-        *time = 0;
-        let did_change = *phase == AcrcLoopState::Release;
+        let (c_fixed, c_negative) = read_cv_signed_fixed(cv[1]);
+        *time = get_acrc_inverse_attack(last_value, c_fixed, c_negative);
+        did_change = *phase == AcrcLoopState::Release;
         *phase = AcrcLoopState::Attack;
-        return (0, did_change);
     }
 
-    match phase {
+    let (value, rollover) = match phase {
         AcrcLoopState::Attack => {
-            let (value, rollover) = acrc_segment(time, cv[0], cv[1], false);
+            let dt = timing.delta_t(0, &time_cvs);
+            let (value, rollover) = acrc_segment(time, dt, cv[1], false);
             if rollover {
                 *phase = AcrcLoopState::Release;
             }
             (value, rollover)
         }
         AcrcLoopState::Release => {
-            let (value, rollover) = acrc_segment(time, cv[2], cv[3], true);
+            let dt = timing.delta_t(1, &time_cvs);
+            let (value, rollover) = acrc_segment(time, dt, cv[3], true);
             if rollover && input.gate == GateState::High {
                 *time = u32::MAX;
                 return (0, false);
@@ -141,11 +168,12 @@ pub fn acrc_loop(
             }
             (value, rollover)
         }
-    }
+    };
+    (value, rollover || did_change)
 }
 
-fn acrc_segment(time: &mut u32, raw_cv_len: u16, raw_cv_c: u16, invert: bool) -> (u16, bool) {
-    let (t, rollover) = step_time(time, raw_cv_len);
+fn acrc_segment(time: &mut u32, dt: u32, raw_cv_c: u16, invert: bool) -> (u16, bool) {
+    let (t, rollover) = step_time_by(time, dt);
     let (c_fixed, c_negative) = read_cv_signed_fixed(raw_cv_c);
     let t_fixed = FixedU16::<U16>::from_bits((t >> 16) as u16);
     let value = exp_curve(t_fixed, c_fixed, c_negative);
@@ -1,7 +1,10 @@
+use fixed::{types::extra::U16, FixedU16};
+
 use super::{
-    shared::{lerp, read_cv, step_time, CvType},
-    GateState, Input, MAX_DAC_VALUE,
+    shared::{lerp, read_cv_level, step_time},
+    GateState, Input, StageInfo, MAX_DAC_VALUE,
 };
+use crate::exponential_curves::{exp_curve, exp_curve_inverse};
 
 #[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub enum AdsrState {
@@ -13,53 +16,135 @@ pub enum AdsrState {
     Release,
 }
 
+impl AdsrState {
+    pub const fn stage_info(self) -> StageInfo {
+        match self {
+            AdsrState::Wait => StageInfo::RESTING,
+            AdsrState::Attack => StageInfo::RISING.while_gated(),
+            AdsrState::Decay | AdsrState::Sustain => StageInfo::FALLING.while_gated(),
+            AdsrState::Release => StageInfo::FALLING,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AdsrCurve {
+    Linear,
+    /**
+    Mimics an analog envelope built around charging and discharging a
+    capacitor: every stage moves quickly at first and then slows down as it
+    approaches its target. Holds how strongly the stages curve, from 1 to
+    `RC_CURVATURES`.
+    */
+    Rc(u8),
+}
+
+pub const RC_CURVATURES: u8 = 3;
+
+// An analog attack charges towards a voltage above the peak and gets cut off
+// when it reaches the peak, so it is only gently curved
+const RC_ATTACK_CURVE: u16 = 0x1a00;
+// Decay and release discharge all the way, leaving a long tail
+const RC_FALL_CURVE: u16 = 0x6000;
+
+/**
+The `exp_curve` amounts for the attack and for the decay and release. The
+gentlest curvature is the classic analog shape, and each step up bends the
+stages half as much again, as if the capacitor charged toward a higher voltage.
+*/
+fn rc_curves(curvature: u8) -> (FixedU16<U16>, FixedU16<U16>) {
+    debug_assert!((1..=RC_CURVATURES).contains(&curvature));
+    let steps = curvature.saturating_sub(1) as u16;
+    (
+        FixedU16::<U16>::from_bits(RC_ATTACK_CURVE + (RC_ATTACK_CURVE / 2) * steps),
+        FixedU16::<U16>::from_bits(RC_FALL_CURVE + (RC_FALL_CURVE / 2) * steps),
+    )
+}
+
 pub fn adsr(
     phase: &mut AdsrState,
     time: &mut u32,
     last_value: u16,
     input: &Input,
     cv: &[u16; 4],
+    curve: AdsrCurve,
 ) -> (u16, bool) {
+    let start_stage = |phase: &mut AdsrState, time: &mut u32| {
+        let (value, _) = compute_adsr_value(phase, time, cv, curve);
+        (value, true)
+    };
+
     if input.trigger && (*phase == AdsrState::Decay || *phase == AdsrState::Sustain) {
-        *time = get_adsr_inverse_attack(last_value);
+        *time = get_adsr_inverse_attack(last_value, curve);
         *phase = AdsrState::Attack;
-        let (value, _) = compute_adsr_value(phase, time, cv);
-        return (value, true);
+        return start_stage(phase, time);
     }
 
     match input.gate {
-        GateState::High | GateState::Low => compute_adsr_value(phase, time, cv),
+        GateState::High | GateState::Low => compute_adsr_value(phase, time, cv, curve),
         GateState::Rising => {
-            *time = get_adsr_inverse_attack(last_value);
+            *time = get_adsr_inverse_attack(last_value, curve);
             *phase = AdsrState::Attack;
-            let (value, _) = compute_adsr_value(phase, time, cv);
-            (value, true)
+            start_stage(phase, time)
         }
         GateState::Falling => {
             *phase = AdsrState::Release;
-            *time = get_adsr_inverse_release(last_value);
-            let (value, _) = compute_adsr_value(phase, time, cv);
-            (value, true)
+            *time = get_adsr_inverse_release(last_value, curve);
+            start_stage(phase, time)
         }
     }
 }
 
-fn get_adsr_inverse_attack(current_value: u16) -> u32 {
-    (current_value as u32) << 20
+fn get_adsr_inverse_attack(current_value: u16, curve: AdsrCurve) -> u32 {
+    match curve {
+        AdsrCurve::Linear => (current_value as u32) << 20,
+        AdsrCurve::Rc(curvature) => {
+            let (attack_curve, _) = rc_curves(curvature);
+            let value_frac = FixedU16::<U16>::from_bits(current_value << 4);
+            let x_frac = exp_curve_inverse(value_frac, attack_curve, true);
+            (x_frac.to_bits() as u32) << 16
+        }
+    }
 }
 
-fn get_adsr_inverse_release(current_value: u16) -> u32 {
-    ((MAX_DAC_VALUE - current_value) as u32) << 20
+fn get_adsr_inverse_release(current_value: u16, curve: AdsrCurve) -> u32 {
+    let fallen = MAX_DAC_VALUE - current_value;
+    match curve {
+        AdsrCurve::Linear => (fallen as u32) << 20,
+        AdsrCurve::Rc(curvature) => {
+            let (_, fall_curve) = rc_curves(curvature);
+            let value_frac = FixedU16::<U16>::from_bits(fallen << 4);
+            let x_frac = exp_curve_inverse(value_frac, fall_curve, true);
+            (x_frac.to_bits() as u32) << 16
+        }
+    }
 }
 
-fn compute_adsr_value(phase: &mut AdsrState, time: &mut u32, cv: &[u16; 4]) -> (u16, bool) {
-    let scale = |input: u32| (input >> 20) as u16;
-    let get_sustain = || {
-        let cv_frac = read_cv::<{ CvType::Linear }>(cv[2]);
-        let scaled = ((cv_frac.numerator as u32 * (MAX_DAC_VALUE + 1) as u32)
-            / cv_frac.denominator as u32) as u16;
-        u16::min(scaled, MAX_DAC_VALUE)
-    };
+/**
+How far through the stage's change in level the envelope should be, as a
+fraction of u16::MAX. `rising` picks the attack's curve rather than the
+decay and release's.
+*/
+fn stage_progress(t: u32, rising: bool, curve: AdsrCurve) -> u16 {
+    match curve {
+        AdsrCurve::Linear => (t >> 16) as u16,
+        AdsrCurve::Rc(curvature) => {
+            let (attack_curve, fall_curve) = rc_curves(curvature);
+            let rc_curve = if rising { attack_curve } else { fall_curve };
+            let t_fixed = FixedU16::<U16>::from_bits((t >> 16) as u16);
+            exp_curve(t_fixed, rc_curve, true) << 4
+        }
+    }
+}
+
+fn compute_adsr_value(
+    phase: &mut AdsrState,
+    time: &mut u32,
+    cv: &[u16; 4],
+    curve: AdsrCurve,
+) -> (u16, bool) {
+    let scale = |progress: u16| progress >> 4;
+    let get_sustain = || read_cv_level(cv[2]);
 
     match phase {
         AdsrState::Wait => (0, false),
@@ -69,7 +154,7 @@ fn compute_adsr_value(phase: &mut AdsrState, time: &mut u32, cv: &[u16; 4]) -> (
                 *phase = AdsrState::Decay;
                 // TODO skip decay if sustain is maxed or decay is 0
             }
-            (scale(t), rollover)
+            (scale(stage_progress(t, true, curve)), rollover)
         }
         AdsrState::Decay => {
             let (t, rollover) = step_time(time, cv[1]);
@@ -77,7 +162,8 @@ fn compute_adsr_value(phase: &mut AdsrState, time: &mut u32, cv: &[u16; 4]) -> (
                 *phase = AdsrState::Sustain;
             }
             let sustain = get_sustain();
-            let scaled = lerp((t >> 16) as u16, sustain, MAX_DAC_VALUE);
+            let progress = stage_progress(t, false, curve);
+            let scaled = lerp(progress, sustain, MAX_DAC_VALUE);
             (sustain + (MAX_DAC_VALUE - scaled), rollover)
         }
         AdsrState::Sustain => (get_sustain(), false),
@@ -86,7 +172,8 @@ fn compute_adsr_value(phase: &mut AdsrState, time: &mut u32, cv: &[u16; 4]) -> (
             if rollover {
                 *phase = AdsrState::Wait;
             }
-            (MAX_DAC_VALUE.saturating_sub(scale(t)), rollover)
+            let progress = stage_progress(t, false, curve);
+            (MAX_DAC_VALUE.saturating_sub(scale(progress)), rollover)
         }
     }
 }
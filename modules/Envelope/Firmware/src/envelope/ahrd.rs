@@ -1,6 +1,7 @@
 use super::{
-    shared::{step_time, step_time_no_rollover},
-    GateState, Input,
+    shared::{step_time_by, step_time_no_rollover_by},
+    sync::LoopTiming,
+    GateState, Input, StageInfo,
 };
 
 #[derive(Copy, Clone, Default, PartialEq, Eq)]
@@ -12,33 +13,57 @@ pub enum AhrdState {
     Delay,
 }
 
-pub fn ahrd(phase: &mut AhrdState, time: &mut u32, input: &Input, cv: &[u16; 4]) -> (u16, bool) {
+impl AhrdState {
+    pub const fn stage_info(self) -> StageInfo {
+        match self {
+            AhrdState::Attack => StageInfo {
+                ends_cycle: true,
+                ..StageInfo::RISING
+            },
+            AhrdState::Hold | AhrdState::Release => StageInfo::FALLING,
+            AhrdState::Delay => StageInfo {
+                ends_cycle: false,
+                ..StageInfo::RESTING
+            },
+        }
+    }
+}
+
+pub fn ahrd(
+    phase: &mut AhrdState,
+    time: &mut u32,
+    last_value: u16,
+    input: &Input,
+    cv: &[u16; 4],
+    timing: LoopTiming,
+) -> (u16, bool) {
     let scale = |x: u32| (x >> 20) as u16;
 
     if input.trigger {
-        *time = 0;
+        // Attack is linear, so its inverse is just the reverse of `scale`
+        *time = (last_value as u32) << 20;
         let did_change = *phase != AhrdState::Attack;
         *phase = AhrdState::Attack;
-        return (0, did_change);
+        return (last_value, did_change);
     }
 
     match phase {
         AhrdState::Attack => {
-            let (t, rollover) = step_time(time, cv[0]);
+            let (t, rollover) = step_time_by(time, timing.delta_t(0, cv));
             if rollover {
                 *phase = AhrdState::Hold;
             }
             (scale(t), rollover)
         }
         AhrdState::Hold => {
-            let (_, rollover) = step_time(time, cv[1]);
+            let (_, rollover) = step_time_by(time, timing.delta_t(1, cv));
             if rollover {
                 *phase = AhrdState::Release;
             }
             (scale(u32::MAX), rollover)
         }
         AhrdState::Release => {
-            let (t, rollover) = step_time(time, cv[2]);
+            let (t, rollover) = step_time_by(time, timing.delta_t(2, cv));
             if rollover {
                 *phase = AhrdState::Delay
             }
@@ -46,10 +71,10 @@ pub fn ahrd(phase: &mut AhrdState, time: &mut u32, input: &Input, cv: &[u16; 4])
         }
         AhrdState::Delay => {
             if input.gate == GateState::High {
-                step_time_no_rollover(time, cv[3]);
+                step_time_no_rollover_by(time, timing.delta_t(3, cv));
                 return (0, false);
             };
-            let (_, rollover) = step_time(time, cv[3]);
+            let (_, rollover) = step_time_by(time, timing.delta_t(3, cv));
             if rollover {
                 *phase = AhrdState::Attack;
             }
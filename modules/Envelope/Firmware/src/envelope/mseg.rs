@@ -0,0 +1,363 @@
+use fixed::{types::extra::U16, FixedU16};
+
+use super::{
+    shared::{
+        get_delta_t, get_delta_t_for_millis, read_cv_level, read_cv_signed_fixed, step_time_by,
+    },
+    GateState, Input, StageInfo, MAX_DAC_VALUE,
+};
+use crate::exponential_curves::exp_curve;
+
+/**
+The level a segment moves towards
+*/
+#[derive(Copy, Clone)]
+pub enum SegmentLevel {
+    Fixed(u16),
+    /// Set by the knob/CV at the given index, from 0 to `MAX_DAC_VALUE`
+    Cv(u8),
+    /// Stay at whatever level the segment started at (e.g. for delay or hold)
+    Previous,
+}
+
+/**
+How long it takes a segment to reach its level
+*/
+#[derive(Copy, Clone)]
+pub enum SegmentTime {
+    /// Set by the knob/CV at the given index, with the same range and response
+    /// as the stages in the other modes
+    Cv(u8),
+    Millis(u16),
+}
+
+#[derive(Copy, Clone)]
+pub enum SegmentCurve {
+    Linear,
+    /// Curvature set by the knob/CV at the given index, bending from
+    /// logarithmic through linear to exponential like in ACRC mode
+    Cv(u8),
+    Fixed {
+        c: FixedU16<U16>,
+        negative: bool,
+    },
+}
+
+#[derive(Copy, Clone)]
+pub struct Segment {
+    pub level: SegmentLevel,
+    pub time: SegmentTime,
+    pub curve: SegmentCurve,
+}
+
+/**
+A range of segments (inclusive) to repeat. If `while_gate_high` is set, the
+loop only repeats while the gate is held and releasing the gate moves on to the
+segment after the loop. Otherwise, it repeats forever until the next gate or
+trigger restarts the envelope.
+*/
+#[derive(Copy, Clone)]
+pub struct SegmentLoop {
+    pub start: u8,
+    pub end: u8,
+    pub while_gate_high: bool,
+}
+
+/**
+A multi-segment envelope. Each segment moves from wherever the previous one
+left off towards its own level, so the output stays continuous no matter when
+the envelope is re-gated or released.
+
+If there is a sustain point, the envelope will stop at the end of that segment
+while the gate is high, and releasing the gate at any point before then skips
+straight to the following segment.
+*/
+pub struct MsegShape {
+    pub segments: &'static [Segment],
+    pub sustain: Option<u8>,
+    pub segment_loop: Option<SegmentLoop>,
+}
+
+impl MsegShape {
+    /**
+    The segment to jump to when the gate is released, if releasing the gate
+    does anything for this shape
+    */
+    fn release_segment(&self) -> Option<u8> {
+        match (self.sustain, self.segment_loop) {
+            (Some(sustain), _) => Some(sustain + 1),
+            (None, Some(segment_loop)) if segment_loop.while_gate_high => {
+                Some(segment_loop.end + 1)
+            }
+            _ => None,
+        }
+    }
+}
+
+const DAHDSR_DELAY_MS: u16 = 20;
+const DAHDSR_HOLD_MS: u16 = 100;
+
+/**
+Traditional ADSR with the same controls as ADSR mode, plus a short fixed
+delay before the attack and hold at the peak
+*/
+const DAHDSR: MsegShape = MsegShape {
+    segments: &[
+        Segment {
+            level: SegmentLevel::Previous,
+            time: SegmentTime::Millis(DAHDSR_DELAY_MS),
+            curve: SegmentCurve::Linear,
+        },
+        Segment {
+            level: SegmentLevel::Fixed(MAX_DAC_VALUE),
+            time: SegmentTime::Cv(0),
+            curve: SegmentCurve::Linear,
+        },
+        Segment {
+            level: SegmentLevel::Previous,
+            time: SegmentTime::Millis(DAHDSR_HOLD_MS),
+            curve: SegmentCurve::Linear,
+        },
+        Segment {
+            level: SegmentLevel::Cv(2),
+            time: SegmentTime::Cv(1),
+            curve: SegmentCurve::Linear,
+        },
+        Segment {
+            level: SegmentLevel::Fixed(0),
+            time: SegmentTime::Cv(3),
+            curve: SegmentCurve::Linear,
+        },
+    ],
+    sustain: Some(3),
+    segment_loop: None,
+};
+
+/**
+Attack to full, then wobble between 1/2 and 3/4 while the gate is held, then
+release. Knobs are attack time, curve (for all segments), wobble time and
+release time.
+*/
+const TREMOLO: MsegShape = MsegShape {
+    segments: &[
+        Segment {
+            level: SegmentLevel::Fixed(MAX_DAC_VALUE),
+            time: SegmentTime::Cv(0),
+            curve: SegmentCurve::Cv(1),
+        },
+        Segment {
+            level: SegmentLevel::Fixed(MAX_DAC_VALUE / 2),
+            time: SegmentTime::Cv(2),
+            curve: SegmentCurve::Cv(1),
+        },
+        Segment {
+            level: SegmentLevel::Fixed(MAX_DAC_VALUE / 4 * 3),
+            time: SegmentTime::Cv(2),
+            curve: SegmentCurve::Cv(1),
+        },
+        Segment {
+            level: SegmentLevel::Fixed(0),
+            time: SegmentTime::Cv(3),
+            curve: SegmentCurve::Cv(1),
+        },
+    ],
+    sustain: None,
+    segment_loop: Some(SegmentLoop {
+        start: 1,
+        end: 2,
+        while_gate_high: true,
+    }),
+};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MsegPreset {
+    Dahdsr,
+    Tremolo,
+}
+
+impl MsegPreset {
+    pub fn shape(self) -> &'static MsegShape {
+        match self {
+            MsegPreset::Dahdsr => &DAHDSR,
+            MsegPreset::Tremolo => &TREMOLO,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MsegPhase {
+    Idle,
+    Segment(u8),
+    Sustain(u8),
+}
+
+#[derive(Copy, Clone)]
+pub struct MsegState {
+    pub preset: MsegPreset,
+    pub phase: MsegPhase,
+    start_level: u16,
+    rising: bool,
+    silent: bool,
+}
+
+impl MsegState {
+    pub const fn new(preset: MsegPreset) -> Self {
+        Self {
+            preset,
+            phase: MsegPhase::Idle,
+            start_level: 0,
+            rising: false,
+            silent: true,
+        }
+    }
+
+    fn start_segment(&mut self, index: u8, time: &mut u32, start_level: u16, cv: &[u16; 4]) {
+        let segments = self.preset.shape().segments;
+        *time = 0;
+        self.start_level = start_level;
+        if index as usize >= segments.len() {
+            self.phase = MsegPhase::Idle;
+            self.rising = false;
+            self.silent = start_level == 0;
+            return;
+        }
+        self.phase = MsegPhase::Segment(index);
+        let target = read_level(&segments[index as usize].level, start_level, cv);
+        self.rising = target > start_level;
+        self.silent = start_level == 0 && target == 0;
+    }
+
+    pub fn stage_info(&self) -> StageInfo {
+        let shape = self.preset.shape();
+        let idle = self.phase == MsegPhase::Idle;
+        let segment = match self.phase {
+            MsegPhase::Idle => None,
+            MsegPhase::Segment(i) | MsegPhase::Sustain(i) => Some(i),
+        };
+        StageInfo {
+            past_rise: !idle && !self.rising && !self.silent,
+            past_fall: idle,
+            // This can lag behind the actual output for segments whose level
+            // is set by a knob
+            nonzero: !idle && !self.silent,
+            gated: match (segment, shape.release_segment()) {
+                (Some(i), Some(release_segment)) => i < release_segment,
+                _ => false,
+            },
+            ends_cycle: match (segment, shape.segment_loop) {
+                (None, _) => true,
+                (Some(i), Some(segment_loop)) => i == segment_loop.start,
+                (Some(_), None) => false,
+            },
+        }
+    }
+}
+
+fn read_level(level: &SegmentLevel, start_level: u16, cv: &[u16; 4]) -> u16 {
+    match level {
+        SegmentLevel::Fixed(value) => *value,
+        SegmentLevel::Cv(i) => read_cv_level(cv[*i as usize]),
+        SegmentLevel::Previous => start_level,
+    }
+}
+
+fn get_segment_delta_t(time: &SegmentTime, cv: &[u16; 4]) -> u32 {
+    match time {
+        SegmentTime::Cv(i) => get_delta_t(cv[*i as usize]),
+        SegmentTime::Millis(millis) => get_delta_t_for_millis(*millis),
+    }
+}
+
+/**
+Maps the time through the segment to how far along the segment the output
+should be, from 0 to 4095
+*/
+fn apply_curve(curve: &SegmentCurve, t: u32, cv: &[u16; 4]) -> u16 {
+    let t_fixed = FixedU16::<U16>::from_bits((t >> 16) as u16);
+    match curve {
+        SegmentCurve::Linear => (t >> 20) as u16,
+        SegmentCurve::Cv(i) => {
+            let (c_fixed, c_negative) = read_cv_signed_fixed(cv[*i as usize]);
+            exp_curve(t_fixed, c_fixed, c_negative)
+        }
+        SegmentCurve::Fixed { c, negative } => exp_curve(t_fixed, *c, *negative),
+    }
+}
+
+fn interpolate(from: u16, to: u16, fraction: u16) -> u16 {
+    if to >= from {
+        from + (((to - from) as u32 * fraction as u32) >> 12) as u16
+    } else {
+        from - (((from - to) as u32 * fraction as u32) >> 12) as u16
+    }
+}
+
+pub fn mseg(
+    state: &mut MsegState,
+    time: &mut u32,
+    last_value: u16,
+    input: &Input,
+    cv: &[u16; 4],
+) -> (u16, bool) {
+    let shape = state.preset.shape();
+    let mut did_change_phase = false;
+
+    if input.trigger || input.gate == GateState::Rising {
+        state.start_segment(0, time, last_value, cv);
+        did_change_phase = true;
+    } else if input.gate == GateState::Falling {
+        if let Some(release_segment) = shape.release_segment() {
+            if state.stage_info().gated {
+                state.start_segment(release_segment, time, last_value, cv);
+                did_change_phase = true;
+            }
+        }
+    }
+
+    let index = match state.phase {
+        MsegPhase::Idle => return (state.start_level, did_change_phase),
+        MsegPhase::Sustain(i) => {
+            let level = &shape.segments[i as usize].level;
+            return (read_level(level, state.start_level, cv), did_change_phase);
+        }
+        MsegPhase::Segment(i) => i,
+    };
+
+    let segment = &shape.segments[index as usize];
+    let (t, rollover) = step_time_by(time, get_segment_delta_t(&segment.time, cv));
+    let target = read_level(&segment.level, state.start_level, cv);
+    // The curve tops out one step short of 1.0, so make sure each segment
+    // actually lands on its level
+    let value = if rollover {
+        target
+    } else {
+        interpolate(
+            state.start_level,
+            target,
+            apply_curve(&segment.curve, t, cv),
+        )
+    };
+
+    if rollover {
+        let gate_is_high = input.gate == GateState::High;
+        let next_segment = match shape.segment_loop {
+            _ if shape.sustain == Some(index) && gate_is_high => None,
+            Some(segment_loop)
+                if segment_loop.end == index && (gate_is_high || !segment_loop.while_gate_high) =>
+            {
+                Some(segment_loop.start)
+            }
+            _ => Some(index + 1),
+        };
+        match next_segment {
+            Some(next) => state.start_segment(next, time, value, cv),
+            None => {
+                state.start_level = value;
+                state.phase = MsegPhase::Sustain(index);
+                state.rising = false;
+            }
+        }
+    }
+
+    (value, did_change_phase || rollover)
+}
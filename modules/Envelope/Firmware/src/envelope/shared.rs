@@ -1,6 +1,8 @@
 use fixed::{types::extra::U16, FixedU16};
 use core::marker::ConstParamTy;
 
+use super::MAX_DAC_VALUE;
+
 #[derive(Copy, Clone)]
 pub struct Fraction<T> {
     pub numerator: T,
@@ -26,15 +28,17 @@ pub fn read_cv<const CURVE: CvType>(cv: u16) -> Fraction<u16> {
     // CV is inverted in hardware; correct for that here
     let x = MAX_ADC_VALUE.saturating_sub(cv);
 
+    // The exponential curve is scaled up by 4 so that the slow part at the
+    // bottom of the range doesn't lose any resolution to rounding
     let numerator = match CURVE {
         CvType::Linear => x,
         CvType::Exponential => {
             if x < 512 {
-                x / 4
+                x
             } else if x < 768 {
-                x - 384
+                4 * x - 1536
             } else {
-                3 * x - 1920
+                12 * x - 7680
             }
         }
     };
@@ -42,8 +46,8 @@ pub fn read_cv<const CURVE: CvType>(cv: u16) -> Fraction<u16> {
     let denominator = match CURVE {
         CvType::Linear => MAX_ADC_VALUE,
         // the piecewise function isn't perfect, the range is a little larger
-        // than the domain. It actually goes to 1011. Round to 1024 for performance
-        CvType::Exponential => 1024,
+        // than the domain. It actually goes to 4044. Round to 4096 for performance
+        CvType::Exponential => 4096,
     };
 
     Fraction {
@@ -71,24 +75,60 @@ pub fn read_cv_signed_fixed(cv: u16) -> (FixedU16<U16>, bool) {
     }
 }
 
-fn get_delta_t(cv: u16) -> u32 {
-    // 10 seconds
-    const MAX_PHASE_TIME_MICROS: u32 = 10 * 1000 * 1000;
-    // ~2.27kHz == .48 ms / period
-    const MICROS_PER_STEP: u32 = 480;
-    const MAX_STEPS_PER_CYCLE: u16 = (MAX_PHASE_TIME_MICROS / MICROS_PER_STEP) as u16;
+/**
+Reads a cv as a level anywhere from 0 up to the DAC's full range
+*/
+pub fn read_cv_level(cv: u16) -> u16 {
+    let cv_frac = read_cv::<{ CvType::Linear }>(cv);
+    let scaled = ((cv_frac.numerator as u32 * (MAX_DAC_VALUE + 1) as u32)
+        / cv_frac.denominator as u32) as u16;
+    u16::min(scaled, MAX_DAC_VALUE)
+}
+
+/**
+Time between envelope steps, which is also the shortest a stage can be:
+~2.27kHz == .48 ms / period. Everything else is worked out from this, but a
+shorter step has to be measured with the `profile` build first, see
+`docs/profiling.md`.
+*/
+pub const MICROS_PER_STEP: u32 = 480;
+
+// The timer that runs the envelope counts in steps of 4us, up to 256 steps
+const _: () = assert!(MICROS_PER_STEP & 0b11 == 0 && MICROS_PER_STEP / 4 <= 256);
+
+// 10 seconds
+pub const MAX_PHASE_TIME_MICROS: u32 = 10 * 1000 * 1000;
+pub const MAX_STEPS_PER_CYCLE: u32 = MAX_PHASE_TIME_MICROS / MICROS_PER_STEP;
+
+/**
+The number of samples a phase controlled by the given cv should last
+*/
+pub fn get_steps_per_cycle(cv: u16) -> u32 {
     let cv_fraction = read_cv::<{ CvType::Exponential }>(cv);
-    let mut actual_steps_per_cycle = (cv_fraction.numerator as u32 * MAX_STEPS_PER_CYCLE as u32)
-        / cv_fraction.denominator as u32;
-    if actual_steps_per_cycle == 0 {
-        actual_steps_per_cycle = 1;
-    }
+    let actual_steps_per_cycle =
+        (cv_fraction.numerator as u32 * MAX_STEPS_PER_CYCLE) / cv_fraction.denominator as u32;
+    u32::max(1, actual_steps_per_cycle)
+}
+
+pub fn get_delta_t(cv: u16) -> u32 {
+    u32::MAX / get_steps_per_cycle(cv)
+}
 
-    u32::MAX / actual_steps_per_cycle
+/**
+Like `get_delta_t` but for a phase with a fixed length instead of one set by a
+knob. Always advances by at least one step, so a length of 0 finishes the phase
+on the very next sample.
+*/
+pub fn get_delta_t_for_millis(millis: u16) -> u32 {
+    let steps_per_cycle = u32::max(1, millis as u32 * 1000 / MICROS_PER_STEP);
+    u32::MAX / steps_per_cycle
 }
 
 pub fn step_time(t: &mut u32, cv: u16) -> (u32, bool) {
-    let dt = get_delta_t(cv);
+    step_time_by(t, get_delta_t(cv))
+}
+
+pub fn step_time_by(t: &mut u32, dt: u32) -> (u32, bool) {
     *t = t.saturating_add(dt);
     let rollover = *t == u32::MAX;
     let before_rollover = *t;
@@ -98,8 +138,7 @@ pub fn step_time(t: &mut u32, cv: u16) -> (u32, bool) {
     (before_rollover, rollover)
 }
 
-pub fn step_time_no_rollover(t: &mut u32, cv: u16) -> u32 {
-    let dt = get_delta_t(cv);
+pub fn step_time_no_rollover_by(t: &mut u32, dt: u32) -> u32 {
     *t = t.saturating_add(dt);
     *t
 }
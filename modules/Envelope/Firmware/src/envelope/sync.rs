@@ -0,0 +1,157 @@
+use super::shared::{get_delta_t, get_steps_per_cycle, MAX_STEPS_PER_CYCLE};
+
+// Clock edges further apart than the longest possible phase are treated as the
+// clock having stopped
+const MAX_CLOCK_PERIOD: u32 = MAX_STEPS_PER_CYCLE;
+// Keeps the stage proportion math within a u32
+const MAX_CYCLE_LENGTH: u32 = 1 << 18;
+
+/**
+Length of one loop relative to the clock: the loop runs `cycles` times for
+every `clocks` clock pulses
+*/
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SyncRatio {
+    pub clocks: u8,
+    pub cycles: u8,
+}
+
+impl SyncRatio {
+    pub const fn new(clocks: u8, cycles: u8) -> Self {
+        Self { clocks, cycles }
+    }
+}
+
+pub const SYNC_RATIOS: [SyncRatio; 7] = [
+    SyncRatio::new(1, 4),
+    SyncRatio::new(1, 3),
+    SyncRatio::new(1, 2),
+    SyncRatio::new(1, 1),
+    SyncRatio::new(2, 1),
+    SyncRatio::new(3, 1),
+    SyncRatio::new(4, 1),
+];
+
+/**
+Measures the time between rising edges of a clock and keeps track of which
+edges line up with the start of a loop
+*/
+pub struct ClockSync {
+    samples_since_edge: u32,
+    period: Option<u32>,
+    edges_until_sync: u8,
+}
+
+impl ClockSync {
+    pub const fn new() -> Self {
+        Self {
+            // The first edge shouldn't count as the end of a (very short) period
+            samples_since_edge: u32::MAX,
+            period: None,
+            edges_until_sync: 0,
+        }
+    }
+
+    /**
+    Should be called once per sample. Returns true if the loop should restart
+    on this sample to stay in phase with the clock.
+    */
+    pub fn tick(&mut self, rising_edge: bool, ratio: SyncRatio) -> bool {
+        self.samples_since_edge = self.samples_since_edge.saturating_add(1);
+        if !rising_edge {
+            if self.samples_since_edge > MAX_CLOCK_PERIOD {
+                self.period = None;
+            }
+            return false;
+        }
+
+        self.period = if self.samples_since_edge <= MAX_CLOCK_PERIOD {
+            Some(self.samples_since_edge)
+        } else {
+            None
+        };
+        self.samples_since_edge = 0;
+
+        if self.edges_until_sync <= 1 || self.edges_until_sync > ratio.clocks {
+            self.edges_until_sync = ratio.clocks;
+            true
+        } else {
+            self.edges_until_sync -= 1;
+            false
+        }
+    }
+
+    /**
+    The measured time between the last two clock pulses, in samples
+    */
+    pub fn period(&self) -> Option<u32> {
+        self.period
+    }
+
+    /**
+    How many samples one full loop should take, if the clock is running
+    */
+    pub fn cycle_length(&self, ratio: SyncRatio) -> Option<u32> {
+        self.period
+            .map(|period| synced_cycle_length(period, ratio).clamp(1, MAX_CYCLE_LENGTH))
+    }
+}
+
+pub fn synced_cycle_length(clock_period: u32, ratio: SyncRatio) -> u32 {
+    clock_period * ratio.clocks as u32 / ratio.cycles as u32
+}
+
+/**
+How fast each stage of a looping mode should run
+*/
+#[derive(Copy, Clone)]
+pub enum LoopTiming {
+    /// Stage lengths come straight from their knobs
+    Free,
+    /// Stages are stretched or squashed so they add up to this many samples,
+    /// keeping the proportions set by their knobs
+    Synced { cycle_length: u32 },
+}
+
+impl LoopTiming {
+    /**
+    `time_cvs` are the raw cv values that control the length of each stage of
+    the loop, in order
+    */
+    pub fn delta_t(&self, stage: usize, time_cvs: &[u16]) -> u32 {
+        match *self {
+            LoopTiming::Free => get_delta_t(time_cvs[stage]),
+            LoopTiming::Synced { cycle_length } => {
+                let total_steps = time_cvs.iter().map(|cv| get_steps_per_cycle(*cv)).sum();
+                let steps = synced_stage_length(
+                    get_steps_per_cycle(time_cvs[stage]),
+                    total_steps,
+                    cycle_length,
+                );
+                u32::MAX / steps
+            }
+        }
+    }
+}
+
+/**
+Scales a stage that would normally take `stage_steps` out of a loop of
+`total_steps` to fit in a loop of `cycle_length`. Never returns 0.
+*/
+pub fn synced_stage_length(stage_steps: u32, total_steps: u32, cycle_length: u32) -> u32 {
+    debug_assert!(stage_steps <= total_steps);
+    debug_assert!(cycle_length <= MAX_CYCLE_LENGTH);
+    let product_bits = 64 - stage_steps.leading_zeros() - cycle_length.leading_zeros();
+    let length = if product_bits <= 32 {
+        stage_steps * cycle_length / total_steps
+    } else {
+        // At fast step rates a long stage times a long cycle can overflow, so
+        // multiply by the top bits of the cycle length first and then carry
+        // the remainder over into the bottom bits
+        let shift = product_bits - 32;
+        let high = stage_steps * (cycle_length >> shift);
+        let low = stage_steps * (cycle_length & ((1 << shift) - 1));
+        ((high / total_steps) << shift) + (((high % total_steps) << shift) + low) / total_steps
+    };
+    u32::max(1, length)
+}
@@ -1,20 +1,15 @@
 use avr_progmem::{progmem, wrapper::ProgMem};
-use fixed::{types::extra::U16, FixedU16, FixedU32};
+use fixed::{types::extra::U16, FixedU16};
 
 progmem! {
-    pub static progmem EXP_LUT: [u8; LUT_SIZE * U32_BYTES] = *include_bytes!("../exp2lut.bin");
-    pub static progmem LOG_LUT: [u8; LUT_SIZE * U16_BYTES] = *include_bytes!("../log2lut.bin");
+    pub static progmem EXP_LUT: [u8; LUT_SIZE * U16_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/exp2lut.bin"));
+    pub static progmem LOG_LUT: [u8; LUT_SIZE * U16_BYTES] = *include_bytes!(concat!(env!("OUT_DIR"), "/log2lut.bin"));
 }
 
 const LUT_SIZE: usize = 256;
-const U32_BYTES: usize = u32::BITS as usize / 8;
 const U16_BYTES: usize = u16::BITS as usize / 8;
 
-fn lut_load_fixed32(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U32_BYTES]>) -> FixedU32<U16> {
-    debug_assert!(i < lut.len() / U32_BYTES);
-    let bytes: [u8; U32_BYTES] = lut.load_sub_array::<U32_BYTES>(U32_BYTES * i);
-    FixedU32::<U16>::from_le_bytes(bytes)
-}
+const ONE: u32 = 1 << 16;
 
 fn lut_load_u16(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U16_BYTES]>) -> u16 {
     debug_assert!(i < lut.len() / U16_BYTES);
@@ -23,16 +18,104 @@ fn lut_load_u16(i: usize, lut: &ProgMem<[u8; LUT_SIZE * U16_BYTES]>) -> u16 {
 }
 
 /**
-Returns 2^16x by finding the two nearest entries in the lookup table and
-interpolating between them.
+Looks up a (0.16) fraction in one of the tables by finding the two nearest
+entries and interpolating between them. `end` is the value of the function at
+1, just past the last entry.
+*/
+fn lut_interpolate(x: u16, lut: &ProgMem<[u8; LUT_SIZE * U16_BYTES]>, end: u32) -> u32 {
+    let index = (x >> 8) as usize;
+    let remainder = (x & 0xFF) as u32;
+    let low = lut_load_u16(index, lut) as u32;
+    let high = if index + 1 < LUT_SIZE {
+        lut_load_u16(index + 1, lut) as u32
+    } else {
+        end
+    };
+    (low * (256 - remainder) + high * remainder) >> 8
+}
+
+/**
+Returns (2^x - 1) / x for a (0.16) fraction x, as a (0.16) fraction (between
+ln(2) and 1)
+*/
+fn exp2_minus_one_over_x(x: u16) -> u32 {
+    lut_interpolate(x, &EXP_LUT, ONE)
+}
+
+/**
+Computes 2^a - 1, where `a` is a (4.28) fixed point number, returning a fixed
+point number with `frac_bits` fractional bits. There has to be room for the
+integer part of the result, so `frac_bits` can be at most 31 - floor(a).
+
+The fractional part of the exponent comes from the table, multiplied back out
+by x so that it keeps its precision even when a is tiny. The integer part is a
+bit shift.
+*/
+fn exp2_minus_one(a: u32, frac_bits: u32) -> u32 {
+    let integer_part = a >> 28;
+    debug_assert!(frac_bits + integer_part <= 31);
+    let fraction = a & 0x0FFF_FFFF;
+    let table_value = exp2_minus_one_over_x((fraction >> 12) as u16);
+    // 2^f - 1, as (0.32). Split up the multiplication to use all 28 bits of
+    // the fraction without overflowing.
+    let fractional_part =
+        (fraction >> 12) * table_value + (((fraction & 0xFFF) * table_value) >> 12);
+    // 2^(n + f) - 1 = 2^n * (2^f - 1) + 2^n - 1
+    (fractional_part >> (32 - frac_bits - integer_part)) + (((1 << integer_part) - 1) << frac_bits)
+}
+
+/**
+Computes the base 2 log of a (16.16) fixed point number greater than or equal
+to 1, as a (16.16) fixed point number.
+
+Counts leading zeros to find the integer part of the result, then looks up the
+fractional part in a table of log2(1 + t) / t - 1.
+*/
+fn log2(x: u32) -> u32 {
+    debug_assert!(x >= ONE);
+    let lz = x.leading_zeros();
+    let integer_part = 15 - lz;
+    // Shift x to the left to normalize it (i.e. make the MSB 1), and keep the
+    // 16 bits below the MSB
+    let t = ((x << lz) >> 15) as u16;
+    let table_value = lut_interpolate(t, &LOG_LUT, 0);
+    // log2(1 + t) = t + t * (log2(1 + t) / t - 1)
+    let fractional_part = t as u32 + ((t as u32 * table_value) >> 16);
+    (integer_part << 16) + fractional_part
+}
+
+/**
+Multiplies a (16.16) fixed point number by a (0.16) fraction between 0 and 1
+inclusive, without needing 64 bit math
+*/
+fn mul_fraction(a: u32, fraction: u32) -> u32 {
+    debug_assert!(fraction <= ONE);
+    (a >> 16) * fraction + (((a & 0xFFFF) * fraction) >> 16)
+}
+
+/**
+Computes (2^(16xc) - 1) / (2^16c - 1) as a (0.16) fraction. x is given as a
+(0.16) fraction but can also be exactly 1 (0x10000).
+
+Costs two table lookups and one 32 bit division.
 */
-fn exp2_lut(x: FixedU16<U16>) -> FixedU32<U16> {
-    let idx_low = x.to_bits() >> 8;
-    let idx_high = u16::min(255, idx_low + 1);
-    let remainder = FixedU32::<U16>::from_bits((x.to_bits() << 8) as u32);
-    let v_low = lut_load_fixed32(idx_low as usize, &EXP_LUT);
-    let v_high = lut_load_fixed32(idx_high as usize, &EXP_LUT);
-    remainder.lerp(v_low, v_high)
+fn curve_fraction(x: u32, c: FixedU16<U16>) -> u32 {
+    debug_assert!(x <= ONE);
+    // 16c and 16xc as (4.28)
+    let k = (c.to_bits() as u32) << 16;
+    let a = x * c.to_bits() as u32;
+    // The denominator is less than 2^(floor(16c) + 1), so this is as precise
+    // as it can be without overflowing
+    let frac_bits = 31 - (k >> 28);
+    let denominator = exp2_minus_one(k, frac_bits);
+    if denominator == 0 {
+        return x;
+    }
+    let numerator = u32::min(exp2_minus_one(a, frac_bits), denominator);
+    // Keep 16 significant bits, so the result of shifting the numerator over
+    // by 16 fits in 32 bits
+    let shift = (32 - denominator.leading_zeros()).saturating_sub(16);
+    ((numerator >> shift) << 16) / (denominator >> shift)
 }
 
 /**
@@ -43,106 +126,67 @@ which will cause the curve to bend the other direction
 - returns a number between 0 and 4095 (0xFFF) inclusive
 */
 pub fn exp_curve(x: FixedU16<U16>, c: FixedU16<U16>, c_negative: bool) -> u16 {
-    let a = exp2_lut(x * c);
-    let b = exp2_lut(c);
-    const ONE: FixedU32<U16> = FixedU32::<U16>::from_bits(1u32 << 16);
-    const SCALE_FACTOR: u16 = 4096;
-    debug_assert!(a >= ONE);
-    debug_assert!(b >= ONE);
-    let (numerator, denominator) = if c_negative {
-        // ((a - ONE).saturating_mul(b), (b - ONE).saturating_mul(a))
-        (ONE - a.recip(), ONE - b.recip())
+    let fraction = if c_negative {
+        // Bending the other way is the same as rotating the curve by 180
+        // degrees: (1 - 2^(-16xc)) / (1 - 2^-16c) = 1 - f(1 - x) where f is
+        // the curve for positive c
+        ONE - curve_fraction(ONE - x.to_bits() as u32, c)
     } else {
-        (a - ONE, b - ONE)
+        curve_fraction(x.to_bits() as u32, c)
     };
-    if denominator.is_zero() {
-        const SCALE_FACTOR_FIXED: FixedU32<U16> =
-            FixedU32::<U16>::from_bits((SCALE_FACTOR as u32) << 16);
-        let result = (Into::<FixedU32<U16>>::into(x) * SCALE_FACTOR_FIXED).to_num::<u16>();
-        debug_assert!(result < SCALE_FACTOR);
-        return result;
-    }
-    debug_assert!(numerator <= denominator);
-    if numerator == denominator {
-        return SCALE_FACTOR - 1;
-    }
-    let result = ((numerator / denominator) * SCALE_FACTOR as u32).to_num::<u16>();
-    debug_assert!(result < SCALE_FACTOR);
-    return result;
+    u32::min(fraction >> 4, 0xFFF) as u16
 }
 
 /**
-Efficiently computes the ABSOLUTE VALUE of the base 2 log of any value between
-0 and 2^16, exclusive.
-
-First computes the largest power of 2 strictly less than log2(x) by counting
-leading zeros in the binary representation of x (which gives the integer part of
-the result); then looks up the fractional part in a table of values of log2(n) for
-n in [0.5, 1] and adds the two parts together.
+Computes log2(y * (2^16c - 1) + 1) / 16c as a (0.16) fraction. y is given as a
+(0.16) fraction but can also be exactly 1 (0x10000).
+
+When 16c is less than 1, this uses the identity
+log2(1 + y * (2^k - 1)) / k = y * (log2(1 + t) / t) * ((2^k - 1) / k)
+where t = y * (2^k - 1), and both factors come straight out of the tables, so
+no division is needed. Otherwise costs two table lookups and one 32 bit
+division.
 */
-pub fn fixed_point_log2(mut x: FixedU32<U16>) -> FixedU32<U16> {
-    debug_assert!(x != 0);
-
-    if x < 1 {
-        // It seems like there is a slight loss of precision here (and an extra computation).
-        // It might be better to use a separate lookup table and interpolate here, in
-        // the same way as computing 2^x (or re-purpose the existing [0.5, 1] table).
-        // But I don't want to deal with that.
-        // For now, I'm taking advantage of the identity that log2(x) = -log2(1/x)
-        // to ensure that x is always >= 1, so the output of the function is always
-        // positive
-        x = x.recip();
-        debug_assert!(x >= 1);
+fn inverse_fraction(y: u32, c: FixedU16<U16>) -> u32 {
+    debug_assert!(y <= ONE);
+    if c == 0 {
+        return y;
     }
-
-    // Count the number of leading zeros to calculate floor(log2(x))
-    let lz = x.leading_zeros() as u32;
-    let integer_part = 15 - lz;
-
-    // Shift x to the left to normalize it (i.e. make the MSB 1)
-    let normalized = x.to_bits() << lz;
-
-    // Get the most significant 8 bits of the normalized number to use as LUT index
-    let fractional_part_index = ((normalized >> 23) & 0xFF) as usize;
-    debug_assert!(fractional_part_index <= 255);
-
-    // Lookup the fractional part from the table
-    let fractional_part = lut_load_u16(fractional_part_index, &LOG_LUT) as u32;
-
-    // Combine the integer part and the fractional part
-    FixedU32::<U16>::from_bits((integer_part << 16) | fractional_part)
+    let result = if c < FixedU16::<U16>::from_bits(1 << 12) {
+        // 16c as (0.16)
+        let k = c.to_bits() << 4;
+        let exp_over_k = exp2_minus_one_over_x(k);
+        let coefficient = (k as u32 * exp_over_k) >> 16;
+        let t = (y * coefficient) >> 16;
+        let log_over_t = ONE + lut_interpolate(t as u16, &LOG_LUT, 0);
+        let y_log_over_t = mul_fraction(log_over_t, y);
+        // Both of these are at most 17 bits, so drop one to stay in range
+        (y_log_over_t * (exp_over_k >> 1)) >> 15
+    } else {
+        // 16c as (4.28)
+        let k = (c.to_bits() as u32) << 16;
+        let coefficient = exp2_minus_one(k, 16);
+        let numerator = log2(mul_fraction(coefficient, y) + ONE);
+        // 16c as (16.16) is c << 4, and the numerator can't be larger
+        let numerator = u32::min(numerator, (c.to_bits() as u32) << 4);
+        (numerator << 12) / c.to_bits() as u32
+    };
+    u32::min(result, ONE)
 }
 
 /**
 Calculates the inverse of the exp_curve function s.t. exp_curve_inverse(exp_curve(x, c) / 4096, c) ~= x
 
 The formula is log2(x * (2^c - 1) + 1) / c
+
+This is cheap enough to compute alongside a new sample in the same timer tick
 */
 pub fn exp_curve_inverse(x: FixedU16<U16>, c: FixedU16<U16>, c_negative: bool) -> FixedU16<U16> {
-    if c == 0 {
-        return FixedU16::<U16>::from_bits(x.to_bits() >> 4);
-    }
-
-    const ONE: FixedU32<U16> = FixedU32::<U16>::from_bits(1u32 << 16);
-
-    // if c is negative, the output will always work out to be positive
-    // because log2(a) will be < 0, and so will the denominator
-    // To keep things simple, we just ignore the +/- sign.
-    // To calculate A for negative c, use identity 2^(-x) = 1/(2^x)
-    // and rearrange terms to avoid subtraction underflow
-    let a = if c_negative {
-        let coefficient = ONE - exp2_lut(c).recip();
-        ONE - (Into::<FixedU32<U16>>::into(x) * coefficient)
+    let fraction = if c_negative {
+        // See exp_curve
+        ONE - inverse_fraction(ONE - x.to_bits() as u32, c)
     } else {
-        let coefficient = exp2_lut(c) - ONE;
-        (Into::<FixedU32<U16>>::into(x) * coefficient) + ONE
+        inverse_fraction(x.to_bits() as u32, c)
     };
-
-    let numerator = fixed_point_log2(a);
-    let denominator = FixedU32::<U16>::from(c) * 16;
-
-    debug_assert!(numerator <= denominator);
-    debug_assert!(denominator != 0);
-
-    FixedU16::<U16>::from_bits((numerator / denominator).to_bits() as u16)
+    FixedU16::<U16>::from_bits(u32::min(fraction, u16::MAX as u32) as u16)
 }
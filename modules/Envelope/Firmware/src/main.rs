@@ -19,8 +19,9 @@ use arduino_hal::{hal::port::PB0, prelude::*, Peripherals};
 use avr_device::interrupt::{self, Mutex};
 use embedded_hal::digital::v2::OutputPin;
 use envelope::{
-    ui_show_mode, ui_show_stage, update, AcrcLoopState, AcrcState, AdsrState, AhrdState,
-    EnvelopeMode,
+    ui_show_mode, ui_show_stage, update, AcrcLoopState, AcrcState, AdsrCurve, AdsrState, AhrdState,
+    ClockSync, EnvelopeMode, MsegPreset, MsegState, RetriggerPolicy, MAX_DAC_VALUE,
+    MICROS_PER_STEP,
 };
 use fm_lib::{
     async_adc::{
@@ -28,7 +29,7 @@ use fm_lib::{
     },
     asynchronous::{assert_interrupts_disabled, unsafe_access_mutex},
     asynchronous::{AtomicRead, Borrowable},
-    button_debouncer::ButtonDebouncer,
+    button_debouncer::{ButtonWithLongPress, LongPressButtonState},
     eeprom::WearLevelledEepromWriter,
     handle_system_clock_interrupt,
     mcp4922::{DacChannel, MCP4922},
@@ -36,24 +37,52 @@ use fm_lib::{
 };
 use ufmt::uwriteln;
 
-use crate::aux::{update_aux, AuxMode};
+use crate::aux::{AuxMode, AuxOutput};
 use crate::envelope::{EnvelopeState, GateState, Input};
+use crate::settings::{ui_show_settings_page, Settings, SettingsPage, SETTINGS_SIZE};
 
 mod aux;
 mod envelope;
 mod exponential_curves;
+mod settings;
 
 static SYSTEM_CLOCK_STATE: GlobalSystemClockState<{ ClockPrecision::MS16 }> =
     GlobalSystemClockState::new();
 handle_system_clock_interrupt!(&SYSTEM_CLOCK_STATE);
 
 const UI_SHOW_ENVELOPE_MODE_MS: u32 = 2000;
+const UI_SETTINGS_TIMEOUT_MS: u32 = 4000;
+const LONG_PRESS_MS: u32 = 1000;
 #[derive(PartialEq, Eq)]
 enum DisplayMode {
     ShowEnvelopeMode { until: u32 },
+    ShowSettings { page: SettingsPage, until: u32 },
     ShowEnvelopeSegment,
 }
 
+const NUM_ENVELOPE_MODES: usize = 6;
+
+/**
+Persisted state: the current mode, a marker and version for this layout, then
+the settings. The record has room to spare, because changing its size would
+lose everything saved in it.
+*/
+const EEPROM_SIZE: usize = 24;
+const RECORD_MARKER_OFFSET: usize = 1;
+const RECORD_VERSION_OFFSET: usize = 3;
+const SETTINGS_OFFSET: usize = 4;
+const _: () = assert!(SETTINGS_OFFSET + SETTINGS_SIZE <= EEPROM_SIZE);
+
+/**
+The original firmware saved only the mode, in 1 byte records. Neither marker
+byte can be a mode, and the second could only be the high byte of an old
+record's version number after 60000 restarts, so old records are never
+mistaken for this layout.
+*/
+const RECORD_MARKER: [u8; 2] = [0xF5, 0xF5];
+const RECORD_VERSION: u8 = 1;
+const OLD_EEPROM_SIZE: usize = 1;
+
 #[inline(never)]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -149,19 +178,56 @@ impl EnvelopeMode {
             EnvelopeMode::Adsr(_) => EnvelopeMode::Acrc(AcrcState::default()),
             EnvelopeMode::Acrc(_) => EnvelopeMode::AcrcLoop(AcrcLoopState::default()),
             EnvelopeMode::AcrcLoop(_) => EnvelopeMode::AhrdLoop(AhrdState::default()),
-            EnvelopeMode::AhrdLoop(_) => EnvelopeMode::Adsr(AdsrState::default()),
+            EnvelopeMode::AhrdLoop(_) => EnvelopeMode::Mseg(MsegState::new(MsegPreset::Dahdsr)),
+            EnvelopeMode::Mseg(state) => match state.preset {
+                MsegPreset::Dahdsr => EnvelopeMode::Mseg(MsegState::new(MsegPreset::Tremolo)),
+                MsegPreset::Tremolo => EnvelopeMode::Adsr(AdsrState::default()),
+            },
+        }
+    }
+
+    fn index(&self) -> u8 {
+        match self {
+            EnvelopeMode::Adsr(_) => 0,
+            EnvelopeMode::Acrc(_) => 1,
+            EnvelopeMode::AcrcLoop(_) => 2,
+            EnvelopeMode::AhrdLoop(_) => 3,
+            EnvelopeMode::Mseg(state) => match state.preset {
+                MsegPreset::Dahdsr => 4,
+                MsegPreset::Tremolo => 5,
+            },
+        }
+    }
+
+    fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(EnvelopeMode::Adsr(AdsrState::default())),
+            1 => Some(EnvelopeMode::Acrc(AcrcState::default())),
+            2 => Some(EnvelopeMode::AcrcLoop(AcrcLoopState::default())),
+            3 => Some(EnvelopeMode::AhrdLoop(AhrdState::default())),
+            4 => Some(EnvelopeMode::Mseg(MsegState::new(MsegPreset::Dahdsr))),
+            5 => Some(EnvelopeMode::Mseg(MsegState::new(MsegPreset::Tremolo))),
+            _ => None,
         }
     }
 }
 
 impl EnvelopeState {
-    fn cycle_mode(self) -> Self {
-        Self {
+    fn cycle_mode(self, settings: &Settings) -> Self {
+        let mut state = Self {
             mode: self.mode.next(),
             time: 0,
             last_value: 0,
             artificial_gate: false,
-        }
+            retrigger: RetriggerPolicy::Continue,
+            adsr_curve: AdsrCurve::Linear,
+            sync: None,
+            clock: ClockSync::new(),
+            velocity: None,
+            peak: MAX_DAC_VALUE,
+        };
+        settings.apply(&mut state);
+        state
     }
 }
 
@@ -199,7 +265,7 @@ fn main() -> ! {
         },
     );
 
-    #[cfg(feature = "debug")]
+    #[cfg(any(feature = "debug", feature = "profile"))]
     let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
 
     unsafe {
@@ -224,12 +290,8 @@ fn main() -> ! {
         pins.d7.into_output(),
     );
 
-    let config = match (config_pin_1.is_high(), config_pin_2.is_high()) {
-        (true, true) => AuxMode::EndOfRise,
-        (false, true) => AuxMode::EndOfFall,
-        (true, false) => AuxMode::NonZero,
-        (false, false) => AuxMode::FollowGate,
-    };
+    // Bridging a pair of jumper holes pulls its pin low
+    let jumper_aux_mode = AuxMode::from_jumpers(config_pin_1.is_low(), config_pin_2.is_low());
 
     let _ = config_pin_1.into_floating_input();
     let _ = config_pin_2.into_floating_input();
@@ -243,35 +305,64 @@ fn main() -> ! {
         }
     }
 
-    let mut eeprom_data = [0u8; 1];
-    let mut eeprom =
-        WearLevelledEepromWriter::<1>::init_and_advance(dp.EEPROM, &mut eeprom_data, erase_eeprom);
+    // Only used if the EEPROM is empty or in the old layout
+    let mut eeprom_data = [0u8; EEPROM_SIZE];
+    eeprom_data[RECORD_MARKER_OFFSET..RECORD_VERSION_OFFSET].copy_from_slice(&RECORD_MARKER);
+    eeprom_data[RECORD_VERSION_OFFSET] = RECORD_VERSION;
+    eeprom_data[SETTINGS_OFFSET..SETTINGS_OFFSET + SETTINGS_SIZE]
+        .copy_from_slice(&Settings::default_bytes());
+    let mut eeprom = WearLevelledEepromWriter::<EEPROM_SIZE>::init_and_migrate::<OLD_EEPROM_SIZE>(
+        dp.EEPROM,
+        &mut eeprom_data,
+        erase_eeprom,
+        |data| {
+            data[RECORD_MARKER_OFFSET..RECORD_VERSION_OFFSET] == RECORD_MARKER
+                && data[RECORD_VERSION_OFFSET] == RECORD_VERSION
+        },
+        // Keeps the mode, with the default settings
+        |old_data, data| data[0] = old_data[0],
+    );
     ui.update(0);
 
     while btn_pin.is_low() {
         unsafe { asm!("nop") };
     }
 
-    let mut button = ButtonDebouncer::<PB0, 32>::new(btn_pin);
+    let mut button = ButtonWithLongPress::<PB0, 32, LONG_PRESS_MS>::new(btn_pin);
 
     let sys_clock = SystemClock::init_system_clock(dp.TC0, &SYSTEM_CLOCK_STATE);
 
+    let mut settings = Settings::from_bytes(
+        eeprom_data[SETTINGS_OFFSET..SETTINGS_OFFSET + SETTINGS_SIZE]
+            .try_into()
+            .unwrap(),
+        jumper_aux_mode,
+    );
+
+    let mode = match EnvelopeMode::from_index(eeprom_data[0]) {
+        Some(mode) => mode,
+        None => {
+            #[cfg(feature = "debug")]
+            uwriteln!(&mut serial, "Unexpected EEPROM value {}", eeprom_data[0])
+                .unwrap_infallible();
+            EnvelopeMode::Adsr(AdsrState::default())
+        }
+    };
+
     let mut envelope_state = EnvelopeState {
-        mode: match eeprom_data[0] {
-            0 => EnvelopeMode::Adsr(AdsrState::default()),
-            1 => EnvelopeMode::Acrc(AcrcState::default()),
-            2 => EnvelopeMode::AcrcLoop(AcrcLoopState::default()),
-            3 => EnvelopeMode::AhrdLoop(AhrdState::default()),
-            _x => {
-                #[cfg(feature = "debug")]
-                uwriteln!(&mut serial, "Unexpected EEPROM value {}", _x).unwrap_infallible();
-                EnvelopeMode::Adsr(AdsrState::default())
-            }
-        },
+        mode,
         time: 0,
         last_value: 0,
         artificial_gate: false,
+        retrigger: RetriggerPolicy::Continue,
+        adsr_curve: AdsrCurve::Linear,
+        sync: None,
+        clock: ClockSync::new(),
+        velocity: None,
+        peak: MAX_DAC_VALUE,
     };
+    settings.apply(&mut envelope_state);
+    let mut aux = AuxOutput::new(settings.aux_mode());
 
     let mut display = DisplayMode::ShowEnvelopeMode {
         until: UI_SHOW_ENVELOPE_MODE_MS,
@@ -280,6 +371,8 @@ fn main() -> ! {
     ui.update(ui_show_mode(&envelope_state.mode));
 
     configure_timer(&dp.TC2);
+    #[cfg(feature = "profile")]
+    let mut profiler = Profiler::new(&dp.TC1);
     let mut dac = MCP4922::new(d10);
     dac.shutdown_channel(&mut spi, DacChannel::ChannelB);
 
@@ -292,32 +385,69 @@ fn main() -> ! {
     let mut gate_was_high = false;
 
     loop {
+        #[cfg(feature = "profile")]
+        profiler.start(&dp.TC1);
+
         let cv = interrupt::free(|cs| GLOBAL_ASYNC_ADC_STATE.get_inner(cs).get_all());
 
         let current_time = sys_clock.millis_exact();
         let button_state = button.sample(current_time);
-        let button_was_pressed = match button_state {
-            fm_lib::button_debouncer::ButtonState::ButtonJustPressed => true,
-            _ => false,
-        };
 
-        if button_was_pressed {
-            envelope_state = envelope_state.cycle_mode();
-            display = DisplayMode::ShowEnvelopeMode {
-                until: current_time + UI_SHOW_ENVELOPE_MODE_MS,
-            };
-            led_blink_timer = current_time + LED_BLINK_INTERVAL_MS;
-            led_blink_state = true;
-            ui.update(ui_show_mode(&envelope_state.mode));
-            eeprom.update_byte(
-                0,
-                match envelope_state.mode {
-                    EnvelopeMode::Adsr(_) => 0,
-                    EnvelopeMode::Acrc(_) => 1,
-                    EnvelopeMode::AcrcLoop(_) => 2,
-                    EnvelopeMode::AhrdLoop(_) => 3,
-                },
-            );
+        // Short press cycles through modes, long press opens the settings pages
+        match (button_state, &display) {
+            (
+                LongPressButtonState::ButtonJustClickedShort,
+                DisplayMode::ShowSettings { page, .. },
+            ) => {
+                let page = *page;
+                let (offset, byte) = settings.cycle(page, &envelope_state.mode);
+                settings.apply(&mut envelope_state);
+                aux.mode = settings.aux_mode();
+                eeprom.update_byte((SETTINGS_OFFSET + offset) as u16, byte);
+                display = DisplayMode::ShowSettings {
+                    page,
+                    until: current_time + UI_SETTINGS_TIMEOUT_MS,
+                };
+            }
+            (LongPressButtonState::ButtonJustClickedShort, _) => {
+                envelope_state = envelope_state.cycle_mode(&settings);
+                display = DisplayMode::ShowEnvelopeMode {
+                    until: current_time + UI_SHOW_ENVELOPE_MODE_MS,
+                };
+                led_blink_timer = current_time + LED_BLINK_INTERVAL_MS;
+                led_blink_state = true;
+                ui.update(ui_show_mode(&envelope_state.mode));
+                eeprom.update_byte(0, envelope_state.mode.index());
+            }
+            (LongPressButtonState::ButtonJustClickedLong, _) => {
+                let next_page = match display {
+                    DisplayMode::ShowSettings { page, .. } => page.next(),
+                    _ => Some(SettingsPage::FIRST),
+                };
+                display = match next_page {
+                    Some(page) => DisplayMode::ShowSettings {
+                        page,
+                        until: current_time + UI_SETTINGS_TIMEOUT_MS,
+                    },
+                    None => {
+                        ui.update(ui_show_stage(&envelope_state.mode));
+                        DisplayMode::ShowEnvelopeSegment
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        if let DisplayMode::ShowSettings { page, until } = display {
+            if current_time > until {
+                display = DisplayMode::ShowEnvelopeSegment;
+                ui.update(ui_show_stage(&envelope_state.mode));
+            } else if current_time % 1024 < 256 {
+                // Briefly flash which page this is, then show its value
+                ui.update(ui_show_settings_page(page));
+            } else {
+                ui.update(settings.ui_show(page, &envelope_state.mode));
+            }
         }
 
         if let DisplayMode::ShowEnvelopeMode { until } = display {
@@ -357,16 +487,20 @@ fn main() -> ! {
             dac.write_keep_cs_pin_low(&mut spi, DacChannel::ChannelA, value, &Default::default());
             unsafe_access_mutex(|cs| DAC_WRITE_QUEUED.borrow(cs).set(true));
 
-            if did_change_phase {
-                aux_output_pin
-                    .set_state(update_aux(&envelope_state.mode, &config).into())
-                    .unwrap_infallible();
-                if display == DisplayMode::ShowEnvelopeSegment {
-                    ui.update(ui_show_stage(&envelope_state.mode));
-                }
+            aux_output_pin
+                .set_state(aux.update(&envelope_state.mode, did_change_phase).into())
+                .unwrap_infallible();
+            if did_change_phase && display == DisplayMode::ShowEnvelopeSegment {
+                ui.update(ui_show_stage(&envelope_state.mode));
             }
+
+            #[cfg(feature = "profile")]
+            profiler.finish_sample(&dp.TC1);
         }
 
+        #[cfg(feature = "profile")]
+        profiler.report(&mut serial, current_time);
+
         #[cfg(feature = "debug")]
         {
             use ufmt::uwrite;
@@ -384,15 +518,77 @@ fn main() -> ! {
 fn configure_timer(tc2: &arduino_hal::pac::TC2) {
     // reset timer counter at TOP set by OCRA
     tc2.tccr2a.write(|w| w.wgm2().ctc());
-    // set timer frequency to cycle at ~2.2727kHz
-    // (16MHz clock speed / 64 prescale factor / 120 count/reset )
+    // set timer frequency to cycle once per envelope step
+    // (16MHz clock speed / 64 prescale factor = 4us per count, and the
+    // counter resets after reaching TOP so the period is TOP + 1 counts)
     tc2.tccr2b.write(|w| w.cs2().prescale_64());
-    tc2.ocr2a.write(|w| w.bits(120));
+    tc2.ocr2a.write(|w| w.bits((MICROS_PER_STEP / 4 - 1) as u8));
 
     // enable interrupt on match to compare register A
     tc2.timsk2.write(|w| w.ocie2a().set_bit());
 }
 
+/**
+Measures how many CPU cycles the main loop takes whenever it computes a new
+sample, using timer 1 as a cycle counter, and reports the worst case over
+serial once a second. A new sample has to be ready before the next timer 2
+interrupt, so the worst case has to stay under `CYCLES_PER_STEP`.
+*/
+#[cfg(feature = "profile")]
+struct Profiler {
+    loop_start: u16,
+    worst: u16,
+    samples: u16,
+    next_report: u32,
+}
+
+#[cfg(feature = "profile")]
+impl Profiler {
+    const CYCLES_PER_STEP: u32 = MICROS_PER_STEP * 16;
+    const REPORT_INTERVAL_MS: u32 = 1000;
+
+    fn new(tc1: &arduino_hal::pac::TC1) -> Self {
+        // Count every CPU cycle. The counter wraps every ~4ms, which is much
+        // longer than a step.
+        tc1.tccr1b.write(|w| w.cs1().direct());
+        Self {
+            loop_start: 0,
+            worst: 0,
+            samples: 0,
+            next_report: Self::REPORT_INTERVAL_MS,
+        }
+    }
+
+    fn start(&mut self, tc1: &arduino_hal::pac::TC1) {
+        self.loop_start = tc1.tcnt1.read().bits();
+    }
+
+    fn finish_sample(&mut self, tc1: &arduino_hal::pac::TC1) {
+        let cycles = tc1.tcnt1.read().bits().wrapping_sub(self.loop_start);
+        self.worst = u16::max(self.worst, cycles);
+        self.samples += 1;
+    }
+
+    fn report<W: ufmt::uWrite>(&mut self, serial: &mut W, current_time: u32) {
+        if current_time < self.next_report {
+            return;
+        }
+        // Writing to serial blocks for a few ms, which will drop a few
+        // samples. It happens after the measurement, so it doesn't count.
+        uwriteln!(
+            serial,
+            "worst {} of {} cycles over {} samples",
+            self.worst,
+            Self::CYCLES_PER_STEP,
+            self.samples
+        )
+        .ok();
+        self.worst = 0;
+        self.samples = 0;
+        self.next_report = current_time + Self::REPORT_INTERVAL_MS;
+    }
+}
+
 struct UI {
     _d4: Pin<Output, port::PD4>,
     _d5: Pin<Output, port::PD5>,
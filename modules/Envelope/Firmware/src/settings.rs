@@ -0,0 +1,281 @@
+use crate::envelope::{
+    set_peak, ui_show_adsr_curve, ui_show_retrigger_policy, ui_show_velocity, AdsrCurve,
+    EnvelopeMode, EnvelopeState, RetriggerPolicy, SyncRatio, MAX_DAC_VALUE, RC_CURVATURES,
+    SYNC_RATIOS,
+};
+use crate::{
+    aux::{ui_show_aux_mode, AuxMode, AUX_SIGNALS},
+    NUM_ENVELOPE_MODES,
+};
+
+/**
+Extra options, reached by long-pressing the mode button. Each long press moves
+on to the next page (and eventually back out to the normal display) and short
+presses cycle through the values on the current page.
+*/
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SettingsPage {
+    /// Retrigger policy for the current mode
+    Retrigger,
+    /// Curve shape used by ADSR mode, and how strongly the RC curves bend
+    AdsrCurve,
+    /// Whether the looping modes sync to a clock on the gate input, and at
+    /// what ratio
+    Sync,
+    /// Which knob/CV, if any, sets the peak level of each note
+    Velocity,
+    /// What the aux output shows
+    Aux,
+}
+
+impl SettingsPage {
+    pub const FIRST: Self = SettingsPage::Retrigger;
+
+    pub fn next(self) -> Option<Self> {
+        match self {
+            SettingsPage::Retrigger => Some(SettingsPage::AdsrCurve),
+            SettingsPage::AdsrCurve => Some(SettingsPage::Sync),
+            SettingsPage::Sync => Some(SettingsPage::Velocity),
+            SettingsPage::Velocity => Some(SettingsPage::Aux),
+            SettingsPage::Aux => None,
+        }
+    }
+}
+
+/**
+Pages are counted in binary, the same way as segments in MSEG mode
+*/
+pub const fn ui_show_settings_page(page: SettingsPage) -> u8 {
+    let number: u8 = match page {
+        SettingsPage::Retrigger => 1,
+        SettingsPage::AdsrCurve => 2,
+        SettingsPage::Sync => 3,
+        SettingsPage::Velocity => 4,
+        SettingsPage::Aux => 5,
+    };
+    number << 4
+}
+
+pub const SETTINGS_SIZE: usize = NUM_ENVELOPE_MODES + 4;
+
+/**
+Everything that can be changed from the settings pages. Stored in EEPROM
+after the mode and the record's layout marker.
+*/
+pub struct Settings {
+    retrigger_policies: [RetriggerPolicy; NUM_ENVELOPE_MODES],
+    adsr_curve: AdsrCurve,
+    /// Index into `SYNC_RATIOS` plus one, or 0 if sync is off
+    sync: u8,
+    velocity: Option<u8>,
+    aux_mode: AuxMode,
+}
+
+const ADSR_CURVE_OFFSET: usize = NUM_ENVELOPE_MODES;
+const SYNC_OFFSET: usize = NUM_ENVELOPE_MODES + 1;
+const VELOCITY_OFFSET: usize = NUM_ENVELOPE_MODES + 2;
+const AUX_MODE_OFFSET: usize = NUM_ENVELOPE_MODES + 3;
+
+/// Not a valid aux mode, so the jumpers pick it
+const AUX_MODE_UNSET: u8 = 0xFF;
+
+impl Settings {
+    pub fn default_bytes() -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0u8; SETTINGS_SIZE];
+        for i in 0..NUM_ENVELOPE_MODES {
+            let mode = EnvelopeMode::from_index(i as u8).unwrap();
+            bytes[i] = retrigger_policy_to_byte(default_retrigger_policy(&mode));
+        }
+        bytes[ADSR_CURVE_OFFSET] = adsr_curve_to_byte(AdsrCurve::Linear);
+        bytes[SYNC_OFFSET] = 0;
+        bytes[VELOCITY_OFFSET] = velocity_to_byte(None);
+        bytes[AUX_MODE_OFFSET] = AUX_MODE_UNSET;
+        bytes
+    }
+
+    /**
+    Falls back to the default for any setting with an unrecognized value. The
+    configuration jumpers chose the aux mode before it was a setting, so they
+    still do until one is picked on the settings page.
+    */
+    pub fn from_bytes(bytes: &[u8; SETTINGS_SIZE], jumper_aux_mode: AuxMode) -> Self {
+        Self {
+            retrigger_policies: core::array::from_fn(|i| {
+                retrigger_policy_from_byte(bytes[i]).unwrap_or_else(|| {
+                    default_retrigger_policy(&EnvelopeMode::from_index(i as u8).unwrap())
+                })
+            }),
+            adsr_curve: adsr_curve_from_byte(bytes[ADSR_CURVE_OFFSET]).unwrap_or(AdsrCurve::Linear),
+            sync: if bytes[SYNC_OFFSET] as usize <= SYNC_RATIOS.len() {
+                bytes[SYNC_OFFSET]
+            } else {
+                0
+            },
+            velocity: velocity_from_byte(bytes[VELOCITY_OFFSET]).unwrap_or(None),
+            aux_mode: aux_mode_from_byte(bytes[AUX_MODE_OFFSET]).unwrap_or(jumper_aux_mode),
+        }
+    }
+
+    /**
+    Copies the settings that apply to the envelope's current mode into it
+    */
+    pub fn apply(&self, state: &mut EnvelopeState) {
+        state.retrigger = self.retrigger_policies[state.mode.index() as usize];
+        state.adsr_curve = self.adsr_curve;
+        state.sync = self.sync_ratio();
+        state.velocity = self.velocity;
+        if self.velocity.is_none() {
+            set_peak(state, MAX_DAC_VALUE);
+        }
+    }
+
+    /**
+    The aux output isn't part of the envelope, so it is applied separately
+    */
+    pub fn aux_mode(&self) -> AuxMode {
+        self.aux_mode
+    }
+
+    fn sync_ratio(&self) -> Option<SyncRatio> {
+        match self.sync {
+            0 => None,
+            i => Some(SYNC_RATIOS[i as usize - 1]),
+        }
+    }
+
+    /**
+    Moves on to the next value on the given page. Returns the offset (within
+    the settings) and new value of the byte that needs to be saved.
+    */
+    pub fn cycle(&mut self, page: SettingsPage, mode: &EnvelopeMode) -> (usize, u8) {
+        match page {
+            SettingsPage::Retrigger => {
+                let i = mode.index() as usize;
+                let policy = match self.retrigger_policies[i] {
+                    RetriggerPolicy::Continue => RetriggerPolicy::Reset,
+                    RetriggerPolicy::Reset => RetriggerPolicy::Legato,
+                    RetriggerPolicy::Legato => RetriggerPolicy::Continue,
+                };
+                self.retrigger_policies[i] = policy;
+                (i, retrigger_policy_to_byte(policy))
+            }
+            SettingsPage::AdsrCurve => {
+                // Linear, then the RC curves from gentlest to strongest
+                self.adsr_curve = match self.adsr_curve {
+                    AdsrCurve::Linear => AdsrCurve::Rc(1),
+                    AdsrCurve::Rc(curvature) if curvature < RC_CURVATURES => {
+                        AdsrCurve::Rc(curvature + 1)
+                    }
+                    AdsrCurve::Rc(_) => AdsrCurve::Linear,
+                };
+                (ADSR_CURVE_OFFSET, adsr_curve_to_byte(self.adsr_curve))
+            }
+            SettingsPage::Sync => {
+                self.sync = (self.sync + 1) % (SYNC_RATIOS.len() as u8 + 1);
+                (SYNC_OFFSET, self.sync)
+            }
+            SettingsPage::Velocity => {
+                self.velocity = match self.velocity {
+                    None => Some(0),
+                    Some(i) if (i as usize) < 3 => Some(i + 1),
+                    Some(_) => None,
+                };
+                (VELOCITY_OFFSET, velocity_to_byte(self.velocity))
+            }
+            SettingsPage::Aux => {
+                // Alternates between each signal and its inverse
+                let byte = aux_mode_to_byte(self.aux_mode);
+                let next = (byte + 1) % (AUX_SIGNALS.len() as u8 * 2);
+                self.aux_mode = aux_mode_from_byte(next).unwrap();
+                (AUX_MODE_OFFSET, next)
+            }
+        }
+    }
+
+    pub fn ui_show(&self, page: SettingsPage, mode: &EnvelopeMode) -> u8 {
+        match page {
+            SettingsPage::Retrigger => {
+                ui_show_retrigger_policy(self.retrigger_policies[mode.index() as usize])
+            }
+            SettingsPage::AdsrCurve => ui_show_adsr_curve(self.adsr_curve),
+            // Off, then each ratio from slowest to fastest counted in binary
+            SettingsPage::Sync => self.sync << 4,
+            SettingsPage::Velocity => ui_show_velocity(self.velocity),
+            SettingsPage::Aux => ui_show_aux_mode(self.aux_mode),
+        }
+    }
+}
+
+/**
+The looping modes have always hard-synced on a trigger, so they keep doing that
+unless told otherwise
+*/
+fn default_retrigger_policy(mode: &EnvelopeMode) -> RetriggerPolicy {
+    match mode {
+        EnvelopeMode::AcrcLoop(_) | EnvelopeMode::AhrdLoop(_) => RetriggerPolicy::Reset,
+        _ => RetriggerPolicy::Continue,
+    }
+}
+
+fn retrigger_policy_to_byte(policy: RetriggerPolicy) -> u8 {
+    match policy {
+        RetriggerPolicy::Continue => 0,
+        RetriggerPolicy::Reset => 1,
+        RetriggerPolicy::Legato => 2,
+    }
+}
+
+fn retrigger_policy_from_byte(byte: u8) -> Option<RetriggerPolicy> {
+    match byte {
+        0 => Some(RetriggerPolicy::Continue),
+        1 => Some(RetriggerPolicy::Reset),
+        2 => Some(RetriggerPolicy::Legato),
+        _ => None,
+    }
+}
+
+/**
+The RC curve was saved as 1 before it had a curvature, which is the same
+curve as the gentlest one
+*/
+fn adsr_curve_to_byte(curve: AdsrCurve) -> u8 {
+    match curve {
+        AdsrCurve::Linear => 0,
+        AdsrCurve::Rc(curvature) => curvature,
+    }
+}
+
+fn adsr_curve_from_byte(byte: u8) -> Option<AdsrCurve> {
+    match byte {
+        0 => Some(AdsrCurve::Linear),
+        1..=RC_CURVATURES => Some(AdsrCurve::Rc(byte)),
+        _ => None,
+    }
+}
+
+fn velocity_to_byte(velocity: Option<u8>) -> u8 {
+    match velocity {
+        None => 0,
+        Some(i) => i + 1,
+    }
+}
+
+fn velocity_from_byte(byte: u8) -> Option<Option<u8>> {
+    match byte {
+        0 => Some(None),
+        1..=4 => Some(Some(byte - 1)),
+        _ => None,
+    }
+}
+
+fn aux_mode_to_byte(mode: AuxMode) -> u8 {
+    mode.signal.index() << 1 | mode.inverted as u8
+}
+
+fn aux_mode_from_byte(byte: u8) -> Option<AuxMode> {
+    let signal = *AUX_SIGNALS.get((byte >> 1) as usize)?;
+    Some(AuxMode {
+        signal,
+        inverted: byte & 1 != 0,
+    })
+}
@@ -0,0 +1,53 @@
+#![feature(test)]
+extern crate test;
+
+use fixed::{types::extra::U16, FixedU16};
+use host_tests::exponential_curves::{exp_curve, exp_curve_inverse};
+use test::{black_box, Bencher};
+
+// A gentle curve takes the division-free path through `exp_curve_inverse`,
+// and a strong one takes the path with a division
+const GENTLE: FixedU16<U16> = FixedU16::<U16>::from_bits(0x0800);
+const STRONG: FixedU16<U16> = FixedU16::<U16>::from_bits(0x6000);
+
+fn inputs() -> impl Iterator<Item = FixedU16<U16>> {
+    (0..=u16::MAX).step_by(257).map(FixedU16::<U16>::from_bits)
+}
+
+fn bench_curve(b: &mut Bencher, c: FixedU16<U16>) {
+    b.iter(|| {
+        for x in inputs() {
+            black_box(exp_curve(black_box(x), c, false));
+            black_box(exp_curve(black_box(x), c, true));
+        }
+    });
+}
+
+fn bench_inverse(b: &mut Bencher, c: FixedU16<U16>) {
+    b.iter(|| {
+        for x in inputs() {
+            black_box(exp_curve_inverse(black_box(x), c, false));
+            black_box(exp_curve_inverse(black_box(x), c, true));
+        }
+    });
+}
+
+#[bench]
+fn exp_curve_gentle(b: &mut Bencher) {
+    bench_curve(b, GENTLE);
+}
+
+#[bench]
+fn exp_curve_strong(b: &mut Bencher) {
+    bench_curve(b, STRONG);
+}
+
+#[bench]
+fn exp_curve_inverse_gentle(b: &mut Bencher) {
+    bench_inverse(b, GENTLE);
+}
+
+#[bench]
+fn exp_curve_inverse_strong(b: &mut Bencher) {
+    bench_inverse(b, STRONG);
+}
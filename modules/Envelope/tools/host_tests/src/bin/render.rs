@@ -0,0 +1,454 @@
+//! Runs the envelope firmware over scripted inputs and saves the output as CSV
+//! and SVG, so changes to the curves can be reviewed without a scope.
+//!
+//! Usage: `cargo run --bin render -- [script...]`. With no arguments, every
+//! script in `scripts/` is rendered. Output goes to `renders/`.
+//!
+//! Scripts are plain text, one command per line. Settings come first:
+//!
+//! ```text
+//! mode adsr          # adsr, acrc, acrc_loop, ahrd_loop, dahdsr or tremolo
+//! length 2000        # how long to render, in ms
+//! cv 900 700 488 600 # starting raw ADC readings for the four knobs
+//! curve rc 2         # linear, or rc with a curvature of 1 to 3 (ADSR only)
+//! retrigger reset    # continue, reset or legato
+//! velocity 3         # which cv sets the peak level
+//! sync 1 2           # clock the loop modes from the gate: 1 clock, 2 loops
+//! aux stage_change   # aux output to plot, optionally followed by `inverted`
+//! ```
+//!
+//! Followed by events, each starting with a time in ms:
+//!
+//! ```text
+//! 0 gate on
+//! 100 trigger
+//! 250 cv 2 300
+//! 800 gate off
+//! ```
+//!
+//! Raw cv readings are inverted in hardware, so lower numbers mean higher
+//! levels and longer times.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use host_tests::aux::{AuxMode, AuxOutput, AuxSignal, AUX_SIGNALS};
+use host_tests::envelope::{
+    update, AcrcLoopState, AcrcState, AdsrCurve, AdsrState, AhrdState, ClockSync, EnvelopeMode,
+    EnvelopeState, GateState, Input, MsegPreset, MsegState, RetriggerPolicy, SyncRatio,
+    MAX_DAC_VALUE, MICROS_PER_STEP, RC_CURVATURES,
+};
+
+enum Event {
+    Gate(bool),
+    Trigger,
+    Cv(usize, u16),
+}
+
+struct Script {
+    name: String,
+    mode: EnvelopeMode,
+    length_ms: u32,
+    cv: [u16; 4],
+    adsr_curve: AdsrCurve,
+    retrigger: RetriggerPolicy,
+    velocity: Option<u8>,
+    sync: Option<SyncRatio>,
+    aux: AuxMode,
+    events: Vec<(u32, Event)>,
+}
+
+struct Sample {
+    gate: bool,
+    trigger: bool,
+    output: u16,
+    stage_changed: bool,
+    aux: bool,
+}
+
+fn parse_mode(name: &str) -> Result<EnvelopeMode, String> {
+    Ok(match name {
+        "adsr" => EnvelopeMode::Adsr(AdsrState::default()),
+        "acrc" => EnvelopeMode::Acrc(AcrcState::default()),
+        "acrc_loop" => EnvelopeMode::AcrcLoop(AcrcLoopState::default()),
+        "ahrd_loop" => EnvelopeMode::AhrdLoop(AhrdState::default()),
+        "dahdsr" => EnvelopeMode::Mseg(MsegState::new(MsegPreset::Dahdsr)),
+        "tremolo" => EnvelopeMode::Mseg(MsegState::new(MsegPreset::Tremolo)),
+        _ => return Err(format!("unknown mode '{}'", name)),
+    })
+}
+
+fn parse_aux_signal(name: &str) -> Result<AuxSignal, String> {
+    const NAMES: [&str; 6] = [
+        "end_of_rise",
+        "end_of_fall",
+        "non_zero",
+        "follow_gate",
+        "end_of_cycle",
+        "stage_change",
+    ];
+    NAMES
+        .iter()
+        .position(|n| *n == name)
+        .map(|i| AUX_SIGNALS[i])
+        .ok_or_else(|| format!("unknown aux signal '{}'", name))
+}
+
+fn parse_number<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+    let word = word.ok_or("missing number")?;
+    word.parse()
+        .map_err(|_| format!("expected a number, got '{}'", word))
+}
+
+fn parse_script(name: String, text: &str) -> Result<Script, String> {
+    let mut script = Script {
+        name,
+        mode: EnvelopeMode::Adsr(AdsrState::default()),
+        length_ms: 1000,
+        cv: [900; 4],
+        adsr_curve: AdsrCurve::Linear,
+        retrigger: RetriggerPolicy::Continue,
+        velocity: None,
+        sync: None,
+        aux: AuxMode::DEFAULT,
+        events: Vec::new(),
+    };
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        let result: Result<(), String> = (|| {
+            match first {
+                "mode" => script.mode = parse_mode(words.next().unwrap_or(""))?,
+                "length" => script.length_ms = parse_number(words.next())?,
+                "cv" => {
+                    for cv in script.cv.iter_mut() {
+                        *cv = parse_number(words.next())?;
+                    }
+                }
+                "curve" => {
+                    script.adsr_curve = match words.next() {
+                        Some("linear") => AdsrCurve::Linear,
+                        Some("rc") => {
+                            let curvature = match words.next() {
+                                None => 1,
+                                word => parse_number(word)?,
+                            };
+                            if !(1..=RC_CURVATURES).contains(&curvature) {
+                                return Err(format!("curvature should be 1 to {RC_CURVATURES}"));
+                            }
+                            AdsrCurve::Rc(curvature)
+                        }
+                        _ => return Err("expected 'linear' or 'rc'".into()),
+                    }
+                }
+                "retrigger" => {
+                    script.retrigger = match words.next() {
+                        Some("continue") => RetriggerPolicy::Continue,
+                        Some("reset") => RetriggerPolicy::Reset,
+                        Some("legato") => RetriggerPolicy::Legato,
+                        _ => return Err("expected 'continue', 'reset' or 'legato'".into()),
+                    }
+                }
+                "velocity" => {
+                    let index = parse_number(words.next())?;
+                    if index >= 4 {
+                        return Err("cv index must be 0-3".into());
+                    }
+                    script.velocity = Some(index);
+                }
+                "sync" => {
+                    let clocks = parse_number(words.next())?;
+                    let cycles = parse_number(words.next())?;
+                    if clocks == 0 || cycles == 0 {
+                        return Err("sync ratio can't be 0".into());
+                    }
+                    script.sync = Some(SyncRatio::new(clocks, cycles));
+                }
+                "aux" => {
+                    script.aux = AuxMode {
+                        signal: parse_aux_signal(words.next().unwrap_or(""))?,
+                        inverted: words.next() == Some("inverted"),
+                    }
+                }
+                time => {
+                    let time = parse_number(Some(time))?;
+                    let event = match words.next() {
+                        Some("gate") => match words.next() {
+                            Some("on") => Event::Gate(true),
+                            Some("off") => Event::Gate(false),
+                            _ => return Err("expected 'on' or 'off'".into()),
+                        },
+                        Some("trigger") => Event::Trigger,
+                        Some("cv") => {
+                            let index: usize = parse_number(words.next())?;
+                            if index >= 4 {
+                                return Err("cv index must be 0-3".into());
+                            }
+                            Event::Cv(index, parse_number(words.next())?)
+                        }
+                        _ => return Err(format!("unknown command '{}'", line)),
+                    };
+                    script.events.push((time, event));
+                }
+            }
+            Ok(())
+        })();
+        result.map_err(|e| format!("{}:{}: {}", script.name, line_number + 1, e))?;
+    }
+
+    script.events.sort_by_key(|(time, _)| *time);
+    Ok(script)
+}
+
+fn run(script: &Script) -> Vec<Sample> {
+    let mut state = EnvelopeState {
+        mode: script.mode,
+        time: 0,
+        last_value: 0,
+        artificial_gate: false,
+        retrigger: script.retrigger,
+        adsr_curve: script.adsr_curve,
+        sync: script.sync,
+        clock: ClockSync::new(),
+        velocity: script.velocity,
+        peak: MAX_DAC_VALUE,
+    };
+    let mut aux = AuxOutput::new(script.aux);
+    let mut cv = script.cv;
+    let mut gate = false;
+    let mut events = script.events.iter().peekable();
+
+    let num_samples = script.length_ms as u64 * 1000 / MICROS_PER_STEP as u64;
+    (0..num_samples)
+        .map(|i| {
+            let now_ms = (i * MICROS_PER_STEP as u64 / 1000) as u32;
+            let gate_was_high = gate;
+            let mut trigger = false;
+            while let Some((_, event)) = events.next_if(|(time, _)| *time <= now_ms) {
+                match event {
+                    Event::Gate(value) => gate = *value,
+                    Event::Trigger => trigger = true,
+                    Event::Cv(index, value) => cv[*index] = *value,
+                }
+            }
+            let input = Input {
+                gate: match (gate_was_high, gate) {
+                    (true, true) => GateState::High,
+                    (true, false) => GateState::Falling,
+                    (false, true) => GateState::Rising,
+                    (false, false) => GateState::Low,
+                },
+                trigger,
+            };
+            let (output, stage_changed) = update(&mut state, &input, &cv);
+            Sample {
+                gate,
+                trigger,
+                output,
+                stage_changed,
+                aux: aux.update(&state.mode, stage_changed),
+            }
+        })
+        .collect()
+}
+
+fn to_csv(samples: &[Sample]) -> String {
+    let mut csv = String::from("sample,time_ms,gate,trigger,output,stage_changed,aux\n");
+    for (i, s) in samples.iter().enumerate() {
+        let time_ms = i as f64 * MICROS_PER_STEP as f64 / 1000.0;
+        writeln!(
+            csv,
+            "{},{:.3},{},{},{},{},{}",
+            i, time_ms, s.gate as u8, s.trigger as u8, s.output, s.stage_changed as u8, s.aux as u8
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// Builds an SVG path that steps between values instead of sloping
+fn step_path(values: impl Iterator<Item = (f64, f64)>) -> String {
+    let mut path = String::new();
+    let mut last_y = None;
+    let mut last_x = 0.0;
+    for (x, y) in values {
+        match last_y {
+            None => write!(path, "M{:.1},{:.1}", x, y).unwrap(),
+            Some(last_y) if last_y != y => {
+                write!(path, "L{:.1},{:.1}L{:.1},{:.1}", x, last_y, x, y).unwrap()
+            }
+            Some(_) => {}
+        }
+        last_y = Some(y);
+        last_x = x;
+    }
+    if let Some(y) = last_y {
+        write!(path, "L{:.1},{:.1}", last_x, y).unwrap();
+    }
+    path
+}
+
+fn to_svg(name: &str, samples: &[Sample]) -> String {
+    const WIDTH: f64 = 1000.0;
+    const PLOT_HEIGHT: f64 = 300.0;
+    const LANE_HEIGHT: f64 = 20.0;
+    const MARGIN: f64 = 20.0;
+
+    let x_scale = WIDTH / samples.len().max(1) as f64;
+    let x = |i: usize| MARGIN + i as f64 * x_scale;
+    let plot_top = MARGIN * 1.5;
+    let plot_bottom = plot_top + PLOT_HEIGHT;
+    let gate_lane = plot_bottom + MARGIN;
+    let aux_lane = gate_lane + LANE_HEIGHT + MARGIN / 2.0;
+    let height = aux_lane + LANE_HEIGHT + MARGIN;
+    let lane = |top: f64, high: bool| if high { top } else { top + LANE_HEIGHT };
+
+    let mut svg = format!(
+        r#"<svg viewBox="0 0 {w} {h}" width="{w}" height="{h}" xmlns="http://www.w3.org/2000/svg" font-family="sans-serif" font-size="12">"#,
+        w = WIDTH + 2.0 * MARGIN,
+        h = height,
+    );
+    writeln!(svg).unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<text x="{}" y="{}">{}</text>"#,
+        MARGIN, MARGIN, name
+    )
+    .unwrap();
+
+    // One grid line every 100ms
+    let samples_per_line = 100_000 / MICROS_PER_STEP as usize;
+    for i in (0..samples.len()).step_by(samples_per_line) {
+        writeln!(
+            svg,
+            r##"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="#ddd"/>"##,
+            plot_top,
+            aux_lane + LANE_HEIGHT,
+            x = x(i),
+        )
+        .unwrap();
+    }
+    writeln!(
+        svg,
+        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#999"/>"##,
+        MARGIN, plot_top, WIDTH, PLOT_HEIGHT
+    )
+    .unwrap();
+
+    let mut output = String::new();
+    for (i, s) in samples.iter().enumerate() {
+        let y = plot_bottom - s.output as f64 / MAX_DAC_VALUE as f64 * PLOT_HEIGHT;
+        write!(
+            output,
+            "{}{:.1},{:.1}",
+            if i == 0 { "M" } else { "L" },
+            x(i),
+            y
+        )
+        .unwrap();
+    }
+    writeln!(
+        svg,
+        r#"<path d="{}" fill="none" stroke="black" stroke-width="1.5"/>"#,
+        output
+    )
+    .unwrap();
+
+    let gate = step_path(
+        samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (x(i), lane(gate_lane, s.gate))),
+    );
+    let aux = step_path(
+        samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (x(i), lane(aux_lane, s.aux))),
+    );
+    writeln!(svg, r##"<path d="{}" fill="none" stroke="#36c"/>"##, gate).unwrap();
+    writeln!(svg, r##"<path d="{}" fill="none" stroke="#c63"/>"##, aux).unwrap();
+    for (i, s) in samples.iter().enumerate() {
+        if s.trigger {
+            writeln!(
+                svg,
+                r##"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="#36c" stroke-width="2"/>"##,
+                gate_lane - MARGIN / 2.0,
+                gate_lane + LANE_HEIGHT,
+                x = x(i),
+            )
+            .unwrap();
+        }
+        if s.stage_changed {
+            writeln!(
+                svg,
+                r##"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="#c33" stroke-dasharray="2,3"/>"##,
+                plot_top,
+                plot_bottom,
+                x = x(i),
+            )
+            .unwrap();
+        }
+    }
+    writeln!(svg, r#"<text x="2" y="{}">gate</text>"#, gate_lane - 4.0).unwrap();
+    writeln!(svg, r#"<text x="2" y="{}">aux</text>"#, aux_lane - 4.0).unwrap();
+    svg += "</svg>\n";
+    svg
+}
+
+fn render(path: &Path, out_dir: &Path) -> Result<(), String> {
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| format!("bad script name {}", path.display()))?
+        .to_owned();
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let script = parse_script(name, &text)?;
+    let samples = run(&script);
+
+    let write = |extension: &str, contents: String| {
+        let out = out_dir.join(&script.name).with_extension(extension);
+        fs::write(&out, contents).map_err(|e| format!("{}: {}", out.display(), e))
+    };
+    write("csv", to_csv(&samples))?;
+    write("svg", to_svg(&script.name, &samples))?;
+    println!("Rendered {} ({} samples)", script.name, samples.len());
+    Ok(())
+}
+
+fn main() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut scripts: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if scripts.is_empty() {
+        let dir = manifest_dir.join("scripts");
+        scripts = fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "txt"))
+            .collect();
+        scripts.sort();
+    }
+
+    let out_dir = manifest_dir.join("renders");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let mut failed = false;
+    for script in scripts {
+        if let Err(e) = render(&script, &out_dir) {
+            eprintln!("{}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,31 @@
+//! Builds the hardware-independent parts of the Envelope firmware for the host
+//! machine so they can be tested with `cargo test`. The firmware crate itself
+//! can only be built for AVR.
+
+#![allow(incomplete_features)]
+#![feature(adt_const_params)]
+// The firmware uses `const fn new()` everywhere so state can live in statics
+#![allow(clippy::new_without_default)]
+// The firmware sources predate this crate and were never linted with a newer
+// clippy. Don't fail the host build on style lints in code shared with AVR.
+#![allow(
+    clippy::collapsible_if,
+    clippy::doc_lazy_continuation,
+    clippy::doc_overindented_list_items,
+    clippy::needless_return,
+    clippy::precedence,
+    clippy::unnecessary_cast
+)]
+
+#[path = "../../../Firmware/src/exponential_curves.rs"]
+pub mod exponential_curves;
+
+// Pointing a module at the source directory (rather than at envelope.rs
+// directly) lets envelope.rs find its own submodules in src/envelope/
+#[path = "../../../Firmware/src"]
+mod firmware {
+    pub mod aux;
+    pub mod envelope;
+}
+
+pub use firmware::{aux, envelope};
@@ -0,0 +1,71 @@
+use host_tests::aux::{AuxMode, AuxOutput, AuxSignal};
+use host_tests::envelope::{AcrcLoopState, AdsrState, AhrdState, EnvelopeMode, MICROS_PER_STEP};
+
+fn output(signal: AuxSignal, inverted: bool) -> AuxOutput {
+    AuxOutput::new(AuxMode { signal, inverted })
+}
+
+/// Counts how many samples a trigger stays high for
+fn trigger_length(aux: &mut AuxOutput, mode: &EnvelopeMode) -> usize {
+    assert!(aux.update(mode, true));
+    1 + (0..1000).take_while(|_| aux.update(mode, false)).count()
+}
+
+#[test]
+fn gates_follow_stage() {
+    let mut aux = output(AuxSignal::EndOfRise, false);
+    assert!(!aux.update(&EnvelopeMode::Adsr(AdsrState::Attack), true));
+    assert!(aux.update(&EnvelopeMode::Adsr(AdsrState::Decay), true));
+    assert!(aux.update(&EnvelopeMode::Adsr(AdsrState::Release), true));
+    assert!(!aux.update(&EnvelopeMode::Adsr(AdsrState::Wait), true));
+}
+
+#[test]
+fn inverted_gates() {
+    let mut aux = output(AuxSignal::NonZero, true);
+    assert!(aux.update(&EnvelopeMode::Adsr(AdsrState::Wait), false));
+    assert!(!aux.update(&EnvelopeMode::Adsr(AdsrState::Attack), true));
+}
+
+#[test]
+fn end_of_cycle_fires_once_per_loop() {
+    let mut aux = output(AuxSignal::EndOfCycle, false);
+    let release = EnvelopeMode::AhrdLoop(AhrdState::Release);
+    let delay = EnvelopeMode::AhrdLoop(AhrdState::Delay);
+    let attack = EnvelopeMode::AhrdLoop(AhrdState::Attack);
+    assert!(!aux.update(&release, true));
+    assert!(!aux.update(&delay, true));
+    assert!(trigger_length(&mut aux, &attack) > 10);
+
+    let mut aux = output(AuxSignal::EndOfCycle, false);
+    assert!(!aux.update(&EnvelopeMode::AcrcLoop(AcrcLoopState::Release), true));
+    assert!(aux.update(&EnvelopeMode::AcrcLoop(AcrcLoopState::Attack), true));
+}
+
+#[test]
+fn stage_change_triggers_end() {
+    let mut aux = output(AuxSignal::StageChange, false);
+    let decay = EnvelopeMode::Adsr(AdsrState::Decay);
+    let length = trigger_length(&mut aux, &decay);
+    // About 10ms at any step rate
+    let micros = length as u32 * MICROS_PER_STEP;
+    assert!((9_500..=10_500).contains(&micros), "{}us", micros);
+    assert!(!aux.update(&decay, false));
+
+    let mut aux = output(AuxSignal::StageChange, true);
+    assert!(aux.update(&decay, false));
+    assert!(!aux.update(&decay, true));
+}
+
+#[test]
+fn jumpers_pick_the_documented_default() {
+    let signal = |pair_1, pair_2| {
+        let mode = AuxMode::from_jumpers(pair_1, pair_2);
+        assert!(!mode.inverted);
+        mode.signal
+    };
+    assert!(signal(false, false) == AuxSignal::EndOfRise);
+    assert!(signal(true, false) == AuxSignal::EndOfFall);
+    assert!(signal(false, true) == AuxSignal::NonZero);
+    assert!(signal(true, true) == AuxSignal::FollowGate);
+}
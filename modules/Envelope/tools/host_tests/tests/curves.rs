@@ -0,0 +1,107 @@
+use fixed::{types::extra::U16, FixedU16};
+use host_tests::exponential_curves::{exp_curve, exp_curve_inverse};
+
+/// The curve `exp_curve` approximates, scaled to 0..=4095
+fn exact_curve(x: f64, c: f64, c_negative: bool) -> f64 {
+    let c = if c_negative { -c } else { c };
+    let y = if c == 0.0 {
+        x
+    } else {
+        ((16.0 * x * c).exp2() - 1.0) / ((16.0 * c).exp2() - 1.0)
+    };
+    y * 4096.0
+}
+
+/// Curve amounts to test, covering the whole range of the knobs. Includes
+/// very small amounts, where the curve is nearly linear.
+fn curve_amounts() -> impl Iterator<Item = (FixedU16<U16>, bool)> {
+    let amounts = (0..=u16::MAX)
+        .step_by(997)
+        .chain([1, 2, 16, 128, 255, 256, 257, 1000]);
+    amounts.flat_map(|bits| {
+        let c = FixedU16::<U16>::from_bits(bits);
+        [(c, false), (c, true)]
+    })
+}
+
+fn inputs() -> impl Iterator<Item = FixedU16<U16>> {
+    (0..=u16::MAX)
+        .step_by(61)
+        .chain([u16::MAX])
+        .map(FixedU16::<U16>::from_bits)
+}
+
+#[test]
+fn exp_curve_error_bound() {
+    let mut worst = (0.0, 0, 0, false);
+    for (c, c_negative) in curve_amounts() {
+        for x in inputs() {
+            let actual = exp_curve(x, c, c_negative) as f64;
+            let expected = exact_curve(x.to_num(), c.to_num(), c_negative);
+            let error = (actual - expected).abs();
+            if error > worst.0 {
+                worst = (error, x.to_bits(), c.to_bits(), c_negative);
+            }
+        }
+    }
+    // Within about one step of the 12 bit output, including truncation
+    assert!(
+        worst.0 < 1.5,
+        "worst error (error, x, c, c_negative): {:?}",
+        worst
+    );
+}
+
+#[test]
+fn exp_curve_inverse_round_trip() {
+    // What matters for the envelopes is that restarting a stage from the
+    // current level doesn't make the output jump
+    let mut worst = (0.0, 0, 0, false);
+    for (c, c_negative) in curve_amounts() {
+        for level in (0..4096u16).step_by(7) {
+            let level_frac = FixedU16::<U16>::from_bits(level << 4);
+            let x = exp_curve_inverse(level_frac, c, c_negative);
+            let round_trip = exp_curve(x, c, c_negative) as f64;
+            let error = (round_trip - level as f64).abs();
+            if error > worst.0 {
+                worst = (error, level, c.to_bits(), c_negative);
+            }
+        }
+    }
+    assert!(
+        worst.0 <= 1.0,
+        "worst error (error, level, c, c_negative): {:?}",
+        worst
+    );
+}
+
+/// The exact inverse of `exact_curve`, for a level between 0 and 1
+fn exact_inverse(level: f64, c: f64, c_negative: bool) -> f64 {
+    let c = if c_negative { -c } else { c };
+    if c == 0.0 {
+        level
+    } else {
+        (level * ((16.0 * c).exp2() - 1.0) + 1.0).log2() / (16.0 * c)
+    }
+}
+
+#[test]
+fn exp_curve_inverse_error_bound() {
+    let mut worst = (0.0, 0, 0, false);
+    for (c, c_negative) in curve_amounts() {
+        for level in inputs() {
+            let actual: f64 = exp_curve_inverse(level, c, c_negative).to_num();
+            let expected = exact_inverse(level.to_num(), c.to_num(), c_negative);
+            let error = (actual - expected).abs() * 65536.0;
+            if error > worst.0 {
+                worst = (error, level.to_bits(), c.to_bits(), c_negative);
+            }
+        }
+    }
+    // Measured in steps of the (0.16) result
+    assert!(
+        worst.0 < 8.0,
+        "worst error (error, level, c, c_negative): {:?}",
+        worst
+    );
+}
@@ -0,0 +1,405 @@
+use std::{fs, path::PathBuf};
+
+use host_tests::envelope::{
+    update, AcrcLoopState, AcrcState, AdsrCurve, AdsrState, AhrdState, ClockSync, EnvelopeMode,
+    EnvelopeState, GateState, Input, RetriggerPolicy, StageInfo, MAX_DAC_VALUE,
+    MAX_PHASE_TIME_MICROS, MICROS_PER_STEP, RC_CURVATURES,
+};
+
+// Raw cv readings are inverted in hardware: lower readings are longer times
+// and higher levels. These are slow enough that a single sample never moves
+// the output very far.
+const SLOW: u16 = 780;
+const LINEAR_CURVE: u16 = 488;
+const HALF: u16 = 488;
+// Any jump bigger than this is a discontinuity
+const MAX_STEP: u16 = 64;
+
+fn new_state(mode: EnvelopeMode) -> EnvelopeState {
+    EnvelopeState {
+        mode,
+        time: 0,
+        last_value: 0,
+        artificial_gate: false,
+        retrigger: RetriggerPolicy::Continue,
+        adsr_curve: AdsrCurve::Linear,
+        sync: None,
+        clock: ClockSync::new(),
+        velocity: None,
+        peak: MAX_DAC_VALUE,
+    }
+}
+
+struct Sample {
+    output: u16,
+    rollover: bool,
+    mode: EnvelopeMode,
+}
+
+/// Drives an envelope one sample at a time from a plain gate level
+struct Sim {
+    state: EnvelopeState,
+    gate: bool,
+    cv: [u16; 4],
+}
+
+impl Sim {
+    fn new(mode: EnvelopeMode, cv: [u16; 4]) -> Self {
+        Self {
+            state: new_state(mode),
+            gate: false,
+            cv,
+        }
+    }
+
+    fn step(&mut self, gate: bool, trigger: bool) -> Sample {
+        let input = Input {
+            gate: match (self.gate, gate) {
+                (true, true) => GateState::High,
+                (true, false) => GateState::Falling,
+                (false, true) => GateState::Rising,
+                (false, false) => GateState::Low,
+            },
+            trigger,
+        };
+        self.gate = gate;
+        let (output, rollover) = update(&mut self.state, &input, &self.cv);
+        Sample {
+            output,
+            rollover,
+            mode: self.state.mode,
+        }
+    }
+
+    fn run(&mut self, gate: bool, samples: usize) -> Vec<Sample> {
+        (0..samples).map(|_| self.step(gate, false)).collect()
+    }
+}
+
+fn adsr() -> EnvelopeMode {
+    EnvelopeMode::Adsr(AdsrState::default())
+}
+
+fn acrc() -> EnvelopeMode {
+    EnvelopeMode::Acrc(AcrcState::default())
+}
+
+fn acrc_loop() -> EnvelopeMode {
+    EnvelopeMode::AcrcLoop(AcrcLoopState::default())
+}
+
+fn ahrd_loop() -> EnvelopeMode {
+    EnvelopeMode::AhrdLoop(AhrdState::default())
+}
+
+fn adsr_rc(curvature: u8) -> Sim {
+    let mut sim = Sim::new(adsr(), [SLOW, SLOW, HALF, SLOW]);
+    sim.state.adsr_curve = AdsrCurve::Rc(curvature);
+    sim
+}
+
+/// Every mode with knob settings that make each stage last long enough to
+/// interrupt it partway through
+fn all_modes() -> Vec<(&'static str, Sim)> {
+    vec![
+        ("adsr", Sim::new(adsr(), [SLOW, SLOW, HALF, SLOW])),
+        ("adsr_rc", adsr_rc(1)),
+        ("adsr_rc_strong", adsr_rc(RC_CURVATURES)),
+        (
+            "acrc",
+            Sim::new(acrc(), [SLOW, LINEAR_CURVE, SLOW, LINEAR_CURVE]),
+        ),
+        ("acrc_curved", Sim::new(acrc(), [SLOW, 200, SLOW, 800])),
+        ("acrc_loop", Sim::new(acrc_loop(), [SLOW, 300, SLOW, 700])),
+        ("ahrd_loop", Sim::new(ahrd_loop(), [SLOW, SLOW, SLOW, SLOW])),
+    ]
+}
+
+/// Number of samples a stage with the given time knob should take, worked
+/// out from the knob curve documented in `read_cv`
+fn expected_stage_samples(cv: u16) -> f64 {
+    let x = 977u16.saturating_sub(cv) as f64;
+    let numerator = if x < 512.0 {
+        x / 4.0
+    } else if x < 768.0 {
+        x - 384.0
+    } else {
+        3.0 * x - 1920.0
+    };
+    let fraction = f64::min(numerator / 1024.0, 1.0);
+    f64::max(
+        1.0,
+        fraction * MAX_PHASE_TIME_MICROS as f64 / MICROS_PER_STEP as f64,
+    )
+}
+
+/// The number of samples in the given amount of time
+fn samples_in_millis(millis: u32) -> usize {
+    (millis * 1000 / MICROS_PER_STEP) as usize
+}
+
+fn stage(mode: &EnvelopeMode) -> StageInfo {
+    mode.stage_info()
+}
+
+fn same_stage(a: &EnvelopeMode, b: &EnvelopeMode) -> bool {
+    match (a, b) {
+        (EnvelopeMode::Adsr(a), EnvelopeMode::Adsr(b)) => a == b,
+        (EnvelopeMode::Acrc(a), EnvelopeMode::Acrc(b)) => a == b,
+        (EnvelopeMode::AcrcLoop(a), EnvelopeMode::AcrcLoop(b)) => a == b,
+        (EnvelopeMode::AhrdLoop(a), EnvelopeMode::AhrdLoop(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Plays one note: the gate goes high for `on` samples and then low for `off`
+fn note(sim: &mut Sim, on: usize, off: usize) -> Vec<Sample> {
+    let mut samples = sim.run(true, on);
+    samples.extend(sim.run(false, off));
+    samples
+}
+
+fn assert_continuous(name: &str, samples: &[Sample], context: &str) {
+    for (i, pair) in samples.windows(2).enumerate() {
+        let step = pair[0].output.abs_diff(pair[1].output);
+        assert!(
+            step <= MAX_STEP,
+            "{}: jumped by {} at sample {} ({})",
+            name,
+            step,
+            i + 1,
+            context
+        );
+    }
+}
+
+/// Holds the gate for 2500 samples and then releases it, stopping after
+/// `samples` samples
+fn partial_note(sim: &mut Sim, samples: usize) -> Vec<Sample> {
+    let mut result = sim.run(true, samples.min(2500));
+    result.extend(sim.run(false, samples.saturating_sub(2500)));
+    result
+}
+
+fn with_settings_of(template: &Sim) -> Sim {
+    let mut sim = Sim::new(template.state.mode, template.cv);
+    sim.state.adsr_curve = template.state.adsr_curve;
+    sim
+}
+
+fn check_regate_at_any_point(name: &str, template: &Sim) {
+    // Re-gate at lots of points throughout the note, covering every stage,
+    // both while the gate is high and during the release
+    for regate_at in (1..6000).step_by(97) {
+        let mut sim = with_settings_of(template);
+        let mut samples = partial_note(&mut sim, regate_at);
+        if regate_at < 2500 {
+            // Release early, partway through the stage
+            samples.extend(sim.run(false, 97));
+        }
+        samples.extend(note(&mut sim, 500, 500));
+        assert_continuous(name, &samples, &format!("re-gated at {}", regate_at));
+    }
+}
+
+fn check_trigger_at_any_point(name: &str, template: &Sim) {
+    for trigger_at in (1..6000).step_by(97) {
+        let mut sim = with_settings_of(template);
+        let mut samples = partial_note(&mut sim, trigger_at);
+        samples.push(sim.step(sim.gate, true));
+        samples.extend(sim.run(false, 3000));
+        assert_continuous(name, &samples, &format!("triggered at {}", trigger_at));
+    }
+}
+
+#[test]
+fn no_jumps_when_regated_at_any_point() {
+    for (name, template) in all_modes() {
+        check_regate_at_any_point(name, &template);
+    }
+}
+
+#[test]
+fn no_jumps_when_triggered_at_any_point() {
+    for (name, template) in all_modes() {
+        check_trigger_at_any_point(name, &template);
+    }
+}
+
+#[test]
+fn stages_are_monotonic() {
+    for (name, mut sim) in all_modes() {
+        let samples = note(&mut sim, 4000, 6000);
+        for pair in samples.windows(2) {
+            // The sample that changes stage can still belong to the last one
+            if !same_stage(&pair[0].mode, &pair[1].mode) || pair[0].rollover || pair[1].rollover {
+                continue;
+            }
+            let info = stage(&pair[1].mode);
+            if info.past_rise {
+                assert!(
+                    pair[1].output <= pair[0].output,
+                    "{}: rose from {} to {} while falling",
+                    name,
+                    pair[0].output,
+                    pair[1].output
+                );
+            } else {
+                assert!(
+                    pair[1].output >= pair[0].output,
+                    "{}: fell from {} to {} while rising",
+                    name,
+                    pair[0].output,
+                    pair[1].output
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn stage_changes_are_flagged() {
+    for (name, mut sim) in all_modes() {
+        let mut samples = note(&mut sim, 4000, 3000);
+        samples.push(sim.step(false, true));
+        samples.extend(note(&mut sim, 1000, 3000));
+        let mut changes = 0;
+        for (i, pair) in samples.windows(2).enumerate() {
+            if !same_stage(&pair[0].mode, &pair[1].mode) {
+                changes += 1;
+                assert!(
+                    pair[1].rollover,
+                    "{}: stage changed without a rollover at sample {}",
+                    name,
+                    i + 1
+                );
+            }
+        }
+        assert!(
+            changes >= 3,
+            "{}: only changed stage {} times",
+            name,
+            changes
+        );
+    }
+}
+
+/// Counts how many samples the envelope spends in the stage it is in right
+/// after `start` samples
+fn stage_length(samples: &[Sample], start: usize) -> usize {
+    let mode = &samples[start].mode;
+    samples[start..]
+        .iter()
+        .take_while(|s| same_stage(&s.mode, mode))
+        .count()
+}
+
+fn assert_stage_length(name: &str, actual: usize, cv: u16) {
+    let expected = expected_stage_samples(cv);
+    // Stages start on the sample after the previous one ends, and the last
+    // step of a stage can be cut short by rounding
+    let tolerance = f64::max(2.0, expected * 0.002);
+    assert!(
+        (actual as f64 - expected).abs() <= tolerance,
+        "{}: took {} samples with cv {}, expected {:.1}",
+        name,
+        actual,
+        cv,
+        expected
+    );
+}
+
+#[test]
+fn stage_times_follow_knobs() {
+    for cv in [0, 100, 300, 500, 700, 900, 970] {
+        let mut sim = Sim::new(adsr(), [cv, cv, HALF, cv]);
+        let samples = sim.run(true, samples_in_millis(21_600));
+        assert_stage_length("adsr attack", stage_length(&samples, 0), cv);
+        let decay_start = stage_length(&samples, 0);
+        assert_stage_length("adsr decay", stage_length(&samples, decay_start), cv);
+
+        let mut sim = Sim::new(acrc(), [cv, LINEAR_CURVE, cv, LINEAR_CURVE]);
+        let half = samples_in_millis(12_000);
+        let mut samples = sim.run(true, half);
+        assert_stage_length("acrc attack", stage_length(&samples, 0), cv);
+        samples.extend(sim.run(false, half));
+        assert_stage_length("acrc release", stage_length(&samples, half), cv);
+
+        let mut sim = Sim::new(ahrd_loop(), [cv; 4]);
+        let samples = sim.run(false, samples_in_millis(48_000));
+        let mut start = 0;
+        for stage in ["attack", "hold", "release", "delay"] {
+            let length = stage_length(&samples, start);
+            assert_stage_length(&format!("ahrd {}", stage), length, cv);
+            start += length;
+        }
+    }
+}
+
+#[test]
+fn longest_stage_is_about_ten_seconds() {
+    let mut sim = Sim::new(adsr(), [0, 0, HALF, 0]);
+    let samples = sim.run(true, samples_in_millis(12_000));
+    let seconds = (stage_length(&samples, 0) as u32 * MICROS_PER_STEP) as f64 / 1_000_000.0;
+    assert!((9.5..=10.0).contains(&seconds), "took {}s", seconds);
+}
+
+#[test]
+fn loops_keep_running_without_input() {
+    for mode in [acrc_loop(), ahrd_loop()] {
+        let mut sim = Sim::new(mode, [SLOW; 4]);
+        let samples = sim.run(false, samples_in_millis(9_600));
+        let cycles = samples
+            .windows(2)
+            .filter(|pair| {
+                !same_stage(&pair[0].mode, &pair[1].mode) && stage(&pair[1].mode).ends_cycle
+            })
+            .count();
+        assert!(cycles >= 3, "only looped {} times", cycles);
+    }
+}
+
+/// Compares the output of a fixed scenario against a file saved from a known
+/// good version. Run with `UPDATE_GOLDEN=1` to save new outputs after an
+/// intentional change, and review the difference with the renderer.
+fn check_golden(name: &str, samples: &[Sample]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+        .with_extension("txt");
+    let actual: String = samples
+        .iter()
+        .map(|s| format!("{} {}\n", s.output, s.rollover as u8))
+        .collect();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}. Run with UPDATE_GOLDEN=1", path.display(), e));
+    for (i, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(a, e, "{}: differs at sample {}", name, i);
+    }
+    assert_eq!(
+        actual.lines().count(),
+        expected.lines().count(),
+        "{}: different length",
+        name
+    );
+}
+
+#[test]
+fn golden_outputs() {
+    for (name, mut sim) in all_modes() {
+        // A full note, then a re-gate during the release and a trigger during
+        // the sustain
+        let mut samples = note(&mut sim, 2000, 1200);
+        samples.extend(sim.run(true, 800));
+        samples.push(sim.step(true, true));
+        samples.extend(sim.run(false, 1500));
+        check_golden(name, &samples);
+    }
+}
@@ -0,0 +1,158 @@
+use host_tests::envelope::{
+    synced_cycle_length, synced_stage_length, ClockSync, LoopTiming, SyncRatio,
+    MAX_PHASE_TIME_MICROS, MICROS_PER_STEP, SYNC_RATIOS,
+};
+use proptest::prelude::*;
+
+const ONE_TO_ONE: SyncRatio = SyncRatio::new(1, 1);
+
+/// Feeds a steady clock with the given period into `sync` and returns which
+/// edges were sync edges
+fn run_clock(sync: &mut ClockSync, period: u32, edges: usize, ratio: SyncRatio) -> Vec<bool> {
+    let mut result = Vec::new();
+    for _ in 0..edges {
+        for _ in 1..period {
+            assert!(!sync.tick(false, ratio));
+        }
+        result.push(sync.tick(true, ratio));
+    }
+    result
+}
+
+#[test]
+fn no_period_before_two_edges() {
+    let mut sync = ClockSync::new();
+    assert_eq!(sync.period(), None);
+    assert_eq!(sync.cycle_length(ONE_TO_ONE), None);
+
+    sync.tick(true, ONE_TO_ONE);
+    assert_eq!(sync.period(), None);
+}
+
+#[test]
+fn measures_steady_clock() {
+    let mut sync = ClockSync::new();
+    run_clock(&mut sync, 500, 4, ONE_TO_ONE);
+    assert_eq!(sync.period(), Some(500));
+    assert_eq!(sync.cycle_length(ONE_TO_ONE), Some(500));
+}
+
+#[test]
+fn follows_tempo_changes() {
+    let mut sync = ClockSync::new();
+    run_clock(&mut sync, 500, 4, ONE_TO_ONE);
+    run_clock(&mut sync, 300, 1, ONE_TO_ONE);
+    assert_eq!(sync.period(), Some(300));
+}
+
+#[test]
+fn forgets_stopped_clock() {
+    let mut sync = ClockSync::new();
+    run_clock(&mut sync, 500, 4, ONE_TO_ONE);
+    // The longest phase is 10 seconds
+    for _ in 0..=MAX_PHASE_TIME_MICROS / MICROS_PER_STEP {
+        sync.tick(false, ONE_TO_ONE);
+    }
+    assert_eq!(sync.period(), None);
+
+    // A single edge after a long gap doesn't give a usable period either
+    sync.tick(true, ONE_TO_ONE);
+    assert_eq!(sync.period(), None);
+}
+
+#[test]
+fn sync_edges_follow_ratio() {
+    for ratio in SYNC_RATIOS {
+        let mut sync = ClockSync::new();
+        let edges = run_clock(&mut sync, 100, 12, ratio);
+        for (i, is_sync) in edges.iter().enumerate() {
+            assert_eq!(
+                *is_sync,
+                i % ratio.clocks as usize == 0,
+                "edge {} with ratio {:?}",
+                i,
+                ratio
+            );
+        }
+    }
+}
+
+#[test]
+fn cycle_length_follows_ratio() {
+    assert_eq!(synced_cycle_length(600, SyncRatio::new(1, 4)), 150);
+    assert_eq!(synced_cycle_length(600, SyncRatio::new(1, 3)), 200);
+    assert_eq!(synced_cycle_length(600, SyncRatio::new(1, 1)), 600);
+    assert_eq!(synced_cycle_length(600, SyncRatio::new(3, 1)), 1800);
+}
+
+#[test]
+fn cycle_length_is_never_zero() {
+    let mut sync = ClockSync::new();
+    run_clock(&mut sync, 2, 4, SyncRatio::new(1, 4));
+    assert_eq!(sync.cycle_length(SyncRatio::new(1, 4)), Some(1));
+}
+
+#[test]
+fn free_timing_ignores_other_stages() {
+    for cv in [0, 100, 2000, 4095] {
+        assert_eq!(
+            LoopTiming::Free.delta_t(0, &[cv, 0]),
+            LoopTiming::Free.delta_t(0, &[cv, 4095])
+        );
+    }
+}
+
+/// Number of samples it takes for a phase to roll over at the given rate
+fn samples_per_stage(dt: u32) -> u64 {
+    (u32::MAX as u64).div_ceil(dt as u64)
+}
+
+proptest! {
+    #[test]
+    fn stage_lengths_keep_proportions(
+        a in 1u32..20_000,
+        b in 1u32..20_000,
+        cycle_length in 1u32..(1 << 17),
+    ) {
+        let total = a + b;
+        let length_a = synced_stage_length(a, total, cycle_length);
+        let length_b = synced_stage_length(b, total, cycle_length);
+        prop_assert!(length_a >= 1 && length_b >= 1);
+        // Rounding down loses at most one sample per stage, and the minimum
+        // stage length adds at most one
+        prop_assert!((length_a + length_b).abs_diff(cycle_length) <= 2);
+        if a <= b {
+            prop_assert!(length_a <= length_b);
+        } else {
+            prop_assert!(length_a >= length_b);
+        }
+    }
+
+    #[test]
+    fn long_stages_do_not_overflow(
+        a in 1u32..400_000,
+        b in 1u32..400_000,
+        cycle_length in 1u32..(1 << 18),
+    ) {
+        let total = a + b;
+        let exact = a as u64 * cycle_length as u64 / total as u64;
+        prop_assert_eq!(
+            synced_stage_length(a, total, cycle_length) as u64,
+            u64::max(1, exact)
+        );
+    }
+
+    #[test]
+    fn synced_stages_add_up_to_cycle(
+        cv_a in 0u16..4096,
+        cv_b in 0u16..4096,
+        cycle_length in 16u32..(1 << 17),
+    ) {
+        let cvs = [cv_a, cv_b];
+        let timing = LoopTiming::Synced { cycle_length };
+        let total: u64 = (0..cvs.len())
+            .map(|i| samples_per_stage(timing.delta_t(i, &cvs)))
+            .sum();
+        prop_assert!(total.abs_diff(cycle_length as u64) <= 4);
+    }
+}
@@ -0,0 +1,124 @@
+use host_tests::envelope::{
+    update, AdsrCurve, AdsrState, ClockSync, EnvelopeMode, EnvelopeState, GateState, Input,
+    RetriggerPolicy, MAX_DAC_VALUE, MICROS_PER_STEP,
+};
+
+// Raw cv readings are inverted in hardware: lower readings are higher levels
+// and longer times
+const FAST: u16 = 900;
+const HALF: u16 = 488;
+const FULL: u16 = 0;
+
+fn adsr_state(velocity: Option<u8>) -> EnvelopeState {
+    EnvelopeState {
+        mode: EnvelopeMode::Adsr(AdsrState::default()),
+        time: 0,
+        last_value: 0,
+        artificial_gate: false,
+        retrigger: RetriggerPolicy::Continue,
+        adsr_curve: AdsrCurve::Linear,
+        sync: None,
+        clock: ClockSync::new(),
+        velocity,
+        peak: MAX_DAC_VALUE,
+    }
+}
+
+/// Runs the envelope with the gate held high for `millis` ms (starting with a
+/// rising edge) and returns every output
+fn hold_gate(state: &mut EnvelopeState, cv: &[u16; 4], millis: u32) -> Vec<u16> {
+    let samples = millis * 1000 / MICROS_PER_STEP;
+    (0..samples)
+        .map(|i| {
+            let gate = if i == 0 {
+                GateState::Rising
+            } else {
+                GateState::High
+            };
+            let input = Input {
+                gate,
+                trigger: false,
+            };
+            update(state, &input, cv).0
+        })
+        .collect()
+}
+
+fn max_step(values: &[u16]) -> u16 {
+    values
+        .windows(2)
+        .map(|w| w[0].abs_diff(w[1]))
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn full_range_without_velocity() {
+    let mut state = adsr_state(None);
+    let out = hold_gate(&mut state, &[FAST, FAST, HALF, HALF], 240);
+    assert_eq!(*out.iter().max().unwrap(), MAX_DAC_VALUE);
+}
+
+#[test]
+fn velocity_scales_peak_and_sustain() {
+    let cv = [FAST, FAST, HALF, HALF];
+
+    let mut full = adsr_state(None);
+    let full_out = hold_gate(&mut full, &cv, 240);
+
+    let mut scaled = adsr_state(Some(3));
+    let scaled_out = hold_gate(&mut scaled, &cv, 240);
+
+    let peak = *scaled_out.iter().max().unwrap();
+    assert!(peak.abs_diff(MAX_DAC_VALUE / 2) < 16, "peak was {}", peak);
+
+    // Sustain stays at the same fraction of the peak
+    let full_sustain = *full_out.last().unwrap() as u32;
+    let scaled_sustain = *scaled_out.last().unwrap() as u32;
+    let expected = full_sustain * (peak as u32 + 1) / (MAX_DAC_VALUE as u32 + 1);
+    assert!(scaled_sustain.abs_diff(expected) <= 1);
+}
+
+#[test]
+fn velocity_is_sampled_at_gate_on() {
+    let mut cv = [FAST, FAST, FULL, HALF];
+    let mut state = adsr_state(Some(3));
+    let out = hold_gate(&mut state, &cv, 240);
+    let sustain = *out.last().unwrap();
+
+    // Turning the knob mid-note doesn't change the level
+    cv[3] = FULL;
+    let input = Input {
+        gate: GateState::High,
+        trigger: false,
+    };
+    for _ in 0..100 {
+        assert_eq!(update(&mut state, &input, &cv).0, sustain);
+    }
+}
+
+#[test]
+fn new_velocity_does_not_jump() {
+    let mut state = adsr_state(Some(3));
+    let mut out = hold_gate(&mut state, &[FAST, FAST, FULL, FULL], 240);
+    assert_eq!(*out.last().unwrap(), MAX_DAC_VALUE);
+
+    // Start a softer note partway through the release. It has to continue
+    // from where the last one left off.
+    let release = Input {
+        gate: GateState::Falling,
+        trigger: false,
+    };
+    let cv = [FAST, FAST, FULL, FAST];
+    out.push(update(&mut state, &release, &cv).0);
+    let input = Input {
+        gate: GateState::Low,
+        trigger: false,
+    };
+    while *out.last().unwrap() > MAX_DAC_VALUE / 4 {
+        out.push(update(&mut state, &input, &cv).0);
+    }
+    out.extend(hold_gate(&mut state, &[FAST, FAST, FULL, HALF], 240));
+    assert!(max_step(&out) < 64, "max step was {}", max_step(&out));
+    assert!(out.last().unwrap().abs_diff(MAX_DAC_VALUE / 2) < 16);
+}
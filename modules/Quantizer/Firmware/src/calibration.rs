@@ -0,0 +1,107 @@
+use fixed::types::{I16F16, I2F14, I8F8};
+
+// The two reference pitches used for calibration: 1V and 3V
+pub const CALIBRATION_REFERENCE_SEMITONES: [i8; 2] = [12, 36];
+
+const MAX_GAIN_ERROR: I16F16 = I16F16::from_bits(1 << 13);
+const MAX_OFFSET_ERROR: I16F16 = I16F16::from_bits(4 << 16);
+
+/**
+A linear trim applied to a pitch value in semitones. Used both to correct the
+reading from an input and to pre-distort a value sent to the DAC so the voltage
+that actually comes out of the jack is correct.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LinearCorrection {
+    pub gain: I2F14,
+    pub offset: I8F8,
+}
+
+impl LinearCorrection {
+    pub const IDENTITY: Self = Self {
+        gain: I2F14::ONE,
+        offset: I8F8::ZERO,
+    };
+
+    /**
+    Computes the correction that maps the two `measured` values onto the two
+    `expected` values. Returns `None` if the measurements are so far off that
+    something was probably patched wrong.
+    */
+    pub fn from_measurements(measured: [I16F16; 2], expected: [I8F8; 2]) -> Option<Self> {
+        let expected_low = I16F16::from_num(expected[0]);
+        let expected_high = I16F16::from_num(expected[1]);
+        let measured_span = measured[1] - measured[0];
+        if measured_span <= 0 {
+            return None;
+        }
+
+        let gain = (expected_high - expected_low) / measured_span;
+        let offset = expected_low - gain * measured[0];
+
+        if (gain - I16F16::ONE).abs() > MAX_GAIN_ERROR || offset.abs() > MAX_OFFSET_ERROR {
+            return None;
+        }
+
+        Some(Self {
+            gain: I2F14::from_num(gain),
+            offset: I8F8::from_num(offset),
+        })
+    }
+
+    /**
+    Applies the correction, clamping the result to the 0-120 semitone range
+    supported by the hardware
+    */
+    pub fn apply(&self, semitones: I8F8) -> I8F8 {
+        let scaled = (semitones.to_bits() as i32 * self.gain.to_bits() as i32) >> 14;
+        let bits = (scaled + self.offset.to_bits() as i32).clamp(0, 120 << 8);
+        I8F8::from_bits(bits as i16)
+    }
+
+    pub fn from_bytes(bytes: &[u8; 4]) -> Self {
+        Self {
+            gain: I2F14::from_le_bytes([bytes[0], bytes[1]]),
+            offset: I8F8::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let gain = self.gain.to_le_bytes();
+        let offset = self.offset.to_le_bytes();
+        [gain[0], gain[1], offset[0], offset[1]]
+    }
+}
+
+/**
+Per-channel trims for both the v/oct inputs and the DAC outputs
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Calibration {
+    pub inputs: [LinearCorrection; 2],
+    pub outputs: [LinearCorrection; 2],
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Self {
+        inputs: [LinearCorrection::IDENTITY; 2],
+        outputs: [LinearCorrection::IDENTITY; 2],
+    };
+
+    pub fn from_bytes(bytes: &[u8; 16]) -> Self {
+        let correction =
+            |i: usize| LinearCorrection::from_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        Self {
+            inputs: [correction(0), correction(1)],
+            outputs: [correction(2), correction(3)],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        for (i, correction) in self.inputs.iter().chain(self.outputs.iter()).enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&correction.to_bytes());
+        }
+        bytes
+    }
+}
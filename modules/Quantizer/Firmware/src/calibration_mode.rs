@@ -0,0 +1,175 @@
+use fixed::types::{I16F16, I8F8};
+use fm_lib::button_debouncer::LongPressButtonState;
+
+use crate::{
+    calibration::{Calibration, LinearCorrection, CALIBRATION_REFERENCE_SEMITONES},
+    menu::LedColor,
+    resistor_ladder_buttons::ButtonEvent,
+};
+
+const NUM_STEPS: u8 = 8;
+const SAMPLES_PER_MEASUREMENT: u8 = 64;
+const ERROR_DISPLAY_TIME_MS: u32 = 768;
+
+/**
+Interactive routine to trim the v/oct inputs and outputs. Entered by holding
+shift and save while powering on the module.
+
+First, for each input (A then B), patch a 1V reference into it and press any
+key, then patch a 3V reference and press any key again. The LED for the
+expected voltage is lit in the channel's color while waiting.
+
+Then, for each output (A then B), patch the output back into the matching input
+and press any key twice. The module sends each reference voltage itself and
+measures it through the freshly calibrated input. The LED blinks during these
+steps.
+
+If a measurement is way off, all LEDs flash red and the pair of measurements
+has to be repeated. Pressing load at any time exits without saving.
+*/
+pub struct CalibrationMode {
+    step: u8,
+    calibration: Calibration,
+    measurements: [I16F16; 2],
+    sample_sum: i32,
+    samples_remaining: u8,
+    error_start_time: Option<u32>,
+}
+
+pub enum CalibrationStatus {
+    InProgress,
+    Finished(Calibration),
+    Cancelled,
+}
+
+impl CalibrationMode {
+    pub fn new() -> Self {
+        Self {
+            step: 0,
+            calibration: Calibration::IDENTITY,
+            measurements: [I16F16::ZERO; 2],
+            sample_sum: 0,
+            samples_remaining: 0,
+            error_start_time: None,
+        }
+    }
+
+    fn channel(&self) -> usize {
+        ((self.step / 2) % 2) as usize
+    }
+
+    fn is_output_step(&self) -> bool {
+        self.step >= NUM_STEPS / 2
+    }
+
+    fn reference_index(&self) -> usize {
+        (self.step % 2) as usize
+    }
+
+    /**
+    Should be called once per sample in place of the normal quantizer logic.
+    `raw_input_semitones` should be read from the ADC without any correction.
+    */
+    pub fn step(
+        &mut self,
+        raw_input_semitones: [I8F8; 2],
+        key_event: &ButtonEvent,
+        load_button: &LongPressButtonState,
+        current_time_ms: u32,
+    ) -> CalibrationStatus {
+        if *load_button == LongPressButtonState::ButtonJustDown {
+            return CalibrationStatus::Cancelled;
+        }
+
+        if self.samples_remaining == 0 {
+            if let ButtonEvent::ButtonJustPressed(_) = key_event {
+                self.samples_remaining = SAMPLES_PER_MEASUREMENT;
+                self.sample_sum = 0;
+                self.error_start_time = None;
+            }
+            return CalibrationStatus::InProgress;
+        }
+
+        let channel = self.channel();
+        let reading = if self.is_output_step() {
+            self.calibration.inputs[channel].apply(raw_input_semitones[channel])
+        } else {
+            raw_input_semitones[channel]
+        };
+        self.sample_sum += reading.to_bits() as i32;
+        self.samples_remaining -= 1;
+        if self.samples_remaining > 0 {
+            return CalibrationStatus::InProgress;
+        }
+
+        // Averaging gives us a few more bits of precision than a single reading
+        self.measurements[self.reference_index()] =
+            I16F16::from_bits(self.sample_sum * (1 << 8) / SAMPLES_PER_MEASUREMENT as i32);
+
+        if self.reference_index() == 0 {
+            self.step += 1;
+            return CalibrationStatus::InProgress;
+        }
+
+        let expected = CALIBRATION_REFERENCE_SEMITONES.map(I8F8::from_num);
+        match LinearCorrection::from_measurements(self.measurements, expected) {
+            Some(correction) => {
+                if self.is_output_step() {
+                    self.calibration.outputs[channel] = correction;
+                } else {
+                    self.calibration.inputs[channel] = correction;
+                }
+                self.step += 1;
+                if self.step == NUM_STEPS {
+                    return CalibrationStatus::Finished(self.calibration);
+                }
+            }
+            None => {
+                self.step -= 1;
+                self.error_start_time = Some(current_time_ms);
+            }
+        }
+
+        CalibrationStatus::InProgress
+    }
+
+    /**
+    The channel and uncorrected pitch that should be sent to the DAC during the
+    current step, if any
+    */
+    pub fn dac_output(&self) -> Option<(usize, I8F8)> {
+        if !self.is_output_step() {
+            return None;
+        }
+
+        Some((
+            self.channel(),
+            I8F8::from_num(CALIBRATION_REFERENCE_SEMITONES[self.reference_index()]),
+        ))
+    }
+
+    pub fn render(&self, current_time_ms: u32) -> [LedColor; 12] {
+        if let Some(start) = self.error_start_time {
+            if current_time_ms - start < ERROR_DISPLAY_TIME_MS {
+                return [LedColor::RED; 12];
+            }
+        }
+
+        let mut leds = [LedColor::OFF; 12];
+        if self.is_output_step() && current_time_ms & 128 != 0 {
+            return leds;
+        }
+
+        let color = if self.samples_remaining > 0 {
+            LedColor::AMBER
+        } else if self.channel() == 0 {
+            LedColor::GREEN
+        } else {
+            LedColor::RED
+        };
+        let volts = CALIBRATION_REFERENCE_SEMITONES[self.reference_index()] / 12;
+        leds[volts as usize] = color;
+
+        leds
+    }
+}
@@ -10,10 +10,13 @@
 #![feature(cell_update)]
 
 mod bitvec;
+mod calibration;
+mod calibration_mode;
 mod menu;
 mod persistence;
 mod quantizer;
 mod resistor_ladder_buttons;
+mod scale_library;
 
 use core::cell::Cell;
 
@@ -24,6 +27,8 @@ use arduino_hal::prelude::*;
 use arduino_hal::Spi;
 use avr_device::interrupt;
 use avr_device::interrupt::Mutex;
+use calibration::LinearCorrection;
+use calibration_mode::{CalibrationMode, CalibrationStatus};
 use embedded_hal::digital::v2::OutputPin;
 use fixed::traits::FromFixed as _;
 use fixed::types::I1F15;
@@ -43,6 +48,7 @@ use fm_lib::{
 };
 use menu::ButtonInput;
 use menu::{LedColor, MenuState};
+use persistence::{migrate_config_slots, read_calibration, write_calibration};
 use quantizer::QuantizationResult;
 use quantizer::QuantizerState;
 use resistor_ladder_buttons::ButtonLadderState;
@@ -120,6 +126,18 @@ fn main() -> ! {
     let trig_input_pin_a = pins.d2.into_floating_input();
     let trig_input_pin_b = pins.d3.into_floating_input();
 
+    let save_button_pin = pins.d7.into_pull_up_input();
+    let load_button_pin = pins.d6.into_pull_up_input();
+    delay_ms(1);
+
+    let mut calibration = read_calibration(&mut eeprom);
+    let mut calibration_mode = if shift_btn_pin.is_low() && save_button_pin.is_low() {
+        Some(CalibrationMode::new())
+    } else {
+        None
+    };
+
+    migrate_config_slots(&mut eeprom);
     let mut quantizer_state = QuantizerState::new();
     let mut menu_state = MenuState::new(&mut eeprom);
 
@@ -134,10 +152,11 @@ fn main() -> ! {
 
     let sys_clock = SystemClock::init_system_clock(dp.TC0, &SYSTEM_CLOCK_STATE);
     let mut button_state = ButtonLadderState::new();
-    let mut save_button = ButtonWithLongPress::<PD7, 32, 2000>::new(pins.d7.into_pull_up_input());
-    let mut load_button = ButtonWithLongPress::<PD6, 32, 2000>::new(pins.d6.into_pull_up_input());
+    let mut save_button = ButtonWithLongPress::<PD7, 32, 2000>::new(save_button_pin);
+    let mut load_button = ButtonWithLongPress::<PD6, 32, 2000>::new(load_button_pin);
 
     let mut last_output = QuantizationResult::zero();
+    let mut force_dac_update = false;
 
     let mut dac = MCP4922::new(d10);
 
@@ -154,9 +173,52 @@ fn main() -> ! {
 
         let adc_value_a = I1F15::from_bits((cv[1] << 5) as i16);
         let adc_value_b = I1F15::from_bits((cv[2] << 5) as i16);
+
+        if let Some(ref mut routine) = calibration_mode {
+            let status = routine.step(
+                [
+                    adc_to_semitones(adc_value_a, &LinearCorrection::IDENTITY),
+                    adc_to_semitones(adc_value_b, &LinearCorrection::IDENTITY),
+                ],
+                &button_event,
+                &load_button_state,
+                current_time_ms,
+            );
+            if let Some((channel, semitones)) = routine.dac_output() {
+                let dac_channel = match channel {
+                    0 => DacChannel::ChannelA,
+                    _ => DacChannel::ChannelB,
+                };
+                dac.write(
+                    &mut spi,
+                    dac_channel,
+                    semitones_to_dac(semitones, &LinearCorrection::IDENTITY),
+                );
+            }
+            let leds = routine.render(current_time_ms);
+            match status {
+                CalibrationStatus::InProgress => {}
+                CalibrationStatus::Finished(new_calibration) => {
+                    write_calibration(&mut eeprom, &new_calibration);
+                    calibration = new_calibration;
+                    calibration_mode = None;
+                    force_dac_update = true;
+                }
+                CalibrationStatus::Cancelled => {
+                    calibration_mode = None;
+                    force_dac_update = true;
+                }
+            }
+            if !all_equal(leds, cached_led_state) {
+                update_leds(&mut spi, &leds);
+                cached_led_state = leds;
+            }
+            continue;
+        }
+
         let result = quantizer_state.step(
-            adc_to_semitones(adc_value_a),
-            adc_to_semitones(adc_value_b),
+            adc_to_semitones(adc_value_a, &calibration.inputs[0]),
+            adc_to_semitones(adc_value_b, &calibration.inputs[1]),
             trig_input_pin_a.is_high(),
             trig_input_pin_b.is_high(),
         );
@@ -174,23 +236,28 @@ fn main() -> ! {
             &mut eeprom,
         );
 
-        if result.channel_a.actual_semitones != last_output.channel_a.actual_semitones {
+        if force_dac_update
+            || result.channel_a.actual_semitones != last_output.channel_a.actual_semitones
+        {
             dac.write(
                 &mut spi,
                 DacChannel::ChannelA,
-                semitones_to_dac(result.channel_a.actual_semitones),
+                semitones_to_dac(result.channel_a.actual_semitones, &calibration.outputs[0]),
             );
             dac.end_write();
         }
-        if result.channel_b.actual_semitones != last_output.channel_b.actual_semitones {
+        if force_dac_update
+            || result.channel_b.actual_semitones != last_output.channel_b.actual_semitones
+        {
             dac.write(
                 &mut spi,
                 DacChannel::ChannelB,
-                semitones_to_dac(result.channel_b.actual_semitones),
+                semitones_to_dac(result.channel_b.actual_semitones, &calibration.outputs[1]),
             );
         }
 
         last_output = result;
+        force_dac_update = false;
 
         {
             let dp = unsafe { arduino_hal::Peripherals::steal() };
@@ -243,16 +310,18 @@ const fn concat_u12s(left: u16, right: u16) -> [u8; 3] {
     [bytes[1], bytes[2], bytes[3]]
 }
 
-fn adc_to_semitones(raw_adc_value: I1F15) -> I8F8 {
+fn adc_to_semitones(raw_adc_value: I1F15, correction: &LinearCorrection) -> I8F8 {
     // NOTE: this assumes readings can go all the way from 0 to 0x7FFF but in fact
     // the scale max is 0x7FE0. Idk if there any accuracy to be gained from
-    // including that at this level of precision
-    raw_adc_value.lerp(I8F8::ZERO, I8F8::from_bits(120 << 8))
+    // including that at this level of precision. Any error is covered by the
+    // calibration gain anyway.
+    correction.apply(raw_adc_value.lerp(I8F8::ZERO, I8F8::from_bits(120 << 8)))
 }
 
-fn semitones_to_dac(semitones: I8F8) -> u16 {
+fn semitones_to_dac(semitones: I8F8, correction: &LinearCorrection) -> u16 {
     debug_assert!(semitones >= 0);
     debug_assert!(semitones <= 120);
+    let semitones = correction.apply(semitones);
     let bits = (U16F16::from_fixed(semitones) / U16F16::from_num(120))
         .to_bits()
         .min(0xFFFF);
@@ -9,8 +9,12 @@ use crate::{
     persistence::{
         check_save_slots, erase_all_save_slots, read_config, read_scale, write_config, write_scale,
     },
-    quantizer::{PitchMode, QuantizationResult, QuantizerChannel, QuantizerState, SampleMode},
+    quantizer::{
+        ChannelConfig, GlideMode, PitchMode, QuantizationResult, QuantizerChannel, QuantizerState,
+        RangeMode, SampleMode,
+    },
     resistor_ladder_buttons::ButtonEvent,
+    scale_library::{find_library_scale, load_library_scale, rotate_mode, LIBRARY_SIZE},
 };
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -56,10 +60,38 @@ enum ScalarSubMenuStatus {
 
 enum ScalarSubMenu {
     Glide,
+    GlideShape,
     Delay,
     PreShift,
     ScaleShift,
+    ModeRotate,
+    ScaleLibrary(u8),
     PostShift,
+    RangeMin,
+    RangeMax,
+    RangeMode,
+}
+
+impl ScalarSubMenu {
+    /**
+    Some pages don't have a shift+key combo of their own. Instead, they are
+    reached by tapping shift again while a related page is open, before
+    selecting a value.
+    */
+    fn next_page(&self) -> Option<ScalarSubMenu> {
+        match self {
+            ScalarSubMenu::Glide => Some(ScalarSubMenu::GlideShape),
+            ScalarSubMenu::ScaleShift => Some(ScalarSubMenu::ModeRotate),
+            ScalarSubMenu::ModeRotate => Some(ScalarSubMenu::ScaleLibrary(0)),
+            ScalarSubMenu::ScaleLibrary(page) if ((page + 1) as usize) * 12 < LIBRARY_SIZE => {
+                Some(ScalarSubMenu::ScaleLibrary(page + 1))
+            }
+            ScalarSubMenu::PostShift => Some(ScalarSubMenu::RangeMin),
+            ScalarSubMenu::RangeMin => Some(ScalarSubMenu::RangeMax),
+            ScalarSubMenu::RangeMax => Some(ScalarSubMenu::RangeMode),
+            _ => None,
+        }
+    }
 }
 
 enum BoolOption {
@@ -203,6 +235,16 @@ impl MenuState {
         current_time_ms: u32,
         eeprom: &mut Eeprom,
     ) -> [LedColor; 12] {
+        if !self.shift_was_pressed && buttons.shift_pressed {
+            if let MenuPage::ScalarSubMenu(ScalarSubMenuStatus::ExitOnShiftRelease, ref menu) =
+                self.menu_page
+            {
+                if let Some(next_page) = menu.next_page() {
+                    self.menu_page =
+                        MenuPage::ScalarSubMenu(ScalarSubMenuStatus::AwaitingFirstInput, next_page);
+                }
+            }
+        }
         if self.shift_was_pressed && !buttons.shift_pressed {
             if let MenuPage::ScalarSubMenu(ref mut menu_status, _) = self.menu_page {
                 match menu_status {
@@ -462,6 +504,13 @@ fn apply_sub_menu_button_press_effect(
         ScalarSubMenu::Glide => {
             channel_state.config.glide_amount = button_idx;
         }
+        ScalarSubMenu::GlideShape => match button_idx {
+            0 => channel_state.config.glide_mode = GlideMode::Exponential,
+            1 => channel_state.config.glide_mode = GlideMode::LinearConstantTime,
+            2 => channel_state.config.glide_mode = GlideMode::LinearConstantRate,
+            11 => channel_state.config.legato_glide = !channel_state.config.legato_glide,
+            _ => {}
+        },
         ScalarSubMenu::Delay => {
             channel_state.config.trigger_delay_amount = button_idx;
         }
@@ -471,9 +520,37 @@ fn apply_sub_menu_button_press_effect(
         ScalarSubMenu::ScaleShift => {
             channel_state.config.scale_shift = button_idx_to_i8(button_idx);
         }
+        ScalarSubMenu::ModeRotate => {
+            rotate_mode(
+                &mut channel_state.config.notes,
+                button_idx_to_i8(button_idx),
+            );
+        }
+        ScalarSubMenu::ScaleLibrary(page) => {
+            let index = *page as usize * 12 + button_idx as usize;
+            if index < LIBRARY_SIZE {
+                channel_state.config.notes = load_library_scale(index);
+            }
+        }
         ScalarSubMenu::PostShift => {
             channel_state.config.post_shift = button_idx_to_i8(button_idx);
         }
+        ScalarSubMenu::RangeMin => {
+            let config = &mut channel_state.config;
+            config.range_min = 12 * button_idx.min(10) as i8;
+            config.range_max = config.range_max.max(config.range_min);
+        }
+        ScalarSubMenu::RangeMax => {
+            let config = &mut channel_state.config;
+            config.range_max = 12 * button_idx.min(10) as i8;
+            config.range_min = config.range_min.min(config.range_max);
+        }
+        ScalarSubMenu::RangeMode => match button_idx {
+            0 => channel_state.config.range_mode = RangeMode::Clamp,
+            1 => channel_state.config.range_mode = RangeMode::Fold,
+            2 => channel_state.config.range_mode = RangeMode::Wrap,
+            _ => {}
+        },
     }
 }
 
@@ -497,11 +574,91 @@ fn get_sub_menu_button_press_result(
 fn render_sub_menu(sub_menu: &ScalarSubMenu, state: &QuantizerChannel) -> [LedColor; 12] {
     match sub_menu {
         ScalarSubMenu::Glide => render_sub_menu_unsigned(state.config.glide_amount),
+        ScalarSubMenu::GlideShape => render_glide_shape_menu(&state.config),
         ScalarSubMenu::Delay => render_sub_menu_unsigned(state.config.trigger_delay_amount),
         ScalarSubMenu::PreShift => render_sub_menu_signed(state.config.pre_shift),
         ScalarSubMenu::ScaleShift => render_sub_menu_signed(state.config.scale_shift),
+        ScalarSubMenu::ModeRotate => render_mode_rotate_menu(&state.config),
+        ScalarSubMenu::ScaleLibrary(page) => render_scale_library_menu(&state.config, *page),
         ScalarSubMenu::PostShift => render_sub_menu_signed(state.config.post_shift),
+        ScalarSubMenu::RangeMin => render_range_menu(&state.config, false),
+        ScalarSubMenu::RangeMax => render_range_menu(&state.config, true),
+        ScalarSubMenu::RangeMode => render_sub_menu_choice(match state.config.range_mode {
+            RangeMode::Clamp => 0,
+            RangeMode::Fold => 1,
+            RangeMode::Wrap => 2,
+        }),
+    }
+}
+
+/**
+Shows the notes in the scale, with the root highlighted
+*/
+fn render_mode_rotate_menu(config: &ChannelConfig) -> [LedColor; 12] {
+    let mut leds = [LedColor::OFF; 12];
+    for i in 0..12 {
+        if config.notes[i] {
+            leds[i] = LedColor::GREEN;
+        }
     }
+    if config.notes[0] {
+        leds[0] = LedColor::AMBER;
+    }
+    leds
+}
+
+/**
+Shows which keys on this page have a scale assigned to them, with the current
+scale highlighted if it came from the library
+*/
+fn render_scale_library_menu(config: &ChannelConfig, page: u8) -> [LedColor; 12] {
+    let mut leds = [LedColor::OFF; 12];
+    let first_index = page as usize * 12;
+    for i in 0..12 {
+        if first_index + i < LIBRARY_SIZE {
+            leds[i] = LedColor::AMBER;
+        }
+    }
+    if let Some(current) = find_library_scale(&config.notes) {
+        if current >= first_index && current < first_index + 12 {
+            leds[current - first_index] = LedColor::GREEN;
+        }
+    }
+    leds
+}
+
+fn render_sub_menu_choice(selected: usize) -> [LedColor; 12] {
+    let mut leds = [LedColor::OFF; 12];
+    leds[selected] = LedColor::GREEN;
+    leds
+}
+
+fn render_glide_shape_menu(config: &ChannelConfig) -> [LedColor; 12] {
+    let mut leds = render_sub_menu_choice(match config.glide_mode {
+        GlideMode::Exponential => 0,
+        GlideMode::LinearConstantTime => 1,
+        GlideMode::LinearConstantRate => 2,
+    });
+    leds[11] = match config.legato_glide {
+        true => LedColor::GREEN,
+        false => LedColor::RED,
+    };
+    leds
+}
+
+/**
+Shows the range of octaves that notes are limited to, with the end that is
+being edited highlighted
+*/
+fn render_range_menu(config: &ChannelConfig, editing_max: bool) -> [LedColor; 12] {
+    let mut leds = [LedColor::OFF; 12];
+    let min_octave = (config.range_min / 12) as usize;
+    let max_octave = (config.range_max / 12) as usize;
+    for i in min_octave..=max_octave {
+        leds[i] = LedColor::GREEN;
+    }
+    leds[if editing_max { max_octave } else { min_octave }] = LedColor::AMBER;
+    leds
 }
 
 fn render_sub_menu_unsigned(n: u8) -> [LedColor; 12] {
@@ -2,12 +2,32 @@ use arduino_hal::Eeprom;
 
 const SENTINEL_VALUE: u8 = 0b10101010;
 
-const STORAGE_OFFSET: u8 = 1;
+const STORAGE_OFFSET: u16 = 1;
+
+const SCALE_SLOT_SIZE: u16 = 3;
+const CONFIG_STORAGE_OFFSET: u16 = STORAGE_OFFSET + 12 * SCALE_SLOT_SIZE;
+const CHANNEL_CONFIG_SIZE: usize = 12;
+const CONFIG_SIZE: usize = 1 + 2 * CHANNEL_CONFIG_SIZE;
+const CONFIG_SLOT_SIZE: u16 = 1 + CONFIG_SIZE as u16;
+
+// Calibration is kept well clear of the save slots so that it survives
+// "erase all" and any future changes to the save slot layout
+const CALIBRATION_OFFSET: u16 = 512;
+
+// Which layout the config save slots are stored in. The original firmware
+// never wrote this byte, so on a module updated from it, it's still erased
+const CONFIG_VERSION_ADDRESS: u16 = CALIBRATION_OFFSET - 1;
+const CONFIG_VERSION: u8 = 2;
+const ERASED_BYTE: u8 = 0xFF;
 
 use crate::{
     bitvec::BitVec,
+    calibration::Calibration,
     menu::Channel,
-    quantizer::{ChannelConfig, PitchMode, QuantizerChannel, QuantizerState, SampleMode},
+    quantizer::{
+        ChannelConfig, GlideMode, PitchMode, QuantizerChannel, QuantizerState, RangeMode,
+        SampleMode,
+    },
 };
 
 fn parse_notes(bytes: &[u8]) -> [bool; 12] {
@@ -35,30 +55,93 @@ fn encode_notes(notes: &[bool; 12]) -> [u8; 2] {
 pub fn check_save_slots(eeprom: &mut Eeprom) -> (BitVec<12>, BitVec<12>) {
     let mut scales = BitVec::<12>::new();
     for i in 0..12 {
-        let address = STORAGE_OFFSET + i * 3;
-        scales.set(i, eeprom.read_byte(address as u16) == SENTINEL_VALUE);
+        let address = STORAGE_OFFSET + i as u16 * SCALE_SLOT_SIZE;
+        scales.set(i, eeprom.read_byte(address) == SENTINEL_VALUE);
     }
-    let offset = STORAGE_OFFSET + 12 * 3;
     let mut configs = BitVec::<12>::new();
     for i in 0..12 {
-        let address = offset + i * 18;
-        configs.set(i, eeprom.read_byte(address as u16) == SENTINEL_VALUE);
+        let address = CONFIG_STORAGE_OFFSET + i as u16 * CONFIG_SLOT_SIZE;
+        configs.set(i, eeprom.read_byte(address) == SENTINEL_VALUE);
     }
     (scales, configs)
 }
 
 pub fn erase_all_save_slots(eeprom: &mut Eeprom) {
     for i in 0..12 {
-        let address = STORAGE_OFFSET + i * 3;
-        eeprom.erase_byte(address as u16);
+        let address = STORAGE_OFFSET + i * SCALE_SLOT_SIZE;
+        eeprom.erase_byte(address);
     }
-    let offset = STORAGE_OFFSET + 12 * 3;
     for i in 0..12 {
-        let address = offset + i * 18;
-        eeprom.erase_byte(address as u16);
+        let address = CONFIG_STORAGE_OFFSET + i * CONFIG_SLOT_SIZE;
+        eeprom.erase_byte(address);
+    }
+}
+
+/**
+The size of each channel's config in an older layout, if it can be migrated
+*/
+fn old_channel_config_size(version: u8) -> Option<usize> {
+    match version {
+        // The original firmware, before the layout had a version
+        ERASED_BYTE => Some(8),
+        // With the glide mode, before the output range
+        1 => Some(9),
+        _ => None,
     }
 }
 
+/**
+Rewrites the config save slots in the current layout if they were saved by an
+older firmware. Fields have only ever been added to the end of each channel's
+config, and every field decodes an all-zero byte as its default, so each
+channel's old config is padded with zeroes. Slots saved by an unknown layout
+can't be read, so they're erased.
+*/
+pub fn migrate_config_slots(eeprom: &mut Eeprom) {
+    let version = eeprom.read_byte(CONFIG_VERSION_ADDRESS);
+    if version == CONFIG_VERSION {
+        return;
+    }
+    match old_channel_config_size(version) {
+        // The slots grow, so each one is moved before the slot below it, which
+        // it overlaps, is written
+        Some(old_size) => {
+            for slot in (0..12).rev() {
+                migrate_config_slot(eeprom, slot, old_size);
+            }
+        }
+        None => {
+            for slot in 0..12 {
+                eeprom.erase_byte(CONFIG_STORAGE_OFFSET + slot * CONFIG_SLOT_SIZE);
+            }
+        }
+    }
+    eeprom.write_byte(CONFIG_VERSION_ADDRESS, CONFIG_VERSION);
+}
+
+fn migrate_config_slot(eeprom: &mut Eeprom, slot: u8, old_channel_size: usize) {
+    let old_size = 1 + 2 * old_channel_size;
+    let old_address = CONFIG_STORAGE_OFFSET + slot as u16 * (1 + old_size as u16);
+    let address = CONFIG_STORAGE_OFFSET + slot as u16 * CONFIG_SLOT_SIZE;
+    if eeprom.read_byte(old_address) != SENTINEL_VALUE {
+        // The new sentinel may land on old data that happens to match it
+        eeprom.erase_byte(address);
+        return;
+    }
+    let mut old = [0u8; CONFIG_SIZE];
+    eeprom.read(old_address + 1, &mut old[..old_size]).unwrap();
+
+    let mut buff = [0u8; CONFIG_SIZE];
+    buff[0] = old[0];
+    for channel in 0..2 {
+        let from = 1 + channel * old_channel_size;
+        let to = 1 + channel * CHANNEL_CONFIG_SIZE;
+        buff[to..to + old_channel_size].copy_from_slice(&old[from..from + old_channel_size]);
+    }
+    eeprom.write_byte(address, SENTINEL_VALUE);
+    eeprom.write(address + 1, &buff).unwrap();
+}
+
 pub fn write_scale(
     eeprom: &mut Eeprom,
     slot: u8,
@@ -66,10 +149,10 @@ pub fn write_scale(
     channel: &Channel,
 ) {
     let notes = &quantizer_state.channels[channel.index()].config.notes;
-    let address = STORAGE_OFFSET + slot * 3;
+    let address = STORAGE_OFFSET + slot as u16 * SCALE_SLOT_SIZE;
     let buff = encode_notes(notes);
-    eeprom.write_byte(address as u16, SENTINEL_VALUE);
-    eeprom.write((address + 1) as u16, &buff).unwrap();
+    eeprom.write_byte(address, SENTINEL_VALUE);
+    eeprom.write(address + 1, &buff).unwrap();
 }
 
 pub fn read_scale(
@@ -78,9 +161,9 @@ pub fn read_scale(
     quantizer_state: &mut QuantizerState,
     channel: &Channel,
 ) {
-    let address = STORAGE_OFFSET + slot * 3;
-    let mut bytes = [0u8; 3];
-    eeprom.read(address as u16, &mut bytes).unwrap();
+    let address = STORAGE_OFFSET + slot as u16 * SCALE_SLOT_SIZE;
+    let mut bytes = [0u8; SCALE_SLOT_SIZE as usize];
+    eeprom.read(address, &mut bytes).unwrap();
     if bytes[0] != SENTINEL_VALUE {
         return;
     }
@@ -88,27 +171,41 @@ pub fn read_scale(
 }
 
 pub fn write_config(eeprom: &mut Eeprom, slot: u8, quantizer_state: &QuantizerState) {
-    let offset = STORAGE_OFFSET + 12 * 3;
-    let address = offset + slot * 18;
+    let address = CONFIG_STORAGE_OFFSET + slot as u16 * CONFIG_SLOT_SIZE;
     let buff = quantizer_state.to_bytes();
-    eeprom.write_byte(address as u16, SENTINEL_VALUE);
-    eeprom.write((address + 1) as u16, &buff).unwrap();
+    eeprom.write_byte(address, SENTINEL_VALUE);
+    eeprom.write(address + 1, &buff).unwrap();
 }
 
 pub fn read_config(eeprom: &mut Eeprom, slot: u8, quantizer_state: &mut QuantizerState) {
-    let offset = STORAGE_OFFSET + 12 * 3;
-    let address = offset + slot * 18;
-    let sentinel = eeprom.read_byte(address as u16);
+    let address = CONFIG_STORAGE_OFFSET + slot as u16 * CONFIG_SLOT_SIZE;
+    let sentinel = eeprom.read_byte(address);
     if sentinel != SENTINEL_VALUE {
         return;
     }
-    let mut buff = [0u8; 17];
-    eeprom.read((address + 1) as u16, &mut buff).unwrap();
+    let mut buff = [0u8; CONFIG_SIZE];
+    eeprom.read(address + 1, &mut buff).unwrap();
     *quantizer_state = QuantizerState::from_bytes(&buff);
 }
 
+pub fn read_calibration(eeprom: &mut Eeprom) -> Calibration {
+    if eeprom.read_byte(CALIBRATION_OFFSET) != SENTINEL_VALUE {
+        return Calibration::IDENTITY;
+    }
+    let mut buff = [0u8; 16];
+    eeprom.read(CALIBRATION_OFFSET + 1, &mut buff).unwrap();
+    Calibration::from_bytes(&buff)
+}
+
+pub fn write_calibration(eeprom: &mut Eeprom, calibration: &Calibration) {
+    eeprom
+        .write(CALIBRATION_OFFSET + 1, &calibration.to_bytes())
+        .unwrap();
+    eeprom.write_byte(CALIBRATION_OFFSET, SENTINEL_VALUE);
+}
+
 impl ChannelConfig {
-    fn from_bytes(bytes: &[u8; 8]) -> Self {
+    fn from_bytes(bytes: &[u8; CHANNEL_CONFIG_SIZE]) -> Self {
         ChannelConfig {
             notes: parse_notes(&bytes[0..2]),
             sample_mode: if bytes[2] == 0 {
@@ -117,15 +214,28 @@ impl ChannelConfig {
                 SampleMode::SampleAndHold
             },
             glide_amount: bytes[3],
+            glide_mode: match bytes[8] & 0b11 {
+                1 => GlideMode::LinearConstantTime,
+                2 => GlideMode::LinearConstantRate,
+                _ => GlideMode::Exponential,
+            },
+            legato_glide: bytes[8] & 0b1000_0000 != 0,
             trigger_delay_amount: bytes[4],
             pre_shift: unsafe { core::mem::transmute(bytes[5]) },
             scale_shift: unsafe { core::mem::transmute(bytes[6]) },
             post_shift: unsafe { core::mem::transmute(bytes[7]) },
+            range_min: bytes[9].min(120) as i8,
+            range_max: 120 - bytes[10].min(120) as i8,
+            range_mode: match bytes[11] {
+                1 => RangeMode::Fold,
+                2 => RangeMode::Wrap,
+                _ => RangeMode::Clamp,
+            },
         }
     }
 
-    fn to_bytes(&self) -> [u8; 8] {
-        let mut bytes = [0u8; 8];
+    fn to_bytes(&self) -> [u8; CHANNEL_CONFIG_SIZE] {
+        let mut bytes = [0u8; CHANNEL_CONFIG_SIZE];
         bytes[0..2].copy_from_slice(&encode_notes(&self.notes));
         bytes[2] = match self.sample_mode {
             SampleMode::TrackAndHold => 0,
@@ -136,12 +246,28 @@ impl ChannelConfig {
         bytes[5] = unsafe { core::mem::transmute(self.pre_shift) };
         bytes[6] = unsafe { core::mem::transmute(self.scale_shift) };
         bytes[7] = unsafe { core::mem::transmute(self.post_shift) };
+        bytes[8] = match self.glide_mode {
+            GlideMode::Exponential => 0,
+            GlideMode::LinearConstantTime => 1,
+            GlideMode::LinearConstantRate => 2,
+        };
+        if self.legato_glide {
+            bytes[8] |= 0b1000_0000;
+        }
+        bytes[9] = self.range_min as u8;
+        // Stored inverted so that all-zero bytes decode to the default full range
+        bytes[10] = (120 - self.range_max) as u8;
+        bytes[11] = match self.range_mode {
+            RangeMode::Clamp => 0,
+            RangeMode::Fold => 1,
+            RangeMode::Wrap => 2,
+        };
         bytes
     }
 }
 
 impl QuantizerState {
-    fn from_bytes(bytes: &[u8; 17]) -> Self {
+    fn from_bytes(bytes: &[u8; CONFIG_SIZE]) -> Self {
         Self {
             channels_linked: bytes[0] & 1 != 0,
             channel_b_mode: if bytes[0] & 2 == 0 {
@@ -151,16 +277,16 @@ impl QuantizerState {
             },
             channels: [
                 QuantizerChannel::from_config(ChannelConfig::from_bytes(
-                    bytes[1..9].try_into().unwrap(),
+                    bytes[1..1 + CHANNEL_CONFIG_SIZE].try_into().unwrap(),
                 )),
                 QuantizerChannel::from_config(ChannelConfig::from_bytes(
-                    bytes[9..17].try_into().unwrap(),
+                    bytes[1 + CHANNEL_CONFIG_SIZE..].try_into().unwrap(),
                 )),
             ],
         }
     }
 
-    fn to_bytes(&self) -> [u8; 17] {
+    fn to_bytes(&self) -> [u8; CONFIG_SIZE] {
         let mut flags = 0u8;
         if self.channels_linked {
             flags |= 1;
@@ -169,10 +295,10 @@ impl QuantizerState {
             flags |= 2;
         }
 
-        let mut bytes = [0u8; 17];
+        let mut bytes = [0u8; CONFIG_SIZE];
         bytes[0] = flags;
-        bytes[1..9].clone_from_slice(&self.channels[0].config.to_bytes());
-        bytes[9..17].clone_from_slice(&self.channels[1].config.to_bytes());
+        bytes[1..1 + CHANNEL_CONFIG_SIZE].clone_from_slice(&self.channels[0].config.to_bytes());
+        bytes[1 + CHANNEL_CONFIG_SIZE..].clone_from_slice(&self.channels[1].config.to_bytes());
         bytes
     }
 }
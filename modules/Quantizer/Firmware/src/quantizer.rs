@@ -41,18 +41,34 @@ pub struct ChannelConfig {
     pub notes: [bool; 12],
     pub sample_mode: SampleMode,
     pub glide_amount: u8,
+    pub glide_mode: GlideMode,
+    pub legato_glide: bool,
     pub trigger_delay_amount: u8,
     pub pre_shift: i8,
     pub scale_shift: i8,
     pub post_shift: i8,
+    pub range_min: i8,
+    pub range_max: i8,
+    pub range_mode: RangeMode,
 }
 
+/**
+Longest the trigger can be low between two notes for the second one to still
+count as legato. Overlapping gates can't be sent down one cable, so notes played
+legato arrive as a gate that only drops for a moment before the next one.
+*/
+const LEGATO_MAX_GAP_MS: u8 = 5;
+
 struct ChannelState {
     last_output: Option<InternalChannelOutput>,
     last_trigger_input: bool,
+    trigger_low_ms: u8,
+    legato: bool,
     hysteresis_state: HysteresisState,
     output_trigger_countdown: u8,
     input_trigger_timer: u8,
+    glide_step: I8F24,
+    glide_active: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -67,7 +83,27 @@ pub enum SampleMode {
     SampleAndHold,
 }
 
-struct HysteresisState {
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum GlideMode {
+    /** Moves a fixed fraction of the remaining distance every step (RC slew) */
+    Exponential,
+    /** Reaches the target in the same amount of time no matter how far it is */
+    LinearConstantTime,
+    /** Moves at a fixed number of semitones per second */
+    LinearConstantRate,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum RangeMode {
+    /** Notes outside the range are replaced by the closest note inside it */
+    Clamp,
+    /** Notes bounce back off the edges of the range, counting in scale steps */
+    Fold,
+    /** Notes are moved by whole octaves until they are inside the range */
+    Wrap,
+}
+
+pub struct HysteresisState {
     last_output: i8,
 }
 
@@ -77,10 +113,15 @@ impl QuantizerChannel {
             notes: [false; 12],
             sample_mode: SampleMode::TrackAndHold,
             glide_amount: 0,
+            glide_mode: GlideMode::Exponential,
+            legato_glide: false,
             trigger_delay_amount: 0,
             pre_shift: 0,
             scale_shift: 0,
             post_shift: 0,
+            range_min: 0,
+            range_max: 120,
+            range_mode: RangeMode::Clamp,
         };
         Self::from_config(default_config)
     }
@@ -91,9 +132,13 @@ impl QuantizerChannel {
             ephemeral: ChannelState {
                 last_output: None,
                 last_trigger_input: false,
-                hysteresis_state: HysteresisState { last_output: 0 },
+                trigger_low_ms: u8::MAX,
+                legato: false,
+                hysteresis_state: HysteresisState::new(),
                 output_trigger_countdown: 0,
                 input_trigger_timer: 0,
+                glide_step: I8F24::ZERO,
+                glide_active: false,
             },
         }
     }
@@ -109,12 +154,21 @@ impl QuantizerChannel {
         self.ephemeral.last_trigger_input = sample_trigger;
 
         if received_trigger {
+            // Decided when the trigger arrives rather than when the delayed
+            // update happens, from how long the trigger was low before it
+            self.ephemeral.legato = self.ephemeral.trigger_low_ms <= LEGATO_MAX_GAP_MS;
             self.ephemeral.input_trigger_timer = 0;
         } else {
             self.ephemeral.input_trigger_timer =
                 self.ephemeral.input_trigger_timer.saturating_add(1);
         }
 
+        if sample_trigger {
+            self.ephemeral.trigger_low_ms = 0;
+        } else {
+            self.ephemeral.trigger_low_ms = self.ephemeral.trigger_low_ms.saturating_add(1);
+        }
+
         let should_update = self.ephemeral.input_trigger_timer == self.config.trigger_delay_amount;
 
         let (nominal_semitones, glide_target) = if should_update {
@@ -133,12 +187,15 @@ impl QuantizerChannel {
             .map(|x| x.nominal_semitones != nominal_semitones)
             .unwrap_or(false);
 
-        let last_actual_output = self
+        let (last_actual_output, last_glide_target) = self
             .ephemeral
             .last_output
             .as_ref()
-            .map(|x| x.glide_current)
-            .unwrap_or(I8F24::ZERO);
+            .map(|x| (x.glide_current, x.glide_target))
+            .unwrap_or((I8F24::ZERO, I8F24::ZERO));
+        if glide_target != last_glide_target {
+            self._start_glide(last_actual_output, glide_target);
+        }
         let actual_output = self._calculate_glide(last_actual_output, glide_target);
         self.ephemeral.last_output = Some(InternalChannelOutput {
             nominal_semitones,
@@ -166,13 +223,36 @@ impl QuantizerChannel {
         }
     }
 
+    fn _start_glide(&mut self, current: I8F24, target: I8F24) {
+        self.ephemeral.glide_active = !self.config.legato_glide || self.ephemeral.legato;
+
+        // Linear glides take 2^(n+2) ms to finish, or in constant-rate mode that
+        // long to move by one octave
+        let duration_ms = 1i32 << (self.config.glide_amount + 2);
+        self.ephemeral.glide_step = match self.config.glide_mode {
+            GlideMode::Exponential => I8F24::ZERO,
+            GlideMode::LinearConstantTime => (target - current).abs() / duration_ms,
+            GlideMode::LinearConstantRate => I8F24::from_num(12) / duration_ms,
+        }
+        .max(I8F24::from_bits(1));
+    }
+
     fn _calculate_glide(&self, current: I8F24, target: I8F24) -> I8F24 {
         debug_assert!(target >= 0);
         debug_assert!(target <= 120);
         debug_assert!(current >= 0);
         debug_assert!(current <= 120);
-        if current == target {
-            return current;
+        if current == target || !self.ephemeral.glide_active || self.config.glide_amount == 0 {
+            return target;
+        }
+
+        if self.config.glide_mode != GlideMode::Exponential {
+            let step = self.ephemeral.glide_step;
+            return if target > current {
+                (current + step).min(target)
+            } else {
+                (current - step).max(target)
+            };
         }
 
         let alpha = I8F24::ONE >> self.config.glide_amount;
@@ -204,8 +284,17 @@ impl QuantizerChannel {
             .hysteresis_state
             .quantize(pre_shifted, &self.config.notes);
         let scale_shifted = step_in_scale(&self.config.notes, quantized, self.config.scale_shift);
-        let post_shifted = (scale_shifted + self.config.post_shift).clamp(0, 120);
-        (scale_shifted, I8F8::from_num(post_shifted))
+        // The range applies to the final output, so it has to be moved to account
+        // for the post-shift, which happens after this
+        let limited = limit_to_range(
+            &self.config.notes,
+            scale_shifted,
+            self.config.range_min - self.config.post_shift,
+            self.config.range_max - self.config.post_shift,
+            self.config.range_mode,
+        );
+        let post_shifted = (limited + self.config.post_shift).clamp(0, 120);
+        (limited, I8F8::from_num(post_shifted))
     }
 }
 
@@ -220,7 +309,11 @@ impl QuantizerState {
 }
 
 impl HysteresisState {
-    fn quantize(&mut self, input_semitones: I8F8, notes: &[bool; 12]) -> i8 {
+    pub const fn new() -> Self {
+        Self { last_output: 0 }
+    }
+
+    pub fn quantize(&mut self, input_semitones: I8F8, notes: &[bool; 12]) -> i8 {
         if notes.iter().all(|x| !x) {
             return 0;
         }
@@ -235,7 +328,7 @@ impl HysteresisState {
 
         let floor = input_semitones.int();
         let should_round_up = input_semitones.frac() >= I8F8::ONE / 2;
-        let mut upper_bound = (floor + I8F8::ONE).to_num::<i8>();
+        let mut upper_bound = (floor + I8F8::ONE).to_num::<i8>().min(120);
         let mut lower_bound = floor.to_num::<i8>();
         loop {
             let mut bounds = [lower_bound, upper_bound];
@@ -255,7 +348,7 @@ impl HysteresisState {
         }
     }
 
-    fn calculate_hysteresis_thresholds(&self, notes: &[bool; 12]) -> Option<(I8F8, I8F8)> {
+    pub fn calculate_hysteresis_thresholds(&self, notes: &[bool; 12]) -> Option<(I8F8, I8F8)> {
         if !notes[(self.last_output % 12) as usize] {
             return None;
         }
@@ -285,7 +378,7 @@ impl HysteresisState {
     }
 }
 
-fn step_in_scale(notes: &[bool; 12], starting_note: i8, num_steps: i8) -> i8 {
+pub fn step_in_scale(notes: &[bool; 12], starting_note: i8, num_steps: i8) -> i8 {
     if notes.iter().all(|x| !x) {
         return starting_note;
     }
@@ -297,14 +390,95 @@ fn step_in_scale(notes: &[bool; 12], starting_note: i8, num_steps: i8) -> i8 {
     };
 
     let mut note = starting_note;
-    for _ in 0..num_steps {
-        note = get_next_selected_note(notes, note, direction);
+    for _ in 0..num_steps.unsigned_abs() {
+        let next_note = get_next_selected_note(notes, note, direction);
+        if !notes[(next_note % 12) as usize] {
+            // Ran into the end of the range without finding another note
+            break;
+        }
+        note = next_note;
     }
 
     note
 }
 
-fn get_next_selected_note(notes: &[bool; 12], starting_note: i8, direction: Direction) -> i8 {
+pub fn limit_to_range(notes: &[bool; 12], note: i8, min: i8, max: i8, mode: RangeMode) -> i8 {
+    let min = min.clamp(0, 120);
+    let max = max.clamp(min, 120);
+    if note >= min && note <= max {
+        return note;
+    }
+
+    if notes.iter().all(|x| !x) {
+        return note.clamp(min, max);
+    }
+
+    let lowest = if notes[(min % 12) as usize] {
+        min
+    } else {
+        get_next_selected_note(notes, min, Direction::Positive)
+    };
+    let highest = if notes[(max % 12) as usize] {
+        max
+    } else {
+        get_next_selected_note(notes, max, Direction::Negative)
+    };
+    if lowest > highest {
+        // No notes from the scale fall inside the range
+        return note.clamp(min, max);
+    }
+    let clamped = if note < min { lowest } else { highest };
+
+    match mode {
+        RangeMode::Clamp => clamped,
+        RangeMode::Wrap => {
+            let mut wrapped = note;
+            while wrapped > max {
+                wrapped -= 12;
+            }
+            while wrapped < min {
+                wrapped += 12;
+            }
+            // The range might be less than an octave wide
+            if wrapped > max {
+                clamped
+            } else {
+                wrapped
+            }
+        }
+        RangeMode::Fold => {
+            let span = count_scale_steps(notes, lowest, highest);
+            if span == 0 {
+                return lowest;
+            }
+            let degree = if note < lowest {
+                -count_scale_steps(notes, note, lowest)
+            } else {
+                count_scale_steps(notes, lowest, note)
+            };
+            let folded = degree.rem_euclid(2 * span);
+            let folded = if folded > span {
+                2 * span - folded
+            } else {
+                folded
+            };
+            step_in_scale(notes, lowest, folded as i8)
+        }
+    }
+}
+
+fn count_scale_steps(notes: &[bool; 12], from: i8, to: i8) -> i16 {
+    debug_assert!(from <= to);
+    let mut note = from;
+    let mut steps = 0;
+    while note < to {
+        note = get_next_selected_note(notes, note, Direction::Positive);
+        steps += 1;
+    }
+    steps
+}
+
+pub fn get_next_selected_note(notes: &[bool; 12], starting_note: i8, direction: Direction) -> i8 {
     let mut note = starting_note;
 
     loop {
@@ -330,7 +504,7 @@ fn get_next_selected_note(notes: &[bool; 12], starting_note: i8, direction: Dire
 }
 
 #[derive(Clone, Copy)]
-enum Direction {
+pub enum Direction {
     Positive,
     Negative,
 }
@@ -0,0 +1,88 @@
+use avr_progmem::progmem;
+
+pub const LIBRARY_SIZE: usize = 24;
+
+/**
+Builds a 12-bit mask from a list of semitones above the root, in the same bit
+order used to store scales in EEPROM
+*/
+const fn scale_mask<const N: usize>(semitones: [u8; N]) -> u16 {
+    let mut mask = 0u16;
+    let mut i = 0;
+    while i < N {
+        mask |= 1 << semitones[i];
+        i += 1;
+    }
+    mask
+}
+
+progmem! {
+    static progmem SCALE_LIBRARY: [u16; LIBRARY_SIZE] = [
+        // Page 1: diatonic modes and common minor/pentatonic scales
+        scale_mask([0, 2, 4, 5, 7, 9, 11]),      // Major (Ionian)
+        scale_mask([0, 2, 3, 5, 7, 9, 10]),      // Dorian
+        scale_mask([0, 1, 3, 5, 7, 8, 10]),      // Phrygian
+        scale_mask([0, 2, 4, 6, 7, 9, 11]),      // Lydian
+        scale_mask([0, 2, 4, 5, 7, 9, 10]),      // Mixolydian
+        scale_mask([0, 2, 3, 5, 7, 8, 10]),      // Natural minor (Aeolian)
+        scale_mask([0, 1, 3, 5, 6, 8, 10]),      // Locrian
+        scale_mask([0, 2, 3, 5, 7, 8, 11]),      // Harmonic minor
+        scale_mask([0, 2, 3, 5, 7, 9, 11]),      // Melodic minor
+        scale_mask([0, 2, 4, 7, 9]),             // Major pentatonic
+        scale_mask([0, 3, 5, 7, 10]),            // Minor pentatonic
+        scale_mask([0, 3, 5, 6, 7, 10]),         // Blues
+        // Page 2: symmetric and more exotic scales
+        scale_mask([0, 2, 4, 6, 8, 10]),         // Whole tone
+        scale_mask([0, 2, 3, 5, 6, 8, 9, 11]),   // Diminished (whole-half)
+        scale_mask([0, 1, 3, 4, 6, 7, 9, 10]),   // Diminished (half-whole)
+        scale_mask([0, 3, 4, 7, 8, 11]),         // Augmented
+        scale_mask([0, 2, 4, 5, 7, 8, 11]),      // Harmonic major
+        scale_mask([0, 1, 4, 5, 7, 8, 10]),      // Phrygian dominant
+        scale_mask([0, 2, 3, 6, 7, 8, 11]),      // Hungarian minor
+        scale_mask([0, 1, 4, 5, 7, 8, 11]),      // Double harmonic
+        scale_mask([0, 2, 4, 6, 7, 9, 10]),      // Lydian dominant
+        scale_mask([0, 1, 3, 4, 6, 8, 10]),      // Altered
+        scale_mask([0, 2, 3, 7, 8]),             // Hirajoshi
+        scale_mask([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]), // Chromatic
+    ];
+}
+
+fn mask_to_notes(mask: u16) -> [bool; 12] {
+    let mut notes = [false; 12];
+    for i in 0..12 {
+        notes[i] = (mask >> i) & 1 != 0;
+    }
+    notes
+}
+
+/**
+Loads the given scale from the library, rooted on C
+*/
+pub fn load_library_scale(index: usize) -> [bool; 12] {
+    debug_assert!(index < LIBRARY_SIZE);
+    mask_to_notes(SCALE_LIBRARY.load_at(index))
+}
+
+/**
+Finds which library scale, if any, is exactly the same as the given notes
+*/
+pub fn find_library_scale(notes: &[bool; 12]) -> Option<usize> {
+    (0..LIBRARY_SIZE).find(|i| load_library_scale(*i) == *notes)
+}
+
+/**
+Cycles through the modes of the scale while keeping C as the root. For
+example, rotating C major up by one step gives C dorian. If C isn't in the
+scale, the first note above (or below) it is treated as the next degree.
+*/
+pub fn rotate_mode(notes: &mut [bool; 12], steps: i8) {
+    for _ in 0..steps.unsigned_abs() {
+        if steps > 0 {
+            if let Some(next_degree) = (1..12).find(|i| notes[*i]) {
+                notes.rotate_left(next_degree);
+            }
+        } else if let Some(previous_degree) = (1..12).rev().find(|i| notes[*i]) {
+            notes.rotate_right(12 - previous_degree);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Builds the hardware-independent parts of the Quantizer firmware for the host
+//! machine so they can be tested with `cargo test`. The firmware crate itself
+//! can only be built for AVR.
+
+// The firmware uses `const fn new()` everywhere so state can live in statics
+#![allow(clippy::new_without_default)]
+
+#[path = "../../../Firmware/src/calibration.rs"]
+pub mod calibration;
+
+#[path = "../../../Firmware/src/quantizer.rs"]
+pub mod quantizer;
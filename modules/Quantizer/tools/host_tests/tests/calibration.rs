@@ -0,0 +1,119 @@
+use fixed::types::{I16F16, I8F8};
+use host_tests::calibration::{Calibration, LinearCorrection, CALIBRATION_REFERENCE_SEMITONES};
+
+const TOLERANCE: f64 = 0.02;
+
+fn expected() -> [I8F8; 2] {
+    CALIBRATION_REFERENCE_SEMITONES.map(I8F8::from_num)
+}
+
+/// Simulates a channel whose voltage is off by the given gain and offset
+fn distort(semitones: f64, gain: f64, offset: f64) -> f64 {
+    semitones * gain + offset
+}
+
+fn measure(gain: f64, offset: f64) -> [I16F16; 2] {
+    CALIBRATION_REFERENCE_SEMITONES.map(|x| I16F16::from_num(distort(x as f64, gain, offset)))
+}
+
+#[test]
+fn identity_does_not_change_values() {
+    for bits in 0..=(120 << 8) {
+        let x = I8F8::from_bits(bits);
+        assert_eq!(LinearCorrection::IDENTITY.apply(x), x);
+    }
+}
+
+#[test]
+fn perfect_measurements_give_identity() {
+    let correction = LinearCorrection::from_measurements(measure(1.0, 0.0), expected()).unwrap();
+    assert_eq!(correction, LinearCorrection::IDENTITY);
+}
+
+#[test]
+fn input_correction_undoes_error() {
+    for gain in [0.9, 0.97, 1.0, 1.02, 1.1] {
+        for offset in [-3.0, -0.5, 0.0, 0.25, 3.0] {
+            let correction =
+                LinearCorrection::from_measurements(measure(gain, offset), expected()).unwrap();
+            for semitones in (0..=100).step_by(5) {
+                let reading = distort(semitones as f64, gain, offset);
+                if !(0.0..=120.0).contains(&reading) {
+                    continue;
+                }
+                let corrected = correction.apply(I8F8::from_num(reading)).to_num::<f64>();
+                assert!(
+                    (corrected - semitones as f64).abs() < TOLERANCE,
+                    "gain {gain}, offset {offset}: {semitones} read as {corrected}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn output_correction_undoes_error() {
+    // The output is measured through an already-corrected input, so the
+    // measurements are just the distorted reference values
+    let (gain, offset) = (1.04, -1.5);
+    let correction =
+        LinearCorrection::from_measurements(measure(gain, offset), expected()).unwrap();
+    for semitones in (0..=110).step_by(5) {
+        let sent = correction.apply(I8F8::from_num(semitones)).to_num::<f64>();
+        let actual = distort(sent, gain, offset);
+        assert!(
+            (actual - semitones as f64).abs() < TOLERANCE,
+            "{semitones} came out as {actual}"
+        );
+    }
+}
+
+#[test]
+fn implausible_measurements_are_rejected() {
+    let swapped = {
+        let [low, high] = measure(1.0, 0.0);
+        [high, low]
+    };
+    assert_eq!(
+        LinearCorrection::from_measurements(swapped, expected()),
+        None
+    );
+    assert_eq!(
+        LinearCorrection::from_measurements(measure(1.0, 0.0).map(|_| I16F16::ZERO), expected()),
+        None
+    );
+    assert_eq!(
+        LinearCorrection::from_measurements(measure(1.5, 0.0), expected()),
+        None
+    );
+    assert_eq!(
+        LinearCorrection::from_measurements(measure(1.0, 12.0), expected()),
+        None
+    );
+}
+
+#[test]
+fn output_is_clamped_to_hardware_range() {
+    let correction = LinearCorrection::from_measurements(measure(0.9, 2.0), expected()).unwrap();
+    assert_eq!(correction.apply(I8F8::from_num(120)), I8F8::from_num(120));
+    let correction = LinearCorrection::from_measurements(measure(1.1, 2.0), expected()).unwrap();
+    assert_eq!(correction.apply(I8F8::ZERO), I8F8::ZERO);
+}
+
+#[test]
+fn calibration_survives_serialization() {
+    let calibration = Calibration {
+        inputs: [
+            LinearCorrection::from_measurements(measure(1.03, 0.5), expected()).unwrap(),
+            LinearCorrection::from_measurements(measure(0.95, -2.0), expected()).unwrap(),
+        ],
+        outputs: [
+            LinearCorrection::from_measurements(measure(1.08, 1.0), expected()).unwrap(),
+            LinearCorrection::IDENTITY,
+        ],
+    };
+    assert_eq!(
+        Calibration::from_bytes(&calibration.to_bytes()),
+        calibration
+    );
+}
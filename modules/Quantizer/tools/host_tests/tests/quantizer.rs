@@ -0,0 +1,238 @@
+use fixed::types::I8F8;
+use host_tests::quantizer::{
+    step_in_scale, ChannelOutput, GlideMode, HysteresisState, QuantizerState, SampleMode,
+};
+use proptest::prelude::*;
+
+const CHROMATIC: [bool; 12] = [true; 12];
+
+fn scale_from_mask(mask: u16) -> [bool; 12] {
+    core::array::from_fn(|i| (mask >> i) & 1 != 0)
+}
+
+fn is_in_scale(notes: &[bool; 12], note: i8) -> bool {
+    notes[(note % 12) as usize]
+}
+
+/// Straightforward reimplementation of stepping through a scale, by listing
+/// out every note in range that belongs to it
+fn reference_step_in_scale(notes: &[bool; 12], starting_note: i8, num_steps: i8) -> i8 {
+    let all_notes: Vec<i8> = (0..=120).filter(|n| is_in_scale(notes, *n)).collect();
+    let index = all_notes.iter().position(|n| *n == starting_note).unwrap() as i32;
+    let new_index = (index + num_steps as i32).clamp(0, all_notes.len() as i32 - 1);
+    all_notes[new_index as usize]
+}
+
+fn step_channel_a(state: &mut QuantizerState, input: I8F8) -> ChannelOutput {
+    state.step(input, I8F8::ZERO, true, false).channel_a
+}
+
+#[test]
+fn quantized_notes_are_in_scale_and_sweep_monotonically() {
+    let inputs: Vec<I8F8> = (0..=(120 << 8)).step_by(32).map(I8F8::from_bits).collect();
+    for mask in 1..(1 << 12) {
+        let notes = scale_from_mask(mask);
+        let mut state = HysteresisState::new();
+        let mut last_output = 0;
+        for (i, input) in inputs.iter().chain(inputs.iter().rev()).enumerate() {
+            let output = state.quantize(*input, &notes);
+            assert!(
+                (0..=120).contains(&output),
+                "{mask:012b}: {input} -> {output}"
+            );
+            assert!(
+                is_in_scale(&notes, output),
+                "{mask:012b}: {input} -> {output}"
+            );
+            assert_eq!(
+                state.quantize(*input, &notes),
+                output,
+                "{mask:012b}: {input}"
+            );
+            let sweeping_up = i < inputs.len();
+            if i > 0 && i != inputs.len() {
+                if sweeping_up {
+                    assert!(output >= last_output, "{mask:012b}: {input}");
+                } else {
+                    assert!(output <= last_output, "{mask:012b}: {input}");
+                }
+            }
+            last_output = output;
+        }
+    }
+}
+
+#[test]
+fn empty_scale_outputs_bottom_of_range() {
+    let notes = [false; 12];
+    let mut hysteresis = HysteresisState::new();
+    assert_eq!(hysteresis.calculate_hysteresis_thresholds(&notes), None);
+    for bits in (0..=(120 << 8)).step_by(64) {
+        assert_eq!(hysteresis.quantize(I8F8::from_bits(bits), &notes), 0);
+    }
+    for note in 0..=120 {
+        for steps in -5..=6 {
+            assert_eq!(step_in_scale(&notes, note, steps), note);
+        }
+    }
+
+    for post_shift in -5..=6 {
+        let mut state = QuantizerState::new();
+        let config = &mut state.channels[0].config;
+        config.pre_shift = 3;
+        config.scale_shift = 2;
+        config.post_shift = post_shift;
+        for bits in (0..=(120 << 8)).step_by(1024) {
+            let output = step_channel_a(&mut state, I8F8::from_bits(bits));
+            assert_eq!(output.actual_semitones, I8F8::from_num(post_shift.max(0)));
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn no_chattering_near_thresholds(
+        mask in 1u16..(1 << 12),
+        input_bits in 0i16..=(120 << 8),
+        // Hysteresis is 0.4 semitones either side of the midpoint between notes,
+        // so anything under 0.8 semitones of wobble should be ignored
+        wobble_bits in 0i16..=(0.75 * 256.0) as i16,
+    ) {
+        let notes = scale_from_mask(mask);
+        let low = I8F8::from_bits(input_bits);
+        let high = I8F8::from_bits((input_bits + wobble_bits).min(120 << 8));
+        let mut state = HysteresisState::new();
+        state.quantize(low, &notes);
+        let settled = state.quantize(high, &notes);
+        for _ in 0..8 {
+            prop_assert_eq!(state.quantize(low, &notes), settled);
+            prop_assert_eq!(state.quantize(high, &notes), settled);
+        }
+    }
+
+    #[test]
+    fn step_in_scale_matches_reference(
+        mask in 1u16..(1 << 12),
+        start in 0i8..=120,
+        steps in -24i8..=24,
+    ) {
+        let notes = scale_from_mask(mask);
+        prop_assume!(is_in_scale(&notes, start));
+        prop_assert_eq!(
+            step_in_scale(&notes, start, steps),
+            reference_step_in_scale(&notes, start, steps)
+        );
+    }
+
+    #[test]
+    fn transposition_is_applied_in_order(
+        mask in 1u16..(1 << 12),
+        input_bits in 0i16..=(120 << 8),
+    ) {
+        let notes = scale_from_mask(mask);
+        let input = I8F8::from_bits(input_bits);
+        for pre_shift in -5..=6 {
+            let pre_shifted = (input + I8F8::from_num(pre_shift)).clamp(I8F8::ZERO, I8F8::from_num(120));
+            let quantized = HysteresisState::new().quantize(pre_shifted, &notes);
+            for scale_shift in -5..=6 {
+                let scale_shifted = reference_step_in_scale(&notes, quantized, scale_shift);
+                for post_shift in -5..=6 {
+                    let mut state = QuantizerState::new();
+                    let config = &mut state.channels[0].config;
+                    config.notes = notes;
+                    config.pre_shift = pre_shift;
+                    config.scale_shift = scale_shift;
+                    config.post_shift = post_shift;
+                    let output = step_channel_a(&mut state, input);
+                    let post_shifted = scale_shifted + post_shift;
+                    if (0..=120).contains(&post_shifted) {
+                        prop_assert_eq!(output.nominal_semitones, scale_shifted);
+                        prop_assert_eq!(output.actual_semitones, I8F8::from_num(post_shifted));
+                    } else {
+                        // Pushed out of range by the post-shift, so the output
+                        // range pulls it back to the nearest note in the scale
+                        prop_assert!(is_in_scale(&notes, output.nominal_semitones));
+                        prop_assert!(output.actual_semitones >= 0);
+                        prop_assert!(output.actual_semitones <= 120);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Plays a note in sample & hold mode with no trigger delay: `gap_ms` of low
+/// trigger, then a rising edge with the new note. Returns the output on the
+/// edge.
+fn sample_and_hold_note(state: &mut QuantizerState, note: i8, gap_ms: u32) -> I8F8 {
+    let input = I8F8::from_num(note);
+    for _ in 0..gap_ms {
+        state.step(input, I8F8::ZERO, false, false);
+    }
+    let output = state.step(input, I8F8::ZERO, true, false).channel_a;
+    for _ in 0..20 {
+        state.step(input, I8F8::ZERO, true, false);
+    }
+    output.actual_semitones
+}
+
+#[test]
+fn legato_glides_only_between_overlapping_notes_in_sample_and_hold() {
+    let mut state = QuantizerState::new();
+    let channel = &mut state.channels[0];
+    channel.config.notes = CHROMATIC;
+    channel.config.sample_mode = SampleMode::SampleAndHold;
+    channel.config.glide_amount = 8;
+    channel.config.glide_mode = GlideMode::LinearConstantTime;
+    channel.config.legato_glide = true;
+    channel.config.trigger_delay_amount = 0;
+
+    assert_eq!(sample_and_hold_note(&mut state, 48, 100), 48);
+    // Back-to-back gates glide
+    assert!(sample_and_hold_note(&mut state, 60, 1) < 50);
+    // Let the glide finish, then a new note after a rest jumps straight there
+    sample_and_hold_note(&mut state, 60, 2000);
+    assert_eq!(sample_and_hold_note(&mut state, 36, 100), 36);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn glide_reaches_target_without_overshooting(
+        start in 0i8..=120,
+        target in 0i8..=120,
+        glide_amount in 1u8..=11,
+        glide_mode_index in 0usize..3,
+    ) {
+        let glide_mode = [
+            GlideMode::Exponential,
+            GlideMode::LinearConstantTime,
+            GlideMode::LinearConstantRate,
+        ][glide_mode_index];
+        let mut state = QuantizerState::new();
+        state.channels[0].config.notes = CHROMATIC;
+        step_channel_a(&mut state, I8F8::from_num(start));
+        state.channels[0].config.glide_amount = glide_amount;
+        state.channels[0].config.glide_mode = glide_mode;
+
+        let target = I8F8::from_num(target);
+        let mut last = I8F8::from_num(start);
+        let mut steps = 0u32;
+        while last != target {
+            let output = step_channel_a(&mut state, target).actual_semitones;
+            if last < target {
+                prop_assert!(output >= last && output <= target);
+            } else {
+                prop_assert!(output <= last && output >= target);
+            }
+            last = output;
+            steps += 1;
+            prop_assert!(steps < 100_000, "glide never finished");
+        }
+
+        if glide_mode == GlideMode::LinearConstantTime {
+            prop_assert!(steps <= (1 << (glide_amount + 2)) + 1);
+        }
+    }
+}